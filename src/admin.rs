@@ -0,0 +1,23 @@
+use axum::http::{HeaderMap, StatusCode};
+use std::env;
+
+pub const ADMIN_KEY_HEADER: &str = "x-admin-key";
+
+/// Rejects the request unless it carries the configured `ADMIN_API_KEY`.
+/// Admin routes stay closed when no key is configured.
+pub fn require_admin(headers: &HeaderMap) -> Result<(), (StatusCode, String)> {
+    let expected = env::var("ADMIN_API_KEY").unwrap_or_default();
+    if expected.is_empty() {
+        return Err((StatusCode::FORBIDDEN, "Admin API is disabled".to_string()));
+    }
+
+    let provided = headers
+        .get(ADMIN_KEY_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if provided != expected {
+        return Err((StatusCode::UNAUTHORIZED, "Invalid admin key".to_string()));
+    }
+
+    Ok(())
+}
@@ -1,41 +1,41 @@
-use crate::constants::{TWITTER_OAUTH_AUTHORIZE_URL, TWITTER_OAUTH_TOKEN_URL};
+use crate::constants::{DEFAULT_WALLET_LABEL, REAUTH_TOKEN_TTL_SECS, STREAM_TOKEN_TTL_SECS};
+use crate::identity::{Identity, LinkedIdentityDatabase, PROVIDER_TWITTER};
 use crate::models::AppState;
 use crate::profiles::{Profile, ProfileDatabase};
-use crate::wallets::{Wallet, WalletDatabase};
+use crate::reauth::{self, ReauthDatabase};
+use crate::wallets;
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
-    response::{Html, IntoResponse},
+    response::{Html, IntoResponse, Response},
 };
-use ethers::core::k256::ecdsa::SigningKey;
-use ethers::prelude::*;
-use hex;
+use ethers::types::Address;
 use oauth2::{
-    basic::BasicClient, AuthUrl, AuthorizationCode, ClientId, ClientSecret, CsrfToken,
-    PkceCodeChallenge, PkceCodeVerifier, RedirectUrl, Scope, TokenResponse, TokenUrl,
+    basic::BasicClient, AuthorizationCode, CsrfToken, PkceCodeChallenge, PkceCodeVerifier, Scope,
+    TokenResponse,
 };
-use rand::thread_rng;
-use reqwest::Client as HttpClient;
-use serde::{Deserialize, Serialize};
-use std::env;
+use serde::Deserialize;
 
 #[derive(Clone)]
 pub struct OAuthState {
+    provider: String,
     client: BasicClient,
     csrf_token: CsrfToken,
     pkce_verifier: String,
+    /// Set when the login is a step-up for a sensitive action rather than a
+    /// sign-in; the callback then issues a re-authentication token.
+    purpose: Option<String>,
+    /// Attach the provider account to the existing profile; only set once a
+    /// `link` re-authentication token was spent.
+    link: bool,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TwitterUserResponse {
-    pub data: TwitterUser,
-}
-
-#[derive(Debug, Serialize, Deserialize)]
-pub struct TwitterUser {
-    pub id: String,
-    pub name: String,
-    pub username: String,
+#[derive(Deserialize)]
+pub struct LoginQuery {
+    pub purpose: Option<String>,
+    /// Re-authentication token for `link`, to add this provider to a
+    /// profile that already signs in with another.
+    pub link_token: Option<String>,
 }
 
 #[derive(Deserialize)]
@@ -44,172 +44,317 @@ pub struct CallbackQuery {
     pub state: String,
 }
 
-pub fn create_twitter_oauth_client() -> Result<BasicClient, Box<dyn std::error::Error>> {
-    let client_id = ClientId::new(
-        env::var("TWITTER_CLIENT_ID")
-            .map_err(|_| "Missing TWITTER_CLIENT_ID environment variable")?,
-    );
-    let client_secret = ClientSecret::new(
-        env::var("TWITTER_CLIENT_SECRET")
-            .map_err(|_| "Missing TWITTER_CLIENT_SECRET environment variable")?,
-    );
-    let redirect_url = RedirectUrl::new(
-        env::var("TWITTER_REDIRECT_URL")
-            .map_err(|_| "Missing TWITTER_REDIRECT_URL environment variable")?,
-    )?;
-
-    let auth_url = AuthUrl::new(TWITTER_OAUTH_AUTHORIZE_URL.to_string())?;
-    let token_url = TokenUrl::new(TWITTER_OAUTH_TOKEN_URL.to_string())?;
-
-    Ok(
-        BasicClient::new(client_id, Some(client_secret), auth_url, Some(token_url))
-            .set_redirect_uri(redirect_url),
-    )
+fn html_error(status: StatusCode, message: String) -> Response {
+    (status, Html(message)).into_response()
 }
 
+/// Twitter login, kept at its original path.
 pub async fn login(
-    State(state): State<AppState>,
+    state: State<AppState>,
     Path(user_id): Path<String>,
-) -> impl IntoResponse {
-    let client = match create_twitter_oauth_client() {
+    query: Query<LoginQuery>,
+) -> Response {
+    login_with(state, Path((user_id, PROVIDER_TWITTER.to_string())), query).await
+}
+
+pub async fn login_with(
+    State(state): State<AppState>,
+    Path((user_id, provider)): Path<(String, String)>,
+    Query(query): Query<LoginQuery>,
+) -> Response {
+    let Some(provider) = state.identities.get(&provider) else {
+        return html_error(
+            StatusCode::NOT_FOUND,
+            format!("Unknown login provider: {}", provider),
+        );
+    };
+
+    let purpose = query.purpose.filter(|p| !p.is_empty());
+    if let Some(purpose) = &purpose {
+        if !reauth::is_known_purpose(purpose) {
+            return html_error(
+                StatusCode::BAD_REQUEST,
+                format!("Unknown login purpose: {}", purpose),
+            );
+        }
+    }
+
+    let link = match query.link_token.filter(|t| !t.is_empty()) {
+        Some(token) => {
+            match ReauthDatabase::new()
+                .and_then(|db| db.consume(&user_id, reauth::PURPOSE_LINK, &token))
+            {
+                Ok(true) => true,
+                Ok(false) => {
+                    return html_error(
+                        StatusCode::UNAUTHORIZED,
+                        "Re-authentication token is invalid or expired".to_string(),
+                    )
+                }
+                Err(e) => {
+                    return html_error(
+                        StatusCode::INTERNAL_SERVER_ERROR,
+                        format!("Failed to check re-authentication: {}", e),
+                    )
+                }
+            }
+        }
+        None => false,
+    };
+
+    let client = match provider.oauth_client() {
         Ok(client) => client,
         Err(e) => {
-            return (
+            return html_error(
                 StatusCode::INTERNAL_SERVER_ERROR,
-                Html(format!("OAuth setup failed: {}", e)),
+                format!("OAuth setup failed: {}", e),
             )
-                .into_response();
         }
     };
 
     let (pkce_challenge, pkce_verifier) = PkceCodeChallenge::new_random_sha256();
 
-    let scopes = vec![
-        Scope::new("tweet.read".to_string()),
-        Scope::new("users.read".to_string()),
-    ];
-
     let (auth_url, csrf_token) = client
         .authorize_url(|| CsrfToken::new(user_id))
-        .add_scopes(scopes)
+        .add_scopes(provider.scopes().into_iter().map(Scope::new))
         .set_pkce_challenge(pkce_challenge)
         .url();
 
     let mut oauth_state = state.oauth.lock().await;
     *oauth_state = Some(OAuthState {
+        provider: provider.name().to_string(),
         client,
         csrf_token: csrf_token.clone(),
         pkce_verifier: pkce_verifier.secret().to_string(), // Store as string
+        purpose,
+        link,
     });
 
-    (StatusCode::OK, Html(format!("{}", auth_url.as_str()))).into_response()
+    (StatusCode::OK, Html(auth_url.as_str().to_string())).into_response()
 }
 
-pub async fn callback(
+/// Twitter callback, kept at its original path.
+pub async fn callback(state: State<AppState>, params: Query<CallbackQuery>) -> Response {
+    callback_with(state, Path(PROVIDER_TWITTER.to_string()), params).await
+}
+
+pub async fn callback_with(
     State(state): State<AppState>,
+    Path(provider): Path<String>,
     Query(params): Query<CallbackQuery>,
-) -> impl IntoResponse {
+) -> Response {
     let mut oauth_state_guard = state.oauth.lock().await;
 
     let oauth_state = match oauth_state_guard.take() {
         Some(state) => state,
-        None => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Html("OAuth state not found".to_string()),
-            )
-                .into_response()
-        }
+        None => return html_error(StatusCode::BAD_REQUEST, "OAuth state not found".to_string()),
     };
 
     if params.state != *oauth_state.csrf_token.secret() {
-        return (
-            StatusCode::UNAUTHORIZED,
-            Html("CSRF token mismatch".to_string()),
-        )
-            .into_response();
+        return html_error(StatusCode::UNAUTHORIZED, "CSRF token mismatch".to_string());
     }
+    let Some(provider) = state
+        .identities
+        .get(&provider)
+        .filter(|p| p.name() == oauth_state.provider)
+    else {
+        return html_error(
+            StatusCode::BAD_REQUEST,
+            format!("Login was not started with {}", provider),
+        );
+    };
 
     let pkce_verifier = PkceCodeVerifier::new(oauth_state.pkce_verifier);
 
-    match oauth_state
+    let token_response = match oauth_state
         .client
         .exchange_code(AuthorizationCode::new(params.code))
         .set_pkce_verifier(pkce_verifier)
         .request_async(oauth2::reqwest::async_http_client)
         .await
     {
-        Ok(token_response) => {
-            let access_token = token_response.access_token().secret().to_string();
-
-            match fetch_user_info(&access_token).await {
-                Ok(user) => {
-                    let profile_db = ProfileDatabase::new().unwrap();
-
-                    match profile_db.get(&params.state).unwrap() {
-                        Some(_profile) => {}
-                        None => {
-                            let mut rng = thread_rng();
-                            let signing_key = SigningKey::random(&mut rng);
-                            let private_key_bytes = signing_key.to_bytes();
-                            let private_key_hex = hex::encode(private_key_bytes);
-                            let wallet = LocalWallet::from_bytes(&private_key_bytes)
-                                .expect("Invalid private key");
-                            let address = wallet.address();
-
-                            let wallet_db = WalletDatabase::new().unwrap();
-
-                            let wallet = Wallet {
-                                id: None,
-                                address: format!("{:#x}", address),
-                                private: private_key_hex,
-                            };
-
-                            let _ = wallet_db.create(&wallet).unwrap();
-
-                            let profile = Profile {
-                                id: None,
-                                user_id: params.state,
-                                username: user.data.username.to_string(),
-                                name: user.data.name.to_string(),
-                                wallet: format!("{:#x}", address),
-                            };
-
-                            let _ = profile_db.upsert(&profile).unwrap();
-                        }
-                    };
+        Ok(token_response) => token_response,
+        Err(e) => {
+            return html_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Token exchange failed: {}", e),
+            )
+        }
+    };
+    let access_token = token_response.access_token().secret().to_string();
+
+    let identity = match provider.fetch_identity(&access_token).await {
+        Ok(identity) => identity,
+        Err(e) => {
+            return html_error(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to fetch user info: {}", e),
+            )
+        }
+    };
+
+    let result = match &oauth_state.purpose {
+        Some(purpose) => step_up(&params.state, purpose, &identity),
+        None => sign_in(&params.state, &identity, oauth_state.link),
+    };
+    match result {
+        Ok(message) => (StatusCode::OK, Html(message)).into_response(),
+        Err((status, message)) => html_error(status, message),
+    }
+}
+
+fn internal(e: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// Whether `identity` is the Twitter account a profile was created with
+/// before identities were recorded. Such profiles only stored the username,
+/// so this holds only for profiles with no linked identity at all: every
+/// profile created since records its provider, and Sign-In with Ethereum
+/// profiles are keyed by their address.
+fn is_legacy_twitter(
+    profile: &Profile,
+    identity: &Identity,
+    db: &LinkedIdentityDatabase,
+) -> rusqlite::Result<bool> {
+    if identity.provider != PROVIDER_TWITTER
+        || profile.username != identity.username
+        || profile.user_id.parse::<Address>().is_ok()
+    {
+        return Ok(false);
+    }
+    Ok(db.list_for_user(&profile.user_id)?.is_empty())
+}
 
+/// Whether `identity` may act for the profile, recording it when it is a
+/// legacy Twitter sign-in.
+fn belongs_to(
+    profile: &Profile,
+    identity: &Identity,
+    db: &LinkedIdentityDatabase,
+) -> Result<bool, (StatusCode, String)> {
+    if let Some(linked) = db
+        .find(&identity.provider, &identity.subject)
+        .map_err(internal)?
+    {
+        return Ok(linked.user_id == profile.user_id);
+    }
+    if is_legacy_twitter(profile, identity, db).map_err(internal)? {
+        db.link(&profile.user_id, identity).map_err(internal)?;
+        return Ok(true);
+    }
+    Ok(false)
+}
+
+/// Signs in, creating the profile and its default wallet on first login.
+/// An existing profile only accepts identities already linked to it, unless
+/// the login carried a `link` re-authentication token. The page shows the
+/// token that opens the user's event streams.
+pub(crate) fn sign_in(
+    user_id: &str,
+    identity: &Identity,
+    link: bool,
+) -> Result<String, (StatusCode, String)> {
+    let profiles = ProfileDatabase::new().map_err(internal)?;
+    let identities = LinkedIdentityDatabase::new().map_err(internal)?;
+    let linked = identities
+        .find(&identity.provider, &identity.subject)
+        .map_err(internal)?;
+    if let Some(linked) = &linked {
+        if linked.user_id != user_id {
+            return Err((
+                StatusCode::CONFLICT,
+                format!(
+                    "This {} account is linked to another profile",
+                    identity.provider
+                ),
+            ));
+        }
+    }
+
+    match profiles.get(user_id).map_err(internal)? {
+        Some(profile) => {
+            if linked.is_some() {
+                identities.refresh(identity).map_err(internal)?;
+            } else if link
+                || is_legacy_twitter(&profile, identity, &identities).map_err(internal)?
+            {
+                identities.link(user_id, identity).map_err(|e| {
                     (
-                        StatusCode::OK,
-                        Html(format!(
-                            "Logged in successfully! <br/> User: {} ({}) <br/> You can close this page",
-                            user.data.name, user.data.username
-                        )),
+                        StatusCode::CONFLICT,
+                        format!("Failed to link {}: {}", identity.provider, e),
                     )
-                        .into_response()
-                }
-                Err(e) => (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    Html(format!("Failed to fetch user info: {}", e)),
-                )
-                    .into_response(),
+                })?;
+            } else {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    format!(
+                        "Sign in with a provider linked to this profile, or link {} with a re-authentication token",
+                        identity.provider
+                    ),
+                ));
             }
         }
-        Err(e) => (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            Html(format!("Token exchange failed: {}", e)),
-        )
-            .into_response(),
-    }
+        None => {
+            let wallet = wallets::create_wallet(user_id, DEFAULT_WALLET_LABEL, true)
+                .map_err(|e| internal(format!("Failed to create wallet: {}", e)))?;
+
+            // Usernames are unique across providers; qualify clashes.
+            let mut username = identity.username.clone();
+            if profiles.username_taken(&username).map_err(internal)? {
+                username = format!("{}@{}", identity.username, identity.provider);
+            }
+            let profile = Profile {
+                id: None,
+                user_id: user_id.to_string(),
+                username,
+                name: identity.name.clone(),
+                wallet: wallet.address,
+            };
+
+            profiles.upsert(&profile).map_err(internal)?;
+            identities.link(user_id, identity).map_err(internal)?;
+        }
+    };
+
+    let stream_token = ReauthDatabase::new()
+        .and_then(|db| db.issue(user_id, reauth::PURPOSE_STREAM))
+        .map_err(|e| internal(format!("Failed to issue stream token: {}", e)))?;
+    Ok(format!(
+        "Logged in successfully! <br/> User: {} ({}) <br/> Stream token: {} (valid for {} hours) \
+         <br/> You can close this page",
+        identity.name,
+        identity.username,
+        stream_token,
+        STREAM_TOKEN_TTL_SECS / 3600
+    ))
 }
 
-pub async fn fetch_user_info(access_token: &str) -> Result<TwitterUserResponse, reqwest::Error> {
-    let client = HttpClient::new();
-    client
-        .get("https://api.twitter.com/2/users/me")
-        .header("Authorization", format!("Bearer {}", access_token))
-        .send()
-        .await?
-        .json::<TwitterUserResponse>()
-        .await
+/// Issues a re-authentication token once the user has signed in again with
+/// a provider account linked to their profile.
+fn step_up(
+    user_id: &str,
+    purpose: &str,
+    identity: &Identity,
+) -> Result<String, (StatusCode, String)> {
+    let profile = ProfileDatabase::new()
+        .and_then(|db| db.get(user_id))
+        .map_err(|e| internal(format!("Failed to load profile: {}", e)))?
+        .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+    let identities = LinkedIdentityDatabase::new().map_err(internal)?;
+    if !belongs_to(&profile, identity, &identities)? {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Signed in with an account that is not linked to this profile".to_string(),
+        ));
+    }
+
+    let token = ReauthDatabase::new()
+        .and_then(|db| db.issue(user_id, purpose))
+        .map_err(|e| internal(format!("Failed to issue confirmation code: {}", e)))?;
+    Ok(format!(
+        "Identity confirmed. <br/> Confirmation code: {} <br/> It expires in {} minutes and can be used once.",
+        token,
+        REAUTH_TOKEN_TTL_SECS / 60
+    ))
 }
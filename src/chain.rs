@@ -0,0 +1,128 @@
+#![allow(dead_code)]
+
+use crate::constants::{DEFAULT_CHAIN, NATIVE_TOKEN_ADDRESS};
+use ethers::{
+    prelude::*,
+    providers::{Http, Provider},
+    types::Address,
+    utils::format_units,
+};
+use std::{convert::TryFrom, env, sync::Arc};
+
+abigen!(
+    Erc20,
+    r#"[
+        function name() external view returns (string)
+        function symbol() external view returns (string)
+        function decimals() external view returns (uint8)
+        function totalSupply() external view returns (uint256)
+        function balanceOf(address owner) external view returns (uint256)
+    ]"#
+);
+
+pub fn provider() -> Result<Arc<Provider<Http>>, String> {
+    let url = env::var("RPC_URL").map_err(|_| "Missing RPC_URL environment variable")?;
+    let provider =
+        Provider::<Http>::try_from(url).map_err(|e| format!("Invalid RPC_URL: {}", e))?;
+    Ok(Arc::new(provider))
+}
+
+/// Provider for a named catalog chain. Reads `RPC_URL_<CHAIN>` and falls back
+/// to `RPC_URL` for the default chain.
+pub fn provider_for(chain: &str) -> Result<Arc<Provider<Http>>, String> {
+    let key = format!("RPC_URL_{}", chain.to_uppercase().replace('-', "_"));
+    match env::var(&key) {
+        Ok(url) => {
+            let provider =
+                Provider::<Http>::try_from(url).map_err(|e| format!("Invalid {}: {}", key, e))?;
+            Ok(Arc::new(provider))
+        }
+        Err(_) if chain.eq_ignore_ascii_case(DEFAULT_CHAIN) => provider(),
+        Err(_) => Err(format!("No RPC configured for chain {}", chain)),
+    }
+}
+
+pub fn chain_id() -> Result<u64, String> {
+    env::var("CHAIN_ID")
+        .map_err(|_| "Missing CHAIN_ID environment variable".to_string())?
+        .parse::<u64>()
+        .map_err(|_| "Invalid CHAIN_ID environment variable".to_string())
+}
+
+pub fn is_native_token(address: &str) -> bool {
+    address.eq_ignore_ascii_case(NATIVE_TOKEN_ADDRESS)
+}
+
+/// Checks that `address` is either the native token placeholder or a deployed
+/// contract on the configured chain.
+pub async fn validate_token(provider: &Provider<Http>, address: &str) -> Result<(), String> {
+    if is_native_token(address) {
+        return Ok(());
+    }
+
+    let token = address
+        .parse::<Address>()
+        .map_err(|_| format!("Invalid token address: {}", address))?;
+    let code = provider
+        .get_code(token, None)
+        .await
+        .map_err(|e| format!("Failed to look up token {}: {}", address, e))?;
+
+    if code.as_ref().is_empty() {
+        return Err(format!("Token {} is not a contract on this chain", address));
+    }
+
+    Ok(())
+}
+
+/// Balance of `owner` in `token` base units, treating the native token
+/// placeholder as the chain's gas token.
+pub async fn token_balance(
+    provider: Arc<Provider<Http>>,
+    token: &str,
+    owner: &str,
+) -> Result<U256, String> {
+    let owner = owner
+        .parse::<Address>()
+        .map_err(|_| format!("Invalid wallet address: {}", owner))?;
+
+    if is_native_token(token) {
+        return provider
+            .get_balance(owner, None)
+            .await
+            .map_err(|e| format!("Failed to read balance: {}", e));
+    }
+
+    let token = token
+        .parse::<Address>()
+        .map_err(|_| format!("Invalid token address: {}", token))?;
+    Erc20::new(token, provider)
+        .balance_of(owner)
+        .call()
+        .await
+        .map_err(|e| format!("Failed to read token balance: {}", e))
+}
+
+/// Decimals of `token`, 18 for the native token placeholder.
+pub async fn token_decimals(provider: Arc<Provider<Http>>, token: &str) -> Result<u8, String> {
+    if is_native_token(token) {
+        return Ok(18);
+    }
+
+    let address = token
+        .parse::<Address>()
+        .map_err(|_| format!("Invalid token address: {}", token))?;
+    Erc20::new(address, provider)
+        .decimals()
+        .call()
+        .await
+        .map_err(|e| format!("Failed to read decimals of {}: {}", token, e))
+}
+
+/// Converts base units into whole tokens for display and valuation.
+pub fn to_units(amount: U256, decimals: u8) -> f64 {
+    format_units(amount, decimals as u32)
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or_default()
+}
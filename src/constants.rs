@@ -1,4 +1,101 @@
 pub const TWITTER_OAUTH_AUTHORIZE_URL: &str = "https://twitter.com/i/oauth2/authorize";
 pub const TWITTER_OAUTH_TOKEN_URL: &str = "https://api.twitter.com/2/oauth2/token";
+pub const TWITTER_USERINFO_URL: &str = "https://api.twitter.com/2/users/me";
+pub const GITHUB_OAUTH_AUTHORIZE_URL: &str = "https://github.com/login/oauth/authorize";
+pub const GITHUB_OAUTH_TOKEN_URL: &str = "https://github.com/login/oauth/access_token";
+pub const GITHUB_USERINFO_URL: &str = "https://api.github.com/user";
+pub const GOOGLE_OAUTH_AUTHORIZE_URL: &str = "https://accounts.google.com/o/oauth2/v2/auth";
+pub const GOOGLE_OAUTH_TOKEN_URL: &str = "https://oauth2.googleapis.com/token";
+pub const GOOGLE_USERINFO_URL: &str = "https://openidconnect.googleapis.com/v1/userinfo";
+pub const DISCORD_OAUTH_AUTHORIZE_URL: &str = "https://discord.com/oauth2/authorize";
+pub const DISCORD_OAUTH_TOKEN_URL: &str = "https://discord.com/api/oauth2/token";
+pub const DISCORD_USERINFO_URL: &str = "https://discord.com/api/users/@me";
+pub const IDENTITY_USER_AGENT: &str = "onchain-ops";
+pub const TWITTER_SEARCH_URL: &str = "https://api.twitter.com/2/tweets/search/recent";
 
 pub const DB_PATH: &str = "ops.db";
+pub const PROJECTS_JSON_PATH: &str = "projects.json";
+pub const PROJECT_JSON_PATH: &str = "project.json";
+pub const PRICES_JSON_PATH: &str = "prices.json";
+pub const POSTS_JSON_PATH: &str = "posts.json";
+pub const PRICE_POOLS_JSON_PATH: &str = "price_pools.json";
+pub const DEFAULT_CHAIN: &str = "educhain";
+pub const PROJECTS_DEFAULT_LIMIT: i64 = 20;
+pub const PROJECTS_MAX_LIMIT: i64 = 100;
+pub const TOKEN_METADATA_REFRESH_INTERVAL_SECS: u64 = 6 * 60 * 60;
+
+pub const NATIVE_TOKEN_ADDRESS: &str = "0x0000000000000000000000000000000000000000";
+pub const PERMIT2_ADDRESS: &str = "0x000000000022D473030F116dDEE9F6B43aC78BA3";
+pub const PERMIT_DEFAULT_TTL_SECS: u64 = 30 * 60;
+
+pub const SWAP_POLL_INTERVAL_SECS: u64 = 15;
+pub const SWAP_HISTORY_DEFAULT_LIMIT: i64 = 20;
+pub const SWAP_HISTORY_MAX_LIMIT: i64 = 100;
+
+pub const ORDER_EVALUATION_INTERVAL_SECS: u64 = 60;
+
+pub const SUBSCRIPTION_SCHEDULER_INTERVAL_SECS: u64 = 60;
+pub const SUBSCRIPTION_MAX_ATTEMPTS: i64 = 3;
+pub const SUBSCRIPTION_RETRY_DELAY_SECS: u64 = 10;
+
+pub const TVL_SNAPSHOT_INTERVAL_SECS: u64 = 60 * 60;
+pub const TVL_HISTORY_DAYS: i64 = 30;
+
+pub const GITHUB_API_URL: &str = "https://api.github.com";
+pub const GITHUB_ACTIVITY_INTERVAL_SECS: u64 = 6 * 60 * 60;
+
+pub const SENTIMENT_INGEST_INTERVAL_SECS: u64 = 60 * 60;
+pub const SENTIMENT_WINDOW_SECS: i64 = 7 * 24 * 60 * 60;
+
+pub const WHITEPAPER_DIR: &str = "whitepapers";
+pub const WHITEPAPER_MAX_BYTES: usize = 20 * 1024 * 1024;
+pub const WHITEPAPER_SUMMARY_SENTENCES: usize = 5;
+
+pub const PRICE_CACHE_TTL_SECS: i64 = 60;
+pub const PRICE_MAX_STALENESS_SECS: i64 = 15 * 60;
+/// Placeholder sender for price quotes; nothing is ever executed from it.
+pub const PRICE_QUOTE_FROM_ADDRESS: &str = "0x000000000000000000000000000000000000dEaD";
+
+pub const PORTFOLIO_SNAPSHOT_INTERVAL_SECS: u64 = 60 * 60;
+pub const PORTFOLIO_HISTORY_DEFAULT_DAYS: i64 = 30;
+pub const PORTFOLIO_HISTORY_MAX_DAYS: i64 = 365;
+/// How long a swap or transfer holds off reconciliation while it settles;
+/// holds left behind by a crash lapse after this.
+pub const PORTFOLIO_SETTLEMENT_HOLD_SECS: i64 = 60 * 60;
+
+pub const INDEXER_POLL_INTERVAL_SECS: u64 = 15;
+pub const INDEXER_BATCH_BLOCKS: u64 = 50;
+/// Blocks whose hashes are kept to detect reorgs; widened on chains whose
+/// deposits need more confirmations.
+pub const INDEXER_REORG_DEPTH: u64 = 12;
+/// How far back an address first seen by the indexer is scanned when
+/// `INDEXER_START_BLOCK_<CHAIN>` is unset.
+pub const INDEXER_BACKFILL_BLOCKS: u64 = 10_000;
+pub const HISTORY_DEFAULT_LIMIT: i64 = 50;
+pub const HISTORY_MAX_LIMIT: i64 = 200;
+
+/// Label of the wallet created with a profile.
+pub const DEFAULT_WALLET_LABEL: &str = "main";
+/// Retries when concurrent sign-ups race for the same derivation index.
+pub const WALLET_CREATE_ATTEMPTS: usize = 5;
+
+pub const EXTERNAL_WALLET_CHALLENGE_TTL_SECS: i64 = 10 * 60;
+pub const SIWE_NONCE_TTL_SECS: i64 = 10 * 60;
+pub const REAUTH_TOKEN_TTL_SECS: i64 = 5 * 60;
+pub const KEYSTORE_MIN_PASSWORD_LEN: usize = 8;
+pub const WALLET_AUDIT_DEFAULT_LIMIT: i64 = 100;
+pub const WALLET_AUDIT_MAX_LIMIT: i64 = 1000;
+
+pub const DEPOSIT_POLL_INTERVAL_SECS: u64 = 15;
+/// Used when `DEPOSIT_CONFIRMATIONS_<CHAIN>` is not set.
+pub const DEPOSIT_DEFAULT_CONFIRMATIONS: u64 = 12;
+
+pub const EVENT_BUS_CAPACITY: usize = 1024;
+pub const STREAM_KEEPALIVE_SECS: u64 = 15;
+pub const STREAM_TOKEN_TTL_SECS: i64 = 12 * 60 * 60;
+
+pub const WEBHOOK_DISPATCH_INTERVAL_SECS: u64 = 5;
+pub const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+pub const WEBHOOK_MAX_ATTEMPTS: i64 = 8;
+/// First retry delay; doubled after every failed attempt.
+pub const WEBHOOK_RETRY_BASE_SECS: i64 = 30;
@@ -0,0 +1,523 @@
+use crate::admin;
+use crate::constants::{
+    DB_PATH, KEYSTORE_MIN_PASSWORD_LEN, WALLET_AUDIT_DEFAULT_LIMIT, WALLET_AUDIT_MAX_LIMIT,
+};
+use crate::profiles::ProfileDatabase;
+use crate::reauth::{self, PURPOSE_WALLET};
+use crate::utils;
+use crate::wallets::{self, Wallet, WalletDatabase};
+use axum::{
+    extract::{Path, Query},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use ethers::signers::LocalWallet;
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use std::{env, fs, path::PathBuf};
+
+pub const ACTION_IMPORT: &str = "import";
+pub const ACTION_EXPORT: &str = "export";
+
+pub const OUTCOME_SUCCESS: &str = "success";
+/// Rejected by re-authentication or an export lock.
+pub const OUTCOME_DENIED: &str = "denied";
+pub const OUTCOME_FAILED: &str = "failed";
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct AuditEntry {
+    pub id: i64,
+    pub user_id: String,
+    pub wallet_address: Option<String>,
+    /// `import` or `export`.
+    pub action: String,
+    /// `success`, `denied` or `failed`.
+    pub outcome: String,
+    pub detail: Option<String>,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ExportLock {
+    pub user_id: String,
+    pub reason: Option<String>,
+    pub locked_at: i64,
+}
+
+/// Audit trail of key imports and exports, and the users whose exports an
+/// admin has locked.
+pub struct CustodyDatabase {
+    pub conn: Connection,
+}
+
+impl CustodyDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS wallet_audit_log (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                wallet_address TEXT,
+                action TEXT NOT NULL,
+                outcome TEXT NOT NULL,
+                detail TEXT,
+                created_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS idx_wallet_audit_log_user
+             ON wallet_audit_log (user_id, created_at)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS wallet_export_locks (
+                user_id TEXT PRIMARY KEY,
+                reason TEXT,
+                locked_at INTEGER NOT NULL
+            )",
+            [],
+        )?;
+
+        Ok(CustodyDatabase { conn })
+    }
+
+    pub fn record(
+        &self,
+        user_id: &str,
+        wallet_address: Option<&str>,
+        action: &str,
+        outcome: &str,
+        detail: Option<&str>,
+    ) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO wallet_audit_log
+                (user_id, wallet_address, action, outcome, detail, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                user_id,
+                wallet_address,
+                action,
+                outcome,
+                detail,
+                utils::now()
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Most recent entries first, optionally for a single user.
+    pub fn entries(&self, user_id: Option<&str>, limit: i64) -> Result<Vec<AuditEntry>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, user_id, wallet_address, action, outcome, detail, created_at
+             FROM wallet_audit_log
+             WHERE ?1 IS NULL OR user_id = ?1
+             ORDER BY id DESC LIMIT ?2",
+        )?;
+        let rows = stmt.query_map(params![user_id, limit], audit_from_row)?;
+        rows.collect()
+    }
+
+    pub fn lock(&self, user_id: &str, reason: Option<&str>) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO wallet_export_locks (user_id, reason, locked_at)
+             VALUES (?1, ?2, ?3)
+             ON CONFLICT(user_id) DO UPDATE SET reason = excluded.reason",
+            params![user_id, reason, utils::now()],
+        )?;
+        Ok(())
+    }
+
+    pub fn unlock(&self, user_id: &str) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM wallet_export_locks WHERE user_id = ?1",
+            params![user_id],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    pub fn get_lock(&self, user_id: &str) -> Result<Option<ExportLock>> {
+        self.conn
+            .query_row(
+                "SELECT user_id, reason, locked_at FROM wallet_export_locks WHERE user_id = ?1",
+                params![user_id],
+                lock_from_row,
+            )
+            .optional()
+    }
+
+    pub fn locks(&self) -> Result<Vec<ExportLock>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT user_id, reason, locked_at FROM wallet_export_locks ORDER BY locked_at DESC",
+        )?;
+        let rows = stmt.query_map([], lock_from_row)?;
+        rows.collect()
+    }
+}
+
+fn audit_from_row(row: &Row) -> Result<AuditEntry> {
+    Ok(AuditEntry {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        wallet_address: row.get(2)?,
+        action: row.get(3)?,
+        outcome: row.get(4)?,
+        detail: row.get(5)?,
+        created_at: row.get(6)?,
+    })
+}
+
+fn lock_from_row(row: &Row) -> Result<ExportLock> {
+    Ok(ExportLock {
+        user_id: row.get(0)?,
+        reason: row.get(1)?,
+        locked_at: row.get(2)?,
+    })
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Custody database error: {}", e),
+    )
+}
+
+/// Records the outcome of an import or export attempt. Auditing must not
+/// change the response, so failures to write the log are only printed.
+fn audit<T>(
+    user_id: &str,
+    wallet_address: Option<&str>,
+    action: &str,
+    result: &std::result::Result<T, (StatusCode, String)>,
+) {
+    let (outcome, detail) = match result {
+        Ok(_) => (OUTCOME_SUCCESS, None),
+        Err((status, message))
+            if *status == StatusCode::UNAUTHORIZED || *status == StatusCode::FORBIDDEN =>
+        {
+            (OUTCOME_DENIED, Some(message.as_str()))
+        }
+        Err((_, message)) => (OUTCOME_FAILED, Some(message.as_str())),
+    };
+    if let Err(e) = CustodyDatabase::new()
+        .and_then(|db| db.record(user_id, wallet_address, action, outcome, detail))
+    {
+        println!("Failed to audit wallet {} for {}: {}", action, user_id, e);
+    }
+}
+
+/// Whether exports are switched off for everyone via `WALLET_EXPORT_DISABLED`.
+fn exports_disabled() -> bool {
+    matches!(
+        env::var("WALLET_EXPORT_DISABLED")
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase()
+            .as_str(),
+        "1" | "true" | "yes"
+    )
+}
+
+/// Scratch directory for keystore files; the keystore API only reads and
+/// writes files. Removed when dropped.
+struct ScratchDir(PathBuf);
+
+impl ScratchDir {
+    fn new() -> std::io::Result<Self> {
+        let path = env::temp_dir().join(format!(
+            "keystore-{}",
+            hex::encode(rand::random::<[u8; 16]>())
+        ));
+        fs::create_dir(&path)?;
+        Ok(Self(path))
+    }
+}
+
+impl Drop for ScratchDir {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.0);
+    }
+}
+
+fn decrypt_keystore(
+    keystore: &serde_json::Value,
+    password: &str,
+) -> std::result::Result<LocalWallet, String> {
+    let dir = ScratchDir::new().map_err(|e| e.to_string())?;
+    let path = dir.0.join("keystore.json");
+    fs::write(&path, keystore.to_string()).map_err(|e| e.to_string())?;
+    LocalWallet::decrypt_keystore(&path, password).map_err(|e| e.to_string())
+}
+
+fn encrypt_keystore(
+    private: &str,
+    password: &str,
+) -> std::result::Result<serde_json::Value, String> {
+    let key = hex::decode(private.trim_start_matches("0x")).map_err(|e| e.to_string())?;
+    let dir = ScratchDir::new().map_err(|e| e.to_string())?;
+    LocalWallet::encrypt_keystore(
+        &dir.0,
+        &mut rand::thread_rng(),
+        key,
+        password,
+        Some("keystore.json"),
+    )
+    .map_err(|e| e.to_string())?;
+    let json = fs::read_to_string(dir.0.join("keystore.json")).map_err(|e| e.to_string())?;
+    serde_json::from_str(&json).map_err(|e| e.to_string())
+}
+
+/// Runs a scrypt-heavy keystore operation off the async runtime.
+async fn keystore_task<T, F>(task: F) -> std::result::Result<T, String>
+where
+    T: Send + 'static,
+    F: FnOnce() -> std::result::Result<T, String> + Send + 'static,
+{
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|e| e.to_string())?
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportWalletRequest {
+    pub label: String,
+    /// Hex private key, with or without `0x`.
+    pub private_key: Option<String>,
+    /// Encrypted JSON keystore (V3); requires `password`.
+    pub keystore: Option<serde_json::Value>,
+    pub password: Option<String>,
+    #[serde(default)]
+    pub default: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportWalletRequest {
+    /// Password the exported keystore is encrypted with.
+    pub password: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ExportWalletResponse {
+    pub address: String,
+    pub keystore: serde_json::Value,
+}
+
+/// Adds an existing key to the user's wallets. Requires a re-authentication
+/// token in `x-reauth-token`.
+pub async fn import_wallet(
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+    Json(req): Json<ImportWalletRequest>,
+) -> std::result::Result<Json<Wallet>, (StatusCode, String)> {
+    let result = import(&headers, &user_id, req).await;
+    audit(
+        &user_id,
+        result.as_ref().ok().map(|w| w.address.as_str()),
+        ACTION_IMPORT,
+        &result,
+    );
+    result.map(Json)
+}
+
+async fn import(
+    headers: &HeaderMap,
+    user_id: &str,
+    req: ImportWalletRequest,
+) -> std::result::Result<Wallet, (StatusCode, String)> {
+    let label = wallets::validate_label(&req.label)?;
+    ProfileDatabase::new()
+        .and_then(|db| db.get(user_id))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+    reauth::require_reauth(headers, user_id, PURPOSE_WALLET)?;
+
+    let signer = match (req.private_key, req.keystore) {
+        (Some(key), None) => key
+            .trim()
+            .parse::<LocalWallet>()
+            .map_err(|_| (StatusCode::BAD_REQUEST, "Invalid private key".to_string()))?,
+        (None, Some(keystore)) => {
+            let password = req.password.unwrap_or_default();
+            keystore_task(move || decrypt_keystore(&keystore, &password))
+                .await
+                .map_err(|e| {
+                    (
+                        StatusCode::BAD_REQUEST,
+                        format!("Failed to decrypt keystore: {}", e),
+                    )
+                })?
+        }
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                "Provide either private_key or keystore".to_string(),
+            ))
+        }
+    };
+
+    let db = WalletDatabase::new().map_err(db_error)?;
+    let mut wallet = Wallet {
+        user_id: Some(user_id.to_string()),
+        label: Some(label.clone()),
+        ..wallets::wallet_record(&signer, None)
+    };
+    if db.get(&wallet.address).map_err(db_error)?.is_some() {
+        return Err((
+            StatusCode::CONFLICT,
+            "This wallet is already in custody".to_string(),
+        ));
+    }
+    wallets::ensure_label_free(&db, user_id, &label)?;
+
+    wallet.id = Some(db.create(&wallet).map_err(db_error)?);
+    if req.default {
+        db.set_default(user_id, &wallet.address).map_err(db_error)?;
+        wallet.is_default = true;
+    }
+
+    Ok(wallet)
+}
+
+/// Returns the wallet's key as a keystore encrypted with the given password.
+/// Requires a re-authentication token and is refused while exports are
+/// locked for the user or disabled globally.
+pub async fn export_wallet(
+    headers: HeaderMap,
+    Path((user_id, selector)): Path<(String, String)>,
+    Json(req): Json<ExportWalletRequest>,
+) -> std::result::Result<Json<ExportWalletResponse>, (StatusCode, String)> {
+    let wallet = WalletDatabase::new()
+        .and_then(|db| db.find_for_user(&user_id, &selector))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Wallet not found".to_string()))?;
+
+    let result = export(&headers, &user_id, &wallet, req).await;
+    audit(&user_id, Some(&wallet.address), ACTION_EXPORT, &result);
+    result.map(Json)
+}
+
+async fn export(
+    headers: &HeaderMap,
+    user_id: &str,
+    wallet: &Wallet,
+    req: ExportWalletRequest,
+) -> std::result::Result<ExportWalletResponse, (StatusCode, String)> {
+    if req.password.chars().count() < KEYSTORE_MIN_PASSWORD_LEN {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Keystore password must be at least {} characters",
+                KEYSTORE_MIN_PASSWORD_LEN
+            ),
+        ));
+    }
+
+    // Checked before re-authentication so a locked user keeps their token.
+    if exports_disabled() {
+        return Err((
+            StatusCode::FORBIDDEN,
+            "Wallet export is disabled".to_string(),
+        ));
+    }
+    let lock = CustodyDatabase::new()
+        .and_then(|db| db.get_lock(user_id))
+        .map_err(db_error)?;
+    if let Some(lock) = lock {
+        return Err((
+            StatusCode::FORBIDDEN,
+            match lock.reason {
+                Some(reason) => format!("Wallet export is locked: {}", reason),
+                None => "Wallet export is locked".to_string(),
+            },
+        ));
+    }
+    reauth::require_reauth(headers, user_id, PURPOSE_WALLET)?;
+
+    let private = wallet.private.clone();
+    let password = req.password;
+    let keystore = keystore_task(move || encrypt_keystore(&private, &password))
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to encrypt keystore: {}", e),
+            )
+        })?;
+
+    Ok(ExportWalletResponse {
+        address: wallet.address.clone(),
+        keystore,
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub user_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+pub async fn list_audit_log(
+    headers: HeaderMap,
+    Query(query): Query<AuditQuery>,
+) -> std::result::Result<Json<Vec<AuditEntry>>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let limit = query
+        .limit
+        .unwrap_or(WALLET_AUDIT_DEFAULT_LIMIT)
+        .clamp(1, WALLET_AUDIT_MAX_LIMIT);
+    let entries = CustodyDatabase::new()
+        .and_then(|db| db.entries(query.user_id.as_deref(), limit))
+        .map_err(db_error)?;
+    Ok(Json(entries))
+}
+
+pub async fn list_export_locks(
+    headers: HeaderMap,
+) -> std::result::Result<Json<Vec<ExportLock>>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let locks = CustodyDatabase::new()
+        .and_then(|db| db.locks())
+        .map_err(db_error)?;
+    Ok(Json(locks))
+}
+
+#[derive(Debug, Deserialize, Default)]
+pub struct ExportLockRequest {
+    pub reason: Option<String>,
+}
+
+pub async fn lock_exports(
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+    Json(req): Json<ExportLockRequest>,
+) -> std::result::Result<Json<ExportLock>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let db = CustodyDatabase::new().map_err(db_error)?;
+    db.lock(&user_id, req.reason.as_deref()).map_err(db_error)?;
+    let lock = db
+        .get_lock(&user_id)
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Lock not found".to_string()))?;
+    Ok(Json(lock))
+}
+
+pub async fn unlock_exports(
+    headers: HeaderMap,
+    Path(user_id): Path<String>,
+) -> std::result::Result<StatusCode, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let removed = CustodyDatabase::new()
+        .and_then(|db| db.unlock(&user_id))
+        .map_err(db_error)?;
+    if !removed {
+        return Err((StatusCode::NOT_FOUND, "Lock not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -1,6 +1,7 @@
+use crate::permits::PermitKind;
 use serde::{Deserialize, Serialize};
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct QuoteParams {
     #[serde(rename = "fromTokenAddress")]
     pub from_token_address: String,
@@ -96,6 +97,11 @@ pub struct GaslessSwapParams {
     pub permit_signature: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none", rename = "permitDeadline")]
     pub permit_deadline: Option<String>,
+    /// Which struct the permit signature is over: "eip2612" or "permit2".
+    #[serde(skip_serializing_if = "Option::is_none", rename = "permitType")]
+    pub permit_type: Option<PermitKind>,
+    #[serde(skip_serializing_if = "Option::is_none", rename = "permitNonce")]
+    pub permit_nonce: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -147,12 +153,57 @@ pub struct GetQuoteRequest {
     pub from_token: String,
     pub to_token: String,
     pub amount: String,
+    #[serde(default)]
     pub slippage: String,
     pub from_address: String,
     pub to_address: String,
     pub gasless: bool,
     pub affiliate_address: Option<String>,
     pub affiliate_fee: Option<String>,
+    #[serde(default)]
+    pub accept_high_impact: bool,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct GuardedQuoteResponse {
+    #[serde(flatten)]
+    pub quote: QuoteResponse,
+    pub slippage: String,
+    pub price_impact: Option<f64>,
+    pub warning: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct UserQuoteRequest {
+    pub from_token: String,
+    pub to_token: String,
+    pub amount: String,
+    #[serde(default)]
+    pub slippage: String,
+    pub to_address: Option<String>,
+    #[serde(default)]
+    pub gasless: bool,
+    pub affiliate_address: Option<String>,
+    pub affiliate_fee: Option<String>,
+    #[serde(default)]
+    pub accept_high_impact: bool,
+    /// Address or label of the user's wallet to swap from.
+    #[serde(default)]
+    pub wallet: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuoteAndSwapRequest {
+    #[serde(flatten)]
+    pub quote: UserQuoteRequest,
+    pub network_name: String,
+    pub permit_deadline: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct QuoteAndSwapResponse {
+    pub quote: GuardedQuoteResponse,
+    pub swap: SwapResponse,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -161,6 +212,13 @@ pub struct ExecuteSwapRequest {
     pub network_name: String,
     pub wallet_key: Option<String>,
     pub permit_deadline: Option<u64>,
+    /// Optional; the quote's from-token is used, and must match if given.
+    pub from_token: Option<String>,
+    /// Optional; the quoted amount is used, and must match if given.
+    pub amount: Option<String>,
+    /// Address or label of the user's wallet to swap from.
+    #[serde(default)]
+    pub wallet: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -177,3 +235,17 @@ pub struct SwapDetailsRequest {
 pub struct GetDistributionsRequest {
     pub quote_id: String,
 }
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SwapHistoryQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct SwapHistoryResponse {
+    pub swaps: Vec<crate::swap_history::SwapRecord>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
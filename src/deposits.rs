@@ -0,0 +1,362 @@
+#![allow(dead_code)]
+
+use crate::chain;
+use crate::constants::{DB_PATH, DEPOSIT_DEFAULT_CONFIRMATIONS, DEPOSIT_POLL_INTERVAL_SECS};
+use crate::events::{self, EventBus};
+use crate::indexer::{self, IndexedTransfer, IndexerDatabase, TRANSFER_COLUMNS};
+use crate::swap_history::SwapHistoryDatabase;
+use crate::wallets::WalletDatabase;
+use crate::webhooks;
+use axum::{extract::Path, http::StatusCode, Json};
+use ethers::providers::Middleware;
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::env;
+use std::time::Duration;
+
+pub const DEPOSIT_PENDING: &str = "pending";
+pub const DEPOSIT_CONFIRMED: &str = "confirmed";
+/// The transfer was removed by a reorg before reaching its confirmations.
+pub const DEPOSIT_ORPHANED: &str = "orphaned";
+
+pub const EVENT_DETECTED: &str = "deposit.detected";
+pub const EVENT_CONFIRMED: &str = "deposit.confirmed";
+pub const EVENT_ORPHANED: &str = "deposit.orphaned";
+
+const DEPOSIT_COLUMNS: &str = "id, user_id, chain, token, from_address, to_address, amount, \
+     tx_hash, log_index, block_number, confirmations, required_confirmations, status, \
+     detected_at, confirmed_at";
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Deposit {
+    pub id: i64,
+    pub user_id: String,
+    pub chain: String,
+    pub token: String,
+    pub from_address: String,
+    /// Managed wallet that received the funds.
+    pub to_address: String,
+    /// Base units as a decimal string.
+    pub amount: String,
+    pub tx_hash: String,
+    pub log_index: i64,
+    pub block_number: i64,
+    pub confirmations: i64,
+    pub required_confirmations: i64,
+    pub status: String,
+    pub detected_at: i64,
+    pub confirmed_at: Option<i64>,
+}
+
+pub struct DepositDatabase {
+    pub conn: Connection,
+}
+
+impl DepositDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS deposits (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                chain TEXT NOT NULL,
+                token TEXT NOT NULL,
+                from_address TEXT NOT NULL,
+                to_address TEXT NOT NULL,
+                amount TEXT NOT NULL,
+                tx_hash TEXT NOT NULL,
+                log_index INTEGER NOT NULL,
+                block_number INTEGER NOT NULL,
+                confirmations INTEGER NOT NULL DEFAULT 0,
+                required_confirmations INTEGER NOT NULL,
+                status TEXT NOT NULL,
+                detected_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                confirmed_at INTEGER,
+                UNIQUE (chain, tx_hash, log_index)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS deposits_user_id ON deposits (user_id, detected_at)",
+            [],
+        )?;
+
+        Ok(DepositDatabase { conn })
+    }
+
+    /// Indexed transfers without a live deposit row: new ones, and ones that
+    /// were orphaned and have since been mined again.
+    pub fn untracked_transfers(&self) -> Result<Vec<IndexedTransfer>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM indexed_transfers t WHERE NOT EXISTS (
+                SELECT 1 FROM deposits d WHERE d.chain = t.chain AND d.tx_hash = t.tx_hash
+                    AND d.log_index = t.log_index AND d.status != ?1
+             )
+             ORDER BY block_number, log_index",
+            TRANSFER_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![DEPOSIT_ORPHANED], indexer::transfer_from_row)?;
+        rows.collect()
+    }
+
+    /// Records a pending deposit, reviving an orphaned row for the same log.
+    pub fn record_detected(
+        &self,
+        user_id: &str,
+        transfer: &IndexedTransfer,
+        required_confirmations: u64,
+    ) -> Result<i64> {
+        self.conn.query_row(
+            "INSERT INTO deposits (user_id, chain, token, from_address, to_address, amount,
+                tx_hash, log_index, block_number, required_confirmations, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+             ON CONFLICT (chain, tx_hash, log_index) DO UPDATE SET
+                block_number = excluded.block_number, confirmations = 0,
+                status = excluded.status, detected_at = strftime('%s', 'now')
+             RETURNING id",
+            params![
+                user_id,
+                transfer.chain,
+                transfer.token,
+                transfer.from_address,
+                transfer.to_address,
+                transfer.amount,
+                transfer.tx_hash,
+                transfer.log_index,
+                transfer.block_number,
+                required_confirmations as i64,
+                DEPOSIT_PENDING
+            ],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn get(&self, id: i64) -> Result<Deposit> {
+        self.conn.query_row(
+            &format!("SELECT {} FROM deposits WHERE id = ?1", DEPOSIT_COLUMNS),
+            params![id],
+            from_row,
+        )
+    }
+
+    /// Pending deposits on `chain` with the block their transfer is currently
+    /// indexed at, or `None` if a reorg removed it.
+    pub fn pending(&self, chain: &str) -> Result<Vec<(Deposit, Option<i64>)>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {}, (SELECT t.block_number FROM indexed_transfers t
+                WHERE t.chain = deposits.chain AND t.tx_hash = deposits.tx_hash
+                    AND t.log_index = deposits.log_index)
+             FROM deposits WHERE chain = ?1 AND status = ?2 ORDER BY id",
+            DEPOSIT_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![chain, DEPOSIT_PENDING], |row| {
+            Ok((from_row(row)?, row.get(15)?))
+        })?;
+        rows.collect()
+    }
+
+    pub fn update_progress(
+        &self,
+        id: i64,
+        status: &str,
+        block_number: i64,
+        confirmations: i64,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE deposits SET status = ?1, block_number = ?2, confirmations = ?3,
+                confirmed_at = CASE WHEN ?1 = ?4 THEN strftime('%s', 'now') ELSE confirmed_at END
+             WHERE id = ?5",
+            params![status, block_number, confirmations, DEPOSIT_CONFIRMED, id],
+        )?;
+        Ok(())
+    }
+
+    pub fn list_for_user(&self, user_id: &str) -> Result<Vec<Deposit>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM deposits WHERE user_id = ?1 ORDER BY detected_at DESC, id DESC",
+            DEPOSIT_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![user_id], from_row)?;
+        rows.collect()
+    }
+}
+
+fn from_row(row: &Row) -> Result<Deposit> {
+    Ok(Deposit {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        chain: row.get(2)?,
+        token: row.get(3)?,
+        from_address: row.get(4)?,
+        to_address: row.get(5)?,
+        amount: row.get(6)?,
+        tx_hash: row.get(7)?,
+        log_index: row.get(8)?,
+        block_number: row.get(9)?,
+        confirmations: row.get(10)?,
+        required_confirmations: row.get(11)?,
+        status: row.get(12)?,
+        detected_at: row.get(13)?,
+        confirmed_at: row.get(14)?,
+    })
+}
+
+/// Confirmations required on `chain`, from `DEPOSIT_CONFIRMATIONS_<CHAIN>`.
+pub fn required_confirmations(chain: &str) -> u64 {
+    let key = format!(
+        "DEPOSIT_CONFIRMATIONS_{}",
+        chain.to_uppercase().replace('-', "_")
+    );
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DEPOSIT_DEFAULT_CONFIRMATIONS)
+}
+
+/// Announces a deposit transition to webhook endpoints and to the user's
+/// live streams.
+fn notify(events: &EventBus, event: &str, db: &DepositDatabase, id: i64) {
+    let deposit = match db.get(id) {
+        Ok(deposit) => deposit,
+        Err(e) => {
+            println!("Failed to load deposit {}: {}", id, e);
+            return;
+        }
+    };
+    events.publish(
+        &deposit.user_id,
+        events::EVENT_DEPOSIT,
+        serde_json::json!({ "event": event, "deposit": deposit, "status": deposit.status }),
+    );
+    webhooks::notify(event, serde_json::json!(deposit));
+}
+
+/// Turns newly indexed incoming transfers to managed wallets into pending
+/// deposits. Swap proceeds are left out; they are reported as swaps.
+pub fn detect_new(events: &EventBus) -> std::result::Result<usize, String> {
+    // Make sure the indexer tables exist before joining against them.
+    IndexerDatabase::new().map_err(|e| e.to_string())?;
+    let db = DepositDatabase::new().map_err(|e| e.to_string())?;
+    let wallets = WalletDatabase::new().map_err(|e| e.to_string())?;
+    let swaps = SwapHistoryDatabase::new().map_err(|e| e.to_string())?;
+    let managed: HashSet<String> = indexer::managed_addresses()
+        .map_err(|e| e.to_string())?
+        .iter()
+        .map(|a| format!("{:#x}", a))
+        .collect();
+
+    let mut detected = 0;
+    for transfer in db.untracked_transfers().map_err(|e| e.to_string())? {
+        if !managed.contains(&transfer.to_address) {
+            continue;
+        }
+        if swaps
+            .is_swap_tx(&transfer.tx_hash)
+            .map_err(|e| e.to_string())?
+        {
+            continue;
+        }
+        let Some(user_id) = wallets
+            .owner(&transfer.to_address)
+            .map_err(|e| e.to_string())?
+        else {
+            continue;
+        };
+
+        let id = db
+            .record_detected(&user_id, &transfer, required_confirmations(&transfer.chain))
+            .map_err(|e| e.to_string())?;
+        notify(events, EVENT_DETECTED, &db, id);
+        detected += 1;
+    }
+
+    Ok(detected)
+}
+
+/// Advances pending deposits on `chain` against the chain head, confirming
+/// those that reached their threshold and orphaning those a reorg removed.
+pub async fn update_confirmations(
+    events: &EventBus,
+    chain: &str,
+) -> std::result::Result<(), String> {
+    let pending = DepositDatabase::new()
+        .and_then(|db| db.pending(chain))
+        .map_err(|e| e.to_string())?;
+    if pending.is_empty() {
+        return Ok(());
+    }
+
+    let provider = chain::provider_for(chain)?;
+    let latest = provider
+        .get_block_number()
+        .await
+        .map_err(|e| e.to_string())?
+        .as_u64() as i64;
+
+    let db = DepositDatabase::new().map_err(|e| e.to_string())?;
+    for (deposit, indexed_block) in pending {
+        let (status, block_number, confirmations) = match indexed_block {
+            None => (DEPOSIT_ORPHANED, deposit.block_number, 0),
+            Some(block) => {
+                let confirmations = (latest - block + 1).max(0);
+                if confirmations >= deposit.required_confirmations {
+                    (DEPOSIT_CONFIRMED, block, confirmations)
+                } else {
+                    (DEPOSIT_PENDING, block, confirmations)
+                }
+            }
+        };
+        if status == DEPOSIT_PENDING
+            && block_number == deposit.block_number
+            && confirmations == deposit.confirmations
+        {
+            continue;
+        }
+
+        db.update_progress(deposit.id, status, block_number, confirmations)
+            .map_err(|e| e.to_string())?;
+        match status {
+            DEPOSIT_CONFIRMED => notify(events, EVENT_CONFIRMED, &db, deposit.id),
+            DEPOSIT_ORPHANED => notify(events, EVENT_ORPHANED, &db, deposit.id),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Polls the indexed transfers for deposits and tracks their confirmations.
+pub async fn run_detector(events: EventBus) {
+    let mut interval = tokio::time::interval(Duration::from_secs(DEPOSIT_POLL_INTERVAL_SECS));
+    let chains = indexer::configured_chains();
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = detect_new(&events) {
+            println!("Deposit detection failed: {}", e);
+        }
+        for chain in &chains {
+            if let Err(e) = update_confirmations(&events, chain).await {
+                println!("Deposit confirmation update failed on {}: {}", chain, e);
+            }
+        }
+    }
+}
+
+pub async fn get_deposits(
+    Path(user_id): Path<String>,
+) -> std::result::Result<Json<Vec<Deposit>>, (StatusCode, String)> {
+    let deposits = DepositDatabase::new()
+        .and_then(|db| db.list_for_user(&user_id))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to load deposits: {}", e),
+            )
+        })?;
+
+    Ok(Json(deposits))
+}
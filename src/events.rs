@@ -0,0 +1,301 @@
+use crate::chain;
+use crate::constants::{EVENT_BUS_CAPACITY, STREAM_KEEPALIVE_SECS};
+use crate::models::AppState;
+use crate::reauth::{self, ReauthDatabase};
+use crate::utils;
+use crate::wallets;
+use axum::{
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        Path, Query, State,
+    },
+    http::StatusCode,
+    response::{
+        sse::{self, KeepAlive, Sse},
+        IntoResponse,
+    },
+};
+use ethers::{
+    providers::Middleware,
+    types::{Address, U256},
+    utils::format_units,
+};
+use futures::stream::{self, Stream};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::convert::Infallible;
+use std::time::Duration;
+use tokio::sync::broadcast::{self, error::RecvError};
+
+pub const EVENT_BALANCE: &str = "balance";
+pub const EVENT_TRANSACTION: &str = "transaction";
+pub const EVENT_SWAP: &str = "swap";
+pub const EVENT_DEPOSIT: &str = "deposit";
+
+pub const TX_SUBMITTED: &str = "submitted";
+pub const TX_CONFIRMED: &str = "confirmed";
+pub const TX_FAILED: &str = "failed";
+/// The node stopped tracking the transaction before it was mined.
+pub const TX_DROPPED: &str = "dropped";
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Event {
+    pub user_id: String,
+    /// One of the `EVENT_*` kinds.
+    pub kind: String,
+    pub data: serde_json::Value,
+    pub created_at: i64,
+}
+
+/// In-process fan-out of user events. Publishing never blocks; subscribers
+/// that fall more than `EVENT_BUS_CAPACITY` events behind skip ahead.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<Event>,
+}
+
+impl EventBus {
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(EVENT_BUS_CAPACITY);
+        Self { sender }
+    }
+
+    pub fn publish(&self, user_id: &str, kind: &str, data: serde_json::Value) {
+        // Sending only fails when nobody is subscribed.
+        let _ = self.sender.send(Event {
+            user_id: user_id.to_string(),
+            kind: kind.to_string(),
+            data,
+            created_at: utils::now(),
+        });
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.sender.subscribe()
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Native balance of each of the user's custody wallets, with their total
+/// as `balance`. `None` when the user has no wallet.
+async fn native_balances(user_id: &str) -> Result<Option<serde_json::Value>, String> {
+    let addresses = wallets::custody_addresses(user_id).map_err(|e| e.to_string())?;
+    if addresses.is_empty() {
+        return Ok(None);
+    }
+
+    let provider = chain::provider()?;
+    let mut total = U256::zero();
+    let mut per_wallet = serde_json::Map::new();
+    for address in addresses {
+        let wallet = address
+            .parse::<Address>()
+            .map_err(|_| format!("Invalid wallet address: {}", address))?;
+        let balance = provider
+            .get_balance(wallet, None)
+            .await
+            .map_err(|e| e.to_string())?;
+        total += balance;
+        per_wallet.insert(
+            address,
+            format_units(balance, 18).map_err(|e| e.to_string())?.into(),
+        );
+    }
+    Ok(Some(serde_json::json!({
+        "balance": format_units(total, 18).map_err(|e| e.to_string())?,
+        "wallets": per_wallet,
+    })))
+}
+
+/// Whether `event` can have moved funds in or out of the user's wallet.
+fn affects_balance(event: &Event) -> bool {
+    let status = event.data["status"].as_str().unwrap_or_default();
+    match event.kind.as_str() {
+        EVENT_TRANSACTION => status == TX_CONFIRMED,
+        EVENT_SWAP => status == crate::swap_history::STATUS_COMPLETED,
+        EVENT_DEPOSIT => true,
+        _ => false,
+    }
+}
+
+/// Re-reads a user's wallet balances after events that move funds and
+/// publishes a `balance` event when they changed.
+pub async fn run_balance_watcher(events: EventBus) {
+    let mut receiver = events.subscribe();
+    let mut last_known: HashMap<String, serde_json::Value> = HashMap::new();
+
+    loop {
+        let event = match receiver.recv().await {
+            Ok(event) => event,
+            Err(RecvError::Lagged(skipped)) => {
+                println!("Balance watcher skipped {} events", skipped);
+                continue;
+            }
+            Err(RecvError::Closed) => return,
+        };
+        if !affects_balance(&event) {
+            continue;
+        }
+
+        match native_balances(&event.user_id).await {
+            Ok(Some(balances)) => {
+                if last_known.get(&event.user_id) == Some(&balances) {
+                    continue;
+                }
+                last_known.insert(event.user_id.clone(), balances.clone());
+                events.publish(&event.user_id, EVENT_BALANCE, balances);
+            }
+            Ok(None) => {}
+            Err(e) => println!("Failed to refresh balance for {}: {}", event.user_id, e),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StreamQuery {
+    /// Comma separated event kinds; all kinds when omitted.
+    pub events: Option<String>,
+    /// Stream token issued at sign-in. Passed in the query because
+    /// `EventSource` and browser WebSockets cannot set headers.
+    pub token: Option<String>,
+}
+
+/// Rejects the connection unless `token` is an unexpired stream token
+/// issued to `user_id`.
+fn require_stream_token(user_id: &str, token: Option<&str>) -> Result<(), (StatusCode, String)> {
+    let token = token.unwrap_or_default();
+    if token.is_empty() {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "A stream token is required: pass the one issued at sign-in as ?token=".to_string(),
+        ));
+    }
+
+    let valid = ReauthDatabase::new()
+        .and_then(|db| db.verify(user_id, reauth::PURPOSE_STREAM, token))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to check stream token: {}", e),
+            )
+        })?;
+    if !valid {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Stream token is invalid or expired; sign in again".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+struct Subscription {
+    user_id: String,
+    kinds: Vec<String>,
+    receiver: broadcast::Receiver<Event>,
+}
+
+impl Subscription {
+    fn new(events: &EventBus, user_id: String, query: StreamQuery) -> Self {
+        let kinds = query
+            .events
+            .unwrap_or_default()
+            .split(',')
+            .map(|k| k.trim().to_lowercase())
+            .filter(|k| !k.is_empty())
+            .collect();
+        Self {
+            user_id,
+            kinds,
+            receiver: events.subscribe(),
+        }
+    }
+
+    /// Next event for this user, or `None` once the bus is gone. Events lost
+    /// to lag are dropped; clients resync through the REST endpoints.
+    async fn next(&mut self) -> Option<Event> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => {
+                    if event.user_id == self.user_id
+                        && (self.kinds.is_empty() || self.kinds.contains(&event.kind))
+                    {
+                        return Some(event);
+                    }
+                }
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
+
+/// Server-Sent Events stream of the user's events. Each message is named
+/// after the event kind and carries the full event as JSON.
+pub async fn stream_sse(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Query(query): Query<StreamQuery>,
+) -> Result<Sse<impl Stream<Item = Result<sse::Event, Infallible>>>, (StatusCode, String)> {
+    require_stream_token(&user_id, query.token.as_deref())?;
+    let subscription = Subscription::new(&state.events, user_id, query);
+
+    let stream = stream::unfold(subscription, |mut subscription| async move {
+        let event = subscription.next().await?;
+        let message = sse::Event::default()
+            .event(event.kind.clone())
+            .json_data(&event)
+            .unwrap_or_default();
+        Some((Ok(message), subscription))
+    });
+
+    Ok(Sse::new(stream)
+        .keep_alive(KeepAlive::new().interval(Duration::from_secs(STREAM_KEEPALIVE_SECS))))
+}
+
+/// WebSocket stream of the user's events as JSON text frames. Messages
+/// from the client are ignored apart from close frames.
+pub async fn stream_ws(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Query(query): Query<StreamQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Result<impl IntoResponse, (StatusCode, String)> {
+    require_stream_token(&user_id, query.token.as_deref())?;
+    let subscription = Subscription::new(&state.events, user_id, query);
+    Ok(upgrade.on_upgrade(move |socket| forward_to_socket(socket, subscription)))
+}
+
+async fn forward_to_socket(mut socket: WebSocket, mut subscription: Subscription) {
+    let mut keepalive = tokio::time::interval(Duration::from_secs(STREAM_KEEPALIVE_SECS));
+
+    loop {
+        tokio::select! {
+            event = subscription.next() => {
+                let Some(event) = event else { break };
+                let text = match serde_json::to_string(&event) {
+                    Ok(text) => text,
+                    Err(_) => continue,
+                };
+                if socket.send(Message::Text(text)).await.is_err() {
+                    break;
+                }
+            }
+            incoming = socket.recv() => {
+                match incoming {
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+                    Some(Ok(_)) => {}
+                }
+            }
+            _ = keepalive.tick() => {
+                if socket.send(Message::Ping(Vec::new())).await.is_err() {
+                    break;
+                }
+            }
+        }
+    }
+}
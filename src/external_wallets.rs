@@ -0,0 +1,354 @@
+use crate::constants::{DB_PATH, EXTERNAL_WALLET_CHALLENGE_TTL_SECS};
+use crate::profiles::ProfileDatabase;
+use crate::utils;
+use crate::wallets::{self, WalletDatabase};
+use axum::{extract::Path, http::StatusCode, Json};
+use ethers::types::{Address, Signature};
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A self-custody address the user proved they control. Watch-only: it is
+/// shown in portfolio and history but never signs anything here.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ExternalWallet {
+    pub id: Option<i64>,
+    pub user_id: String,
+    pub address: String,
+    pub label: Option<String>,
+    pub linked_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct LinkChallenge {
+    pub address: String,
+    pub nonce: String,
+    /// Text to sign with `personal_sign` (EIP-191).
+    pub message: String,
+    pub expires_at: i64,
+}
+
+pub struct ExternalWalletDatabase {
+    pub conn: Connection,
+}
+
+impl ExternalWalletDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS external_wallets (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                address TEXT NOT NULL,
+                label TEXT,
+                linked_at INTEGER NOT NULL,
+                UNIQUE(user_id, address)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS external_wallet_challenges (
+                nonce TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                address TEXT NOT NULL,
+                message TEXT NOT NULL,
+                expires_at INTEGER NOT NULL,
+                used_at INTEGER
+            )",
+            [],
+        )?;
+
+        Ok(ExternalWalletDatabase { conn })
+    }
+
+    pub fn create_challenge(&self, challenge: &LinkChallenge, user_id: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM external_wallet_challenges WHERE expires_at < ?1",
+            params![utils::now()],
+        )?;
+        self.conn.execute(
+            "INSERT INTO external_wallet_challenges (nonce, user_id, address, message, expires_at)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                challenge.nonce,
+                user_id,
+                challenge.address,
+                challenge.message,
+                challenge.expires_at
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Unused, unexpired challenges issued to `user_id` for `address`,
+    /// newest first.
+    pub fn open_challenges(&self, user_id: &str, address: &str) -> Result<Vec<LinkChallenge>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT address, nonce, message, expires_at FROM external_wallet_challenges
+             WHERE user_id = ?1 AND address = ?2 AND used_at IS NULL AND expires_at >= ?3
+             ORDER BY expires_at DESC",
+        )?;
+        let rows = stmt.query_map(params![user_id, address, utils::now()], |row| {
+            Ok(LinkChallenge {
+                address: row.get(0)?,
+                nonce: row.get(1)?,
+                message: row.get(2)?,
+                expires_at: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Spends the challenge and links the address in one transaction, so a
+    /// signature can only be used once.
+    pub fn link(
+        &self,
+        user_id: &str,
+        nonce: &str,
+        address: &str,
+        label: Option<&str>,
+    ) -> Result<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        let spent = tx.execute(
+            "UPDATE external_wallet_challenges SET used_at = ?1
+             WHERE nonce = ?2 AND used_at IS NULL",
+            params![utils::now(), nonce],
+        )?;
+        if spent == 0 {
+            return Ok(false);
+        }
+        tx.execute(
+            "INSERT INTO external_wallets (user_id, address, label, linked_at)
+             VALUES (?1, ?2, ?3, ?4)
+             ON CONFLICT(user_id, address) DO UPDATE SET label = excluded.label",
+            params![user_id, address, label, utils::now()],
+        )?;
+        tx.commit()?;
+        Ok(true)
+    }
+
+    pub fn list_for_user(&self, user_id: &str) -> Result<Vec<ExternalWallet>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, user_id, address, label, linked_at FROM external_wallets
+             WHERE user_id = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![user_id], external_from_row)?;
+        rows.collect()
+    }
+
+    /// Looks up a linked wallet by address or label.
+    pub fn find_for_user(&self, user_id: &str, selector: &str) -> Result<Option<ExternalWallet>> {
+        self.conn
+            .query_row(
+                "SELECT id, user_id, address, label, linked_at FROM external_wallets
+                 WHERE user_id = ?1 AND (address = ?2 COLLATE NOCASE OR label = ?2)",
+                params![user_id, selector],
+                external_from_row,
+            )
+            .optional()
+    }
+
+    pub fn unlink(&self, id: i64) -> Result<()> {
+        self.conn
+            .execute("DELETE FROM external_wallets WHERE id = ?1", params![id])?;
+        Ok(())
+    }
+
+    /// Every linked address, for the indexer.
+    pub fn addresses(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT DISTINCT address FROM external_wallets")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+}
+
+fn external_from_row(row: &Row) -> Result<ExternalWallet> {
+    Ok(ExternalWallet {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        address: row.get(2)?,
+        label: row.get(3)?,
+        linked_at: row.get(4)?,
+    })
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("External wallet database error: {}", e),
+    )
+}
+
+fn parse_address(address: &str) -> std::result::Result<String, (StatusCode, String)> {
+    Address::from_str(address.trim())
+        .map(|a| format!("{:#x}", a))
+        .map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid address: {}", address),
+            )
+        })
+}
+
+fn timestamp(secs: i64) -> String {
+    chrono::DateTime::from_timestamp(secs, 0)
+        .unwrap_or_default()
+        .to_rfc3339_opts(chrono::SecondsFormat::Secs, true)
+}
+
+fn challenge_message(
+    user_id: &str,
+    address: &str,
+    nonce: &str,
+    issued_at: i64,
+    expires_at: i64,
+) -> String {
+    format!(
+        "Link this address to your profile as a watch-only wallet.\n\
+         This signature does not authorize any transaction.\n\n\
+         Address: {}\nProfile: {}\nNonce: {}\nIssued At: {}\nExpires At: {}",
+        address,
+        user_id,
+        nonce,
+        timestamp(issued_at),
+        timestamp(expires_at)
+    )
+}
+
+/// Whether `signature` is a `personal_sign` of `message` by `address`.
+fn signed_by(message: &str, signature: &str, address: &str) -> bool {
+    let Ok(signature) = Signature::from_str(signature.trim()) else {
+        return false;
+    };
+    match signature.recover(message) {
+        Ok(signer) => format!("{:#x}", signer) == address,
+        Err(_) => false,
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ChallengeRequest {
+    pub address: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LinkRequest {
+    pub address: String,
+    /// Hex signature of the challenge message.
+    pub signature: String,
+    pub label: Option<String>,
+}
+
+/// Issues the message the user signs to prove control of `address`.
+pub async fn create_challenge(
+    Path(user_id): Path<String>,
+    Json(req): Json<ChallengeRequest>,
+) -> std::result::Result<Json<LinkChallenge>, (StatusCode, String)> {
+    let address = parse_address(&req.address)?;
+    ProfileDatabase::new()
+        .and_then(|db| db.get(&user_id))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+    if WalletDatabase::new()
+        .and_then(|db| db.get(&address))
+        .map_err(db_error)?
+        .is_some()
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            "This address is already a custody wallet".to_string(),
+        ));
+    }
+
+    let issued_at = utils::now();
+    let expires_at = issued_at + EXTERNAL_WALLET_CHALLENGE_TTL_SECS;
+    let nonce = hex::encode(rand::random::<[u8; 16]>());
+    let challenge = LinkChallenge {
+        message: challenge_message(&user_id, &address, &nonce, issued_at, expires_at),
+        address,
+        nonce,
+        expires_at,
+    };
+    ExternalWalletDatabase::new()
+        .and_then(|db| db.create_challenge(&challenge, &user_id))
+        .map_err(db_error)?;
+
+    Ok(Json(challenge))
+}
+
+/// Links `address` once the signature recovers to it from one of the user's
+/// open challenges.
+pub async fn link_wallet(
+    Path(user_id): Path<String>,
+    Json(req): Json<LinkRequest>,
+) -> std::result::Result<Json<ExternalWallet>, (StatusCode, String)> {
+    let address = parse_address(&req.address)?;
+    let label = match &req.label {
+        Some(label) => Some(wallets::validate_label(label)?),
+        None => None,
+    };
+
+    let db = ExternalWalletDatabase::new().map_err(db_error)?;
+    let challenge = db
+        .open_challenges(&user_id, &address)
+        .map_err(db_error)?
+        .into_iter()
+        .find(|c| signed_by(&c.message, &req.signature, &address))
+        .ok_or((
+            StatusCode::UNAUTHORIZED,
+            "Signature does not match an open challenge for this address".to_string(),
+        ))?;
+
+    if let Some(label) = &label {
+        if let Some(existing) = db.find_for_user(&user_id, label).map_err(db_error)? {
+            if existing.address != address {
+                return Err((
+                    StatusCode::CONFLICT,
+                    format!("A wallet labelled {} already exists", label),
+                ));
+            }
+        }
+    }
+
+    if !db
+        .link(&user_id, &challenge.nonce, &address, label.as_deref())
+        .map_err(db_error)?
+    {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Challenge was already used".to_string(),
+        ));
+    }
+
+    let wallet = db
+        .find_for_user(&user_id, &address)
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Wallet not found".to_string()))?;
+    Ok(Json(wallet))
+}
+
+pub async fn list_external_wallets(
+    Path(user_id): Path<String>,
+) -> std::result::Result<Json<Vec<ExternalWallet>>, (StatusCode, String)> {
+    let wallets = ExternalWalletDatabase::new()
+        .and_then(|db| db.list_for_user(&user_id))
+        .map_err(db_error)?;
+    Ok(Json(wallets))
+}
+
+pub async fn unlink_wallet(
+    Path((user_id, selector)): Path<(String, String)>,
+) -> std::result::Result<StatusCode, (StatusCode, String)> {
+    let db = ExternalWalletDatabase::new().map_err(db_error)?;
+    let wallet = db
+        .find_for_user(&user_id, &selector)
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Wallet not found".to_string()))?;
+
+    db.unlink(wallet.id.unwrap_or_default()).map_err(db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,385 @@
+#![allow(dead_code)]
+
+use crate::constants::{
+    DB_PATH, GITHUB_ACTIVITY_INTERVAL_SECS, GITHUB_API_URL, IDENTITY_USER_AGENT,
+};
+use crate::models::GithubActivity;
+use crate::projects::ProjectSummaryDatabase;
+use crate::utils;
+use async_trait::async_trait;
+use reqwest::{header, Client, Url};
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result};
+use std::env;
+use std::path::PathBuf;
+use std::time::Duration;
+use tokio::process::Command;
+
+const ACTIVITY_WINDOW_SECS: i64 = 30 * 24 * 60 * 60;
+
+#[derive(Debug, Clone, Default)]
+pub struct ActivityMetrics {
+    pub commits_last_30_days: u64,
+    pub contributors: u64,
+}
+
+/// Where repository activity is read from.
+#[async_trait]
+pub trait RepoSource: Send + Sync {
+    async fn activity(&self, since: i64) -> std::result::Result<ActivityMetrics, String>;
+}
+
+/// Counts commits and contributors through the GitHub REST API. Requests ask
+/// for one item per page so the `last` page number in the Link header is the
+/// total count.
+pub struct GithubSource {
+    client: Client,
+    api_url: String,
+    owner: String,
+    repo: String,
+}
+
+impl GithubSource {
+    pub fn new(owner: &str, repo: &str) -> Self {
+        Self {
+            client: Client::new(),
+            api_url: env::var("GITHUB_API_URL").unwrap_or(GITHUB_API_URL.to_string()),
+            owner: owner.to_string(),
+            repo: repo.trim_end_matches(".git").to_string(),
+        }
+    }
+
+    async fn count(
+        &self,
+        path: &str,
+        query: &[(&str, String)],
+    ) -> std::result::Result<u64, String> {
+        let url = format!(
+            "{}/repos/{}/{}/{}",
+            self.api_url, self.owner, self.repo, path
+        );
+        let mut request = self
+            .client
+            .get(&url)
+            .query(query)
+            .query(&[("per_page", "1")])
+            .header(header::USER_AGENT, IDENTITY_USER_AGENT)
+            .header(header::ACCEPT, "application/vnd.github+json");
+        if let Ok(token) = env::var("GITHUB_TOKEN") {
+            request = request.bearer_auth(token);
+        }
+
+        let response = request.send().await.map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("GitHub returned {} for {}", response.status(), url));
+        }
+
+        let last_page = response
+            .headers()
+            .get(header::LINK)
+            .and_then(|v| v.to_str().ok())
+            .and_then(last_page_from_link);
+        if let Some(count) = last_page {
+            return Ok(count);
+        }
+
+        // No Link header means everything fit on the single page.
+        let items: Vec<serde_json::Value> = response.json().await.map_err(|e| e.to_string())?;
+        Ok(items.len() as u64)
+    }
+}
+
+#[async_trait]
+impl RepoSource for GithubSource {
+    async fn activity(&self, since: i64) -> std::result::Result<ActivityMetrics, String> {
+        let since = chrono::DateTime::from_timestamp(since, 0)
+            .ok_or("Invalid timestamp")?
+            .to_rfc3339();
+
+        Ok(ActivityMetrics {
+            commits_last_30_days: self.count("commits", &[("since", since)]).await?,
+            contributors: self
+                .count("contributors", &[("anon", "1".to_string())])
+                .await?,
+        })
+    }
+}
+
+fn last_page_from_link(link: &str) -> Option<u64> {
+    link.split(',')
+        .find(|part| part.contains("rel=\"last\""))
+        .and_then(|part| part.split(';').next())
+        .map(|url| url.trim().trim_start_matches('<').trim_end_matches('>'))
+        .and_then(|url| Url::parse(url).ok())
+        .and_then(|url| {
+            url.query_pairs()
+                .find(|(key, _)| key == "page")
+                .and_then(|(_, value)| value.parse().ok())
+        })
+}
+
+/// Reads activity from a local clone with `git`. Used for repositories that
+/// are not on GitHub and for exercising the collector without network access.
+pub struct LocalGitSource {
+    path: PathBuf,
+}
+
+impl LocalGitSource {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Self { path: path.into() }
+    }
+
+    async fn git(&self, args: &[&str]) -> std::result::Result<String, String> {
+        let output = Command::new("git")
+            .arg("-C")
+            .arg(&self.path)
+            .args(args)
+            .output()
+            .await
+            .map_err(|e| format!("Failed to run git: {}", e))?;
+        if !output.status.success() {
+            return Err(String::from_utf8_lossy(&output.stderr).trim().to_string());
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).to_string())
+    }
+}
+
+#[async_trait]
+impl RepoSource for LocalGitSource {
+    async fn activity(&self, since: i64) -> std::result::Result<ActivityMetrics, String> {
+        let commits = self
+            .git(&["rev-list", "--count", &format!("--since={}", since), "HEAD"])
+            .await?;
+        let authors = self.git(&["shortlog", "-sne", "HEAD"]).await?;
+
+        Ok(ActivityMetrics {
+            commits_last_30_days: commits.trim().parse().unwrap_or_default(),
+            contributors: authors.lines().filter(|l| !l.trim().is_empty()).count() as u64,
+        })
+    }
+}
+
+/// Picks a source for a summary's `repo_url`: GitHub repository URLs go to the
+/// API, `file://` URLs and plain paths to a local clone.
+pub fn source_for(repo_url: &str) -> std::result::Result<Box<dyn RepoSource>, String> {
+    if let Some(path) = repo_url.strip_prefix("file://") {
+        return Ok(Box::new(LocalGitSource::new(path)));
+    }
+    if repo_url.starts_with('/') {
+        return Ok(Box::new(LocalGitSource::new(repo_url)));
+    }
+
+    let url = Url::parse(repo_url).map_err(|_| format!("Invalid repo URL: {}", repo_url))?;
+    if url.host_str() != Some("github.com") {
+        return Err(format!("Unsupported repository host: {}", repo_url));
+    }
+    let segments: Vec<&str> = url
+        .path_segments()
+        .map(|s| s.filter(|s| !s.is_empty()).collect())
+        .unwrap_or_default();
+    match segments.as_slice() {
+        [owner, repo, ..] => Ok(Box::new(GithubSource::new(owner, repo))),
+        _ => Err(format!("Repo URL does not name a repository: {}", repo_url)),
+    }
+}
+
+pub struct GithubActivityDatabase {
+    pub conn: Connection,
+}
+
+impl GithubActivityDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS github_activity_snapshots (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                project_id INTEGER NOT NULL REFERENCES projects (id),
+                repo_url TEXT NOT NULL,
+                commits_last_30_days INTEGER NOT NULL,
+                contributors INTEGER NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+
+        Ok(GithubActivityDatabase { conn })
+    }
+
+    pub fn record(&self, project_id: i64, repo_url: &str, metrics: &ActivityMetrics) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO github_activity_snapshots (project_id, repo_url, commits_last_30_days, contributors)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![
+                project_id,
+                repo_url,
+                metrics.commits_last_30_days,
+                metrics.contributors
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn latest(&self, project_id: i64, repo_url: &str) -> Result<Option<GithubActivity>> {
+        self.conn
+            .query_row(
+                "SELECT commits_last_30_days, contributors, repo_url FROM github_activity_snapshots
+                 WHERE project_id = ?1 AND repo_url = ?2 ORDER BY created_at DESC, id DESC LIMIT 1",
+                params![project_id, repo_url],
+                |row| {
+                    Ok(GithubActivity {
+                        commits_last_30_days: row.get(0)?,
+                        contributors: row.get(1)?,
+                        repo_url: row.get(2)?,
+                    })
+                },
+            )
+            .optional()
+    }
+}
+
+pub async fn collect_all() -> std::result::Result<usize, String> {
+    let summaries = ProjectSummaryDatabase::new().map_err(|e| e.to_string())?;
+    let repos = summaries.repositories().map_err(|e| e.to_string())?;
+    let db = GithubActivityDatabase::new().map_err(|e| e.to_string())?;
+    let since = utils::now() - ACTIVITY_WINDOW_SECS;
+
+    let mut collected = 0;
+    for (project_id, repo_url) in repos {
+        let result = match source_for(&repo_url) {
+            Ok(source) => source.activity(since).await,
+            Err(e) => Err(e),
+        };
+
+        match result {
+            Ok(metrics) => {
+                db.record(project_id, &repo_url, &metrics)
+                    .map_err(|e| e.to_string())?;
+                // Search sorts by the summary's own columns.
+                summaries
+                    .set_activity(
+                        project_id,
+                        &repo_url,
+                        metrics.commits_last_30_days,
+                        metrics.contributors,
+                    )
+                    .map_err(|e| e.to_string())?;
+                collected += 1;
+            }
+            Err(e) => println!("GitHub activity failed for {}: {}", repo_url, e),
+        }
+    }
+
+    Ok(collected)
+}
+
+pub async fn run_collector() {
+    let mut interval = tokio::time::interval(Duration::from_secs(GITHUB_ACTIVITY_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = collect_all().await {
+            println!("GitHub activity collector failed: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Project, ProjectSummary};
+    use crate::projects::ProjectDatabase;
+    use std::path::Path;
+
+    fn git(dir: &Path, args: &[&str], author: &str, date: &str) {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .env("GIT_AUTHOR_NAME", author)
+            .env("GIT_AUTHOR_EMAIL", format!("{}@example.com", author))
+            .env("GIT_COMMITTER_NAME", author)
+            .env("GIT_COMMITTER_EMAIL", format!("{}@example.com", author))
+            .env("GIT_AUTHOR_DATE", date)
+            .env("GIT_COMMITTER_DATE", date)
+            .status()
+            .unwrap();
+        assert!(status.success(), "git {:?} failed", args);
+    }
+
+    /// A repository with one commit older than the activity window and two
+    /// recent ones, by two authors.
+    fn temp_repo() -> PathBuf {
+        let dir = env::temp_dir().join(format!("activity-repo-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+
+        let now = utils::now();
+        let old = format!("@{} +0000", now - ACTIVITY_WINDOW_SECS - 24 * 60 * 60);
+        let recent = format!("@{} +0000", now - 24 * 60 * 60);
+        git(&dir, &["init", "-q"], "alice", &recent);
+        for (author, date) in [("alice", &old), ("alice", &recent), ("bob", &recent)] {
+            git(
+                &dir,
+                &["commit", "-q", "--allow-empty", "-m", "change"],
+                author,
+                date,
+            );
+        }
+        dir
+    }
+
+    #[tokio::test]
+    async fn collects_commit_and_contributor_counts_from_a_local_repo() {
+        utils::use_temp_db();
+        let repo = temp_repo();
+        let repo_url = repo.to_string_lossy().to_string();
+
+        let metrics = LocalGitSource::new(&repo)
+            .activity(utils::now() - ACTIVITY_WINDOW_SECS)
+            .await
+            .unwrap();
+        assert_eq!(metrics.commits_last_30_days, 2);
+        assert_eq!(metrics.contributors, 2);
+
+        let project_id = ProjectDatabase::new()
+            .and_then(|db| {
+                db.create(&Project {
+                    chain: "educhain".to_string(),
+                    name: "Activity".to_string(),
+                    ..Default::default()
+                })
+            })
+            .unwrap();
+        ProjectSummaryDatabase::new()
+            .and_then(|db| {
+                db.upsert(&ProjectSummary {
+                    project_id: Some(project_id),
+                    chain: "educhain".to_string(),
+                    name: "Activity".to_string(),
+                    github_activity: GithubActivity {
+                        repo_url: repo_url.clone(),
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                })
+            })
+            .unwrap();
+
+        assert!(collect_all().await.unwrap() >= 1);
+        let recorded = GithubActivityDatabase::new()
+            .and_then(|db| db.latest(project_id, &repo_url))
+            .unwrap()
+            .unwrap();
+        assert_eq!(recorded.commits_last_30_days, 2);
+        assert_eq!(recorded.contributors, 2);
+
+        let summary = ProjectSummaryDatabase::new()
+            .and_then(|db| db.get(project_id, "educhain"))
+            .unwrap()
+            .unwrap();
+        assert_eq!(summary.github_activity.commits_last_30_days, 2);
+        assert_eq!(summary.github_activity.contributors, 2);
+    }
+}
@@ -0,0 +1,166 @@
+#![allow(dead_code)]
+
+use crate::defi::magpiefi::MagpieClient;
+use crate::defi::models::{QuoteParams, QuoteResponse};
+use ethers::types::U256;
+use serde::{Deserialize, Serialize};
+use std::env;
+
+// Fraction of the requested amount used for the reference quote. Small enough
+// that the pool barely moves, so its rate approximates the spot price.
+const REFERENCE_AMOUNT_DIVISOR: u64 = 1000;
+
+#[derive(Debug, Clone)]
+pub struct SwapPolicy {
+    pub default_slippage: f64,
+    pub max_slippage: f64,
+    pub warn_price_impact: f64,
+    pub max_price_impact: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PriceImpact {
+    pub reference_rate: f64,
+    pub quoted_rate: f64,
+    pub impact: f64,
+}
+
+impl Default for SwapPolicy {
+    fn default() -> Self {
+        Self {
+            default_slippage: 0.005,
+            max_slippage: 0.05,
+            warn_price_impact: 0.03,
+            max_price_impact: 0.15,
+        }
+    }
+}
+
+impl SwapPolicy {
+    pub fn from_env() -> Self {
+        let defaults = Self::default();
+        Self {
+            default_slippage: env_f64("SWAP_DEFAULT_SLIPPAGE", defaults.default_slippage),
+            max_slippage: env_f64("SWAP_MAX_SLIPPAGE", defaults.max_slippage),
+            warn_price_impact: env_f64("SWAP_WARN_PRICE_IMPACT", defaults.warn_price_impact),
+            max_price_impact: env_f64("SWAP_MAX_PRICE_IMPACT", defaults.max_price_impact),
+        }
+    }
+
+    /// Parses a slippage fraction (e.g. "0.005" for 0.5%) and checks it against the
+    /// policy bounds. An empty value falls back to the default slippage.
+    pub fn parse_slippage(&self, slippage: &str) -> Result<f64, String> {
+        let slippage = slippage.trim();
+        if slippage.is_empty() {
+            return Ok(self.default_slippage);
+        }
+
+        let value = slippage
+            .parse::<f64>()
+            .map_err(|_| format!("Invalid slippage: {}", slippage))?;
+
+        if !value.is_finite() || value <= 0.0 {
+            return Err(format!("Slippage must be greater than 0, got {}", value));
+        }
+        if value > self.max_slippage {
+            return Err(format!(
+                "Slippage {} exceeds the maximum allowed {}",
+                value, self.max_slippage
+            ));
+        }
+
+        Ok(value)
+    }
+
+    /// Returns the warning to surface with the quote, or the reason the swap
+    /// is rejected when the impact is above policy.
+    pub fn check_price_impact(
+        &self,
+        impact: f64,
+        accept_high_impact: bool,
+    ) -> Result<Option<String>, String> {
+        if impact > self.max_price_impact {
+            return Err(format!(
+                "Price impact {:.2}% exceeds the maximum allowed {:.2}%",
+                impact * 100.0,
+                self.max_price_impact * 100.0
+            ));
+        }
+
+        if impact > self.warn_price_impact {
+            let reason = format!(
+                "Price impact {:.2}% is above the {:.2}% warning threshold",
+                impact * 100.0,
+                self.warn_price_impact * 100.0
+            );
+            if !accept_high_impact {
+                return Err(format!(
+                    "{}; resend with accept_high_impact to proceed",
+                    reason
+                ));
+            }
+            return Ok(Some(reason));
+        }
+
+        Ok(None)
+    }
+}
+
+/// Amount of the reference quote for a swap of `amount`. Swaps too small to
+/// carve a reference out of are rejected rather than left unchecked.
+pub fn reference_amount(amount: &str) -> Result<U256, String> {
+    let amount = U256::from_dec_str(amount).map_err(|_| format!("Invalid amount: {}", amount))?;
+    let reference_amount = amount / U256::from(REFERENCE_AMOUNT_DIVISOR);
+    if reference_amount.is_zero() {
+        return Err(format!(
+            "Amount {} is too small to check price impact; the minimum is {} base units",
+            amount, REFERENCE_AMOUNT_DIVISOR
+        ));
+    }
+    Ok(reference_amount)
+}
+
+/// Estimates price impact by comparing the quoted rate against the rate of a
+/// much smaller reference quote for the same pair.
+pub async fn estimate_price_impact(
+    magpie: &MagpieClient,
+    params: &QuoteParams,
+    quote: &QuoteResponse,
+) -> Result<PriceImpact, String> {
+    let reference_params = QuoteParams {
+        amount: reference_amount(&params.amount)?.to_string(),
+        ..params.clone()
+    };
+    let reference = magpie
+        .get_quote(&reference_params)
+        .await
+        .map_err(|e| format!("Failed to get reference quote: {}", e))?;
+
+    let reference_rate = rate(&reference_params.amount, &reference.to_token_amount);
+    let quoted_rate = rate(&params.amount, &quote.to_token_amount);
+
+    match (reference_rate, quoted_rate) {
+        (Some(reference_rate), Some(quoted_rate)) if reference_rate > 0.0 => Ok(PriceImpact {
+            reference_rate,
+            quoted_rate,
+            impact: (1.0 - quoted_rate / reference_rate).max(0.0),
+        }),
+        _ => Err("Quotes returned no usable rate to check price impact".to_string()),
+    }
+}
+
+fn rate(amount_in: &str, amount_out: &str) -> Option<f64> {
+    let amount_in = amount_in.parse::<f64>().ok()?;
+    let amount_out = amount_out.parse::<f64>().ok()?;
+    if amount_in <= 0.0 {
+        return None;
+    }
+    Some(amount_out / amount_in)
+}
+
+fn env_f64(key: &str, default: f64) -> f64 {
+    env::var(key)
+        .ok()
+        .and_then(|v| v.parse::<f64>().ok())
+        .unwrap_or(default)
+}
@@ -0,0 +1,189 @@
+use crate::constants::{DEFAULT_CHAIN, HISTORY_DEFAULT_LIMIT, HISTORY_MAX_LIMIT};
+use crate::external_wallets::ExternalWalletDatabase;
+use crate::indexer::IndexerDatabase;
+use crate::profiles::ProfileDatabase;
+use crate::swap_history::SwapHistoryDatabase;
+use crate::wallets::WalletDatabase;
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct HistoryEntry {
+    /// "deposit", "withdrawal", "internal" (between the user's own wallets)
+    /// or "swap".
+    pub kind: String,
+    /// "chain" for indexed transfers, "api" for activity initiated here.
+    pub source: String,
+    pub chain: String,
+    pub token: String,
+    /// Base units as a decimal string.
+    pub amount: String,
+    /// Output side of a swap.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to_token: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount_out: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub counterparty: Option<String>,
+    pub tx_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_number: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub status: Option<String>,
+    /// The user's side of the transfer is a linked watch-only wallet.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub watch_only: bool,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryPage {
+    pub entries: Vec<HistoryEntry>,
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Failed to load history: {}", e),
+    )
+}
+
+/// Everything that moved funds in or out of a user's wallets, including
+/// linked watch-only wallets, newest first. Transfers belonging to a swap
+/// made through the API are folded into the swap entry.
+pub fn user_history(user_id: &str) -> Result<Vec<HistoryEntry>, (StatusCode, String)> {
+    let profile = ProfileDatabase::new()
+        .and_then(|db| db.get(user_id))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+    let mut addresses: HashSet<String> = WalletDatabase::new()
+        .and_then(|db| db.list_for_user(user_id, true))
+        .map_err(db_error)?
+        .into_iter()
+        .map(|w| w.address.to_lowercase())
+        .collect();
+    if !profile.wallet.is_empty() {
+        addresses.insert(profile.wallet.to_lowercase());
+    }
+    let watch_only: HashSet<String> = ExternalWalletDatabase::new()
+        .and_then(|db| db.list_for_user(user_id))
+        .map_err(db_error)?
+        .into_iter()
+        .map(|w| w.address.to_lowercase())
+        .collect();
+    addresses.extend(watch_only.iter().cloned());
+
+    let swaps_db = SwapHistoryDatabase::new().map_err(db_error)?;
+    let total_swaps = swaps_db.count_for_user(user_id).map_err(db_error)?;
+    let swaps = swaps_db
+        .list_for_user(user_id, total_swaps, 0)
+        .map_err(db_error)?;
+
+    let mut entries = Vec::new();
+    let mut swap_txs = HashSet::new();
+    for swap in swaps.into_iter().filter(|s| s.swap_id.is_some()) {
+        if let Some(hash) = &swap.tx_hash {
+            swap_txs.insert(hash.to_lowercase());
+        }
+        entries.push(HistoryEntry {
+            kind: "swap".to_string(),
+            source: "api".to_string(),
+            chain: DEFAULT_CHAIN.to_string(),
+            token: swap.from_token,
+            amount: swap.amount_in,
+            to_token: Some(swap.to_token),
+            amount_out: Some(swap.amount_out),
+            counterparty: None,
+            tx_hash: swap.tx_hash,
+            block_number: None,
+            status: Some(swap.status),
+            watch_only: false,
+            timestamp: swap.created_at,
+        });
+    }
+
+    let indexer = IndexerDatabase::new().map_err(db_error)?;
+    let mut seen = HashSet::new();
+    for address in &addresses {
+        let transfers = indexer.transfers_for(address).map_err(db_error)?;
+        for transfer in transfers {
+            if swap_txs.contains(&transfer.tx_hash.to_lowercase()) || !seen.insert(transfer.id) {
+                continue;
+            }
+            let incoming = addresses.contains(&transfer.to_address);
+            let outgoing = addresses.contains(&transfer.from_address);
+            let kind = match (incoming, outgoing) {
+                (true, true) => "internal",
+                (true, false) => "deposit",
+                _ => "withdrawal",
+            };
+            let own_side = if incoming {
+                &transfer.to_address
+            } else {
+                &transfer.from_address
+            };
+            let watch_only = watch_only.contains(own_side);
+            entries.push(HistoryEntry {
+                kind: kind.to_string(),
+                source: "chain".to_string(),
+                chain: transfer.chain,
+                token: transfer.token,
+                amount: transfer.amount,
+                to_token: None,
+                amount_out: None,
+                counterparty: Some(if incoming {
+                    transfer.from_address
+                } else {
+                    transfer.to_address
+                }),
+                tx_hash: Some(transfer.tx_hash),
+                block_number: Some(transfer.block_number),
+                status: None,
+                watch_only,
+                timestamp: transfer.timestamp,
+            });
+        }
+    }
+
+    entries.sort_by_key(|e| std::cmp::Reverse(e.timestamp));
+    Ok(entries)
+}
+
+pub async fn get_history(
+    Path(user_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> Result<Json<HistoryPage>, (StatusCode, String)> {
+    let limit = query
+        .limit
+        .unwrap_or(HISTORY_DEFAULT_LIMIT)
+        .clamp(1, HISTORY_MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let entries = user_history(&user_id)?;
+    let total = entries.len() as i64;
+
+    Ok(Json(HistoryPage {
+        entries: entries
+            .into_iter()
+            .skip(offset as usize)
+            .take(limit as usize)
+            .collect(),
+        total,
+        limit,
+        offset,
+    }))
+}
@@ -0,0 +1,734 @@
+use crate::constants::{
+    DB_PATH, DISCORD_OAUTH_AUTHORIZE_URL, DISCORD_OAUTH_TOKEN_URL, DISCORD_USERINFO_URL,
+    GITHUB_OAUTH_AUTHORIZE_URL, GITHUB_OAUTH_TOKEN_URL, GITHUB_USERINFO_URL,
+    GOOGLE_OAUTH_AUTHORIZE_URL, GOOGLE_OAUTH_TOKEN_URL, GOOGLE_USERINFO_URL, IDENTITY_USER_AGENT,
+    TWITTER_OAUTH_AUTHORIZE_URL, TWITTER_OAUTH_TOKEN_URL, TWITTER_USERINFO_URL,
+};
+use crate::models::AppState;
+use crate::profiles::ProfileDatabase;
+use crate::reauth;
+use crate::utils;
+use async_trait::async_trait;
+use axum::{
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use ethers::types::Address;
+use oauth2::{basic::BasicClient, AuthUrl, ClientId, ClientSecret, RedirectUrl, TokenUrl};
+use reqwest::Client;
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::env;
+use std::sync::Arc;
+
+pub const PROVIDER_TWITTER: &str = "twitter";
+pub const PROVIDER_GITHUB: &str = "github";
+pub const PROVIDER_GOOGLE: &str = "google";
+pub const PROVIDER_DISCORD: &str = "discord";
+
+/// Who the user is at an identity provider.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Identity {
+    pub provider: String,
+    /// The provider's stable account id; usernames can change.
+    pub subject: String,
+    pub username: String,
+    pub name: String,
+}
+
+/// OAuth endpoints and credentials of one provider. Credentials come from
+/// `<PROVIDER>_CLIENT_ID`, `<PROVIDER>_CLIENT_SECRET` and
+/// `<PROVIDER>_REDIRECT_URL`; endpoints default to the provider's public ones
+/// and can be pointed elsewhere with `<PROVIDER>_OAUTH_AUTHORIZE_URL`,
+/// `<PROVIDER>_OAUTH_TOKEN_URL` and `<PROVIDER>_USERINFO_URL`.
+#[derive(Debug, Clone)]
+pub struct OAuthSettings {
+    pub client_id: String,
+    pub client_secret: String,
+    pub redirect_url: String,
+    pub authorize_url: String,
+    pub token_url: String,
+    pub userinfo_url: String,
+}
+
+impl OAuthSettings {
+    /// `None` when the provider has no client id configured.
+    pub fn from_env(
+        provider: &str,
+        authorize_url: &str,
+        token_url: &str,
+        userinfo_url: &str,
+    ) -> Option<Self> {
+        let prefix = provider.to_uppercase();
+        let var = |name: &str| env::var(format!("{}_{}", prefix, name)).unwrap_or_default();
+        let or_default = |name: &str, default: &str| {
+            Some(var(name))
+                .filter(|v| !v.is_empty())
+                .unwrap_or_else(|| default.to_string())
+        };
+
+        let client_id = var("CLIENT_ID");
+        if client_id.is_empty() {
+            return None;
+        }
+        Some(Self {
+            client_id,
+            client_secret: var("CLIENT_SECRET"),
+            redirect_url: var("REDIRECT_URL"),
+            authorize_url: or_default("OAUTH_AUTHORIZE_URL", authorize_url),
+            token_url: or_default("OAUTH_TOKEN_URL", token_url),
+            userinfo_url: or_default("USERINFO_URL", userinfo_url),
+        })
+    }
+}
+
+/// An OAuth 2 provider users can sign in with.
+#[async_trait]
+pub trait IdentityProvider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    fn settings(&self) -> &OAuthSettings;
+
+    fn scopes(&self) -> Vec<String>;
+
+    /// Reads the identity out of the provider's user info response.
+    fn identity_from(&self, body: &Value) -> std::result::Result<Identity, String>;
+
+    fn oauth_client(&self) -> std::result::Result<BasicClient, String> {
+        let settings = self.settings();
+        let auth_url = AuthUrl::new(settings.authorize_url.clone()).map_err(|e| e.to_string())?;
+        let token_url = TokenUrl::new(settings.token_url.clone()).map_err(|e| e.to_string())?;
+        let redirect_url = RedirectUrl::new(settings.redirect_url.clone()).map_err(|_| {
+            format!(
+                "Missing or invalid {}_REDIRECT_URL environment variable",
+                self.name().to_uppercase()
+            )
+        })?;
+
+        Ok(BasicClient::new(
+            ClientId::new(settings.client_id.clone()),
+            Some(ClientSecret::new(settings.client_secret.clone())),
+            auth_url,
+            Some(token_url),
+        )
+        .set_redirect_uri(redirect_url))
+    }
+
+    async fn fetch_identity(&self, access_token: &str) -> std::result::Result<Identity, String> {
+        let response = Client::new()
+            .get(&self.settings().userinfo_url)
+            .bearer_auth(access_token)
+            .header(reqwest::header::USER_AGENT, IDENTITY_USER_AGENT)
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!(
+                "{} user info returned {}",
+                self.name(),
+                response.status()
+            ));
+        }
+
+        let body: Value = response.json().await.map_err(|e| e.to_string())?;
+        self.identity_from(&body)
+    }
+}
+
+/// String field of a user info response; ids are numbers at some providers.
+fn field(body: &Value, key: &str) -> Option<String> {
+    match &body[key] {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+fn required(body: &Value, key: &str, provider: &str) -> std::result::Result<String, String> {
+    field(body, key).ok_or_else(|| format!("{} user info has no {}", provider, key))
+}
+
+pub struct TwitterProvider {
+    settings: OAuthSettings,
+}
+
+#[async_trait]
+impl IdentityProvider for TwitterProvider {
+    fn name(&self) -> &'static str {
+        PROVIDER_TWITTER
+    }
+
+    fn settings(&self) -> &OAuthSettings {
+        &self.settings
+    }
+
+    fn scopes(&self) -> Vec<String> {
+        vec!["tweet.read".to_string(), "users.read".to_string()]
+    }
+
+    fn identity_from(&self, body: &Value) -> std::result::Result<Identity, String> {
+        let data = &body["data"];
+        let username = required(data, "username", self.name())?;
+        Ok(Identity {
+            provider: self.name().to_string(),
+            subject: required(data, "id", self.name())?,
+            name: field(data, "name").unwrap_or_else(|| username.clone()),
+            username,
+        })
+    }
+}
+
+pub struct GithubProvider {
+    settings: OAuthSettings,
+}
+
+#[async_trait]
+impl IdentityProvider for GithubProvider {
+    fn name(&self) -> &'static str {
+        PROVIDER_GITHUB
+    }
+
+    fn settings(&self) -> &OAuthSettings {
+        &self.settings
+    }
+
+    fn scopes(&self) -> Vec<String> {
+        vec!["read:user".to_string()]
+    }
+
+    fn identity_from(&self, body: &Value) -> std::result::Result<Identity, String> {
+        let username = required(body, "login", self.name())?;
+        Ok(Identity {
+            provider: self.name().to_string(),
+            subject: required(body, "id", self.name())?,
+            name: field(body, "name").unwrap_or_else(|| username.clone()),
+            username,
+        })
+    }
+}
+
+/// Google through its OpenID Connect user info endpoint; the username is
+/// the account's email address.
+pub struct GoogleProvider {
+    settings: OAuthSettings,
+}
+
+#[async_trait]
+impl IdentityProvider for GoogleProvider {
+    fn name(&self) -> &'static str {
+        PROVIDER_GOOGLE
+    }
+
+    fn settings(&self) -> &OAuthSettings {
+        &self.settings
+    }
+
+    fn scopes(&self) -> Vec<String> {
+        vec![
+            "openid".to_string(),
+            "email".to_string(),
+            "profile".to_string(),
+        ]
+    }
+
+    fn identity_from(&self, body: &Value) -> std::result::Result<Identity, String> {
+        let username = required(body, "email", self.name())?;
+        Ok(Identity {
+            provider: self.name().to_string(),
+            subject: required(body, "sub", self.name())?,
+            name: field(body, "name").unwrap_or_else(|| username.clone()),
+            username,
+        })
+    }
+}
+
+pub struct DiscordProvider {
+    settings: OAuthSettings,
+}
+
+#[async_trait]
+impl IdentityProvider for DiscordProvider {
+    fn name(&self) -> &'static str {
+        PROVIDER_DISCORD
+    }
+
+    fn settings(&self) -> &OAuthSettings {
+        &self.settings
+    }
+
+    fn scopes(&self) -> Vec<String> {
+        vec!["identify".to_string()]
+    }
+
+    fn identity_from(&self, body: &Value) -> std::result::Result<Identity, String> {
+        let username = required(body, "username", self.name())?;
+        Ok(Identity {
+            provider: self.name().to_string(),
+            subject: required(body, "id", self.name())?,
+            name: field(body, "global_name").unwrap_or_else(|| username.clone()),
+            username,
+        })
+    }
+}
+
+/// The providers with credentials configured, by name.
+#[derive(Clone, Default)]
+pub struct IdentityProviders {
+    providers: BTreeMap<&'static str, Arc<dyn IdentityProvider>>,
+}
+
+impl IdentityProviders {
+    pub fn from_env() -> Self {
+        let mut registry = Self::default();
+        if let Some(settings) = OAuthSettings::from_env(
+            PROVIDER_TWITTER,
+            TWITTER_OAUTH_AUTHORIZE_URL,
+            TWITTER_OAUTH_TOKEN_URL,
+            TWITTER_USERINFO_URL,
+        ) {
+            registry.register(Arc::new(TwitterProvider { settings }));
+        }
+        if let Some(settings) = OAuthSettings::from_env(
+            PROVIDER_GITHUB,
+            GITHUB_OAUTH_AUTHORIZE_URL,
+            GITHUB_OAUTH_TOKEN_URL,
+            GITHUB_USERINFO_URL,
+        ) {
+            registry.register(Arc::new(GithubProvider { settings }));
+        }
+        if let Some(settings) = OAuthSettings::from_env(
+            PROVIDER_GOOGLE,
+            GOOGLE_OAUTH_AUTHORIZE_URL,
+            GOOGLE_OAUTH_TOKEN_URL,
+            GOOGLE_USERINFO_URL,
+        ) {
+            registry.register(Arc::new(GoogleProvider { settings }));
+        }
+        if let Some(settings) = OAuthSettings::from_env(
+            PROVIDER_DISCORD,
+            DISCORD_OAUTH_AUTHORIZE_URL,
+            DISCORD_OAUTH_TOKEN_URL,
+            DISCORD_USERINFO_URL,
+        ) {
+            registry.register(Arc::new(DiscordProvider { settings }));
+        }
+        registry
+    }
+
+    pub fn register(&mut self, provider: Arc<dyn IdentityProvider>) {
+        self.providers.insert(provider.name(), provider);
+    }
+
+    pub fn get(&self, name: &str) -> Option<Arc<dyn IdentityProvider>> {
+        self.providers.get(name).cloned()
+    }
+
+    pub fn names(&self) -> Vec<&'static str> {
+        self.providers.keys().copied().collect()
+    }
+}
+
+/// A provider account attached to a profile.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct LinkedIdentity {
+    pub id: Option<i64>,
+    pub user_id: String,
+    pub provider: String,
+    pub subject: String,
+    pub username: String,
+    pub name: String,
+    pub linked_at: i64,
+}
+
+pub struct LinkedIdentityDatabase {
+    pub conn: Connection,
+}
+
+impl LinkedIdentityDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS linked_identities (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                provider TEXT NOT NULL,
+                subject TEXT NOT NULL,
+                username TEXT NOT NULL,
+                name TEXT NOT NULL,
+                linked_at INTEGER NOT NULL,
+                UNIQUE(provider, subject),
+                UNIQUE(user_id, provider)
+            )",
+            [],
+        )?;
+
+        Ok(LinkedIdentityDatabase { conn })
+    }
+
+    pub fn link(&self, user_id: &str, identity: &Identity) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO linked_identities (user_id, provider, subject, username, name, linked_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                user_id,
+                identity.provider,
+                identity.subject,
+                identity.username,
+                identity.name,
+                utils::now()
+            ],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    /// Keeps the stored username and name in step with the provider.
+    pub fn refresh(&self, identity: &Identity) -> Result<()> {
+        self.conn.execute(
+            "UPDATE linked_identities SET username = ?1, name = ?2
+             WHERE provider = ?3 AND subject = ?4",
+            params![
+                identity.username,
+                identity.name,
+                identity.provider,
+                identity.subject
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn find(&self, provider: &str, subject: &str) -> Result<Option<LinkedIdentity>> {
+        self.conn
+            .query_row(
+                "SELECT id, user_id, provider, subject, username, name, linked_at
+                 FROM linked_identities WHERE provider = ?1 AND subject = ?2",
+                params![provider, subject],
+                identity_from_row,
+            )
+            .optional()
+    }
+
+    pub fn list_for_user(&self, user_id: &str) -> Result<Vec<LinkedIdentity>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, user_id, provider, subject, username, name, linked_at
+             FROM linked_identities WHERE user_id = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![user_id], identity_from_row)?;
+        rows.collect()
+    }
+
+    pub fn unlink(&self, user_id: &str, provider: &str) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM linked_identities WHERE user_id = ?1 AND provider = ?2",
+            params![user_id, provider],
+        )?;
+        Ok(deleted > 0)
+    }
+}
+
+fn identity_from_row(row: &Row) -> Result<LinkedIdentity> {
+    Ok(LinkedIdentity {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        provider: row.get(2)?,
+        subject: row.get(3)?,
+        username: row.get(4)?,
+        name: row.get(5)?,
+        linked_at: row.get(6)?,
+    })
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Identity database error: {}", e),
+    )
+}
+
+/// Names of the providers users can sign in with.
+pub async fn list_providers(State(state): State<AppState>) -> Json<Vec<&'static str>> {
+    Json(state.identities.names())
+}
+
+pub async fn list_identities(
+    Path(user_id): Path<String>,
+) -> std::result::Result<Json<Vec<LinkedIdentity>>, (StatusCode, String)> {
+    let identities = LinkedIdentityDatabase::new()
+        .and_then(|db| db.list_for_user(&user_id))
+        .map_err(db_error)?;
+    Ok(Json(identities))
+}
+
+/// Detaches a provider from the profile. Requires a re-authentication token
+/// for `link`, and keeps at least one way to sign in: the last provider of a
+/// profile without a Sign-In with Ethereum address cannot be removed.
+pub async fn unlink_identity(
+    headers: HeaderMap,
+    Path((user_id, provider)): Path<(String, String)>,
+) -> std::result::Result<StatusCode, (StatusCode, String)> {
+    ProfileDatabase::new()
+        .and_then(|db| db.get(&user_id))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+    let db = LinkedIdentityDatabase::new().map_err(db_error)?;
+    let identities = db.list_for_user(&user_id).map_err(db_error)?;
+    if !identities.iter().any(|i| i.provider == provider) {
+        return Err((StatusCode::NOT_FOUND, "Identity not found".to_string()));
+    }
+    if identities.len() == 1 && user_id.parse::<Address>().is_err() {
+        return Err((
+            StatusCode::CONFLICT,
+            "The last sign-in provider cannot be removed".to_string(),
+        ));
+    }
+    reauth::require_reauth(&headers, &user_id, reauth::PURPOSE_LINK)?;
+
+    db.unlink(&user_id, &provider).map_err(db_error)?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::auth;
+    use crate::utils::use_temp_db;
+    use axum::{extract::Form, routing::get, routing::post, Router};
+    use oauth2::{AuthorizationCode, PkceCodeChallenge, TokenResponse};
+    use serde_json::json;
+    use std::collections::HashMap;
+
+    async fn token(Form(form): Form<HashMap<String, String>>) -> (StatusCode, Json<Value>) {
+        let valid = form.get("grant_type").map(String::as_str) == Some("authorization_code")
+            && form.get("code").map(String::as_str) == Some("good-code")
+            && form.contains_key("code_verifier");
+        if !valid {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(json!({ "error": "invalid_grant" })),
+            );
+        }
+        (
+            StatusCode::OK,
+            Json(json!({ "access_token": "mock-access-token", "token_type": "bearer" })),
+        )
+    }
+
+    async fn userinfo(
+        headers: HeaderMap,
+        Path(provider): Path<String>,
+    ) -> (StatusCode, Json<Value>) {
+        let header = |name| headers.get(name).and_then(|v| v.to_str().ok());
+        if header("authorization") != Some("Bearer mock-access-token")
+            || header("user-agent") != Some(IDENTITY_USER_AGENT)
+        {
+            return (StatusCode::UNAUTHORIZED, Json(json!({})));
+        }
+        let body = match provider.as_str() {
+            PROVIDER_TWITTER => {
+                json!({ "data": { "id": "1001", "username": "alice", "name": "Alice" } })
+            }
+            PROVIDER_GITHUB => json!({ "id": 2002, "login": "alice-gh", "name": null }),
+            PROVIDER_GOOGLE => {
+                json!({ "sub": "3003", "email": "alice@example.com", "name": "Alice G" })
+            }
+            PROVIDER_DISCORD => {
+                json!({ "id": "4004", "username": "alice_d", "global_name": "Alice D" })
+            }
+            _ => return (StatusCode::NOT_FOUND, Json(json!({}))),
+        };
+        (StatusCode::OK, Json(body))
+    }
+
+    /// Serves a token endpoint at `/token` and each provider's user info at
+    /// `/<provider>/user`, returning the base URL.
+    fn start_mock() -> String {
+        let app = Router::new()
+            .route("/token", post(token))
+            .route("/:provider/user", get(userinfo));
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let server = axum::Server::from_tcp(listener)
+            .unwrap()
+            .serve(app.into_make_service());
+        tokio::spawn(server);
+        base
+    }
+
+    fn identity(provider: &str, subject: &str, username: &str) -> Identity {
+        Identity {
+            provider: provider.to_string(),
+            subject: subject.to_string(),
+            username: username.to_string(),
+            name: username.to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn exchanges_code_and_fetches_identity_from_each_provider() {
+        let base = start_mock();
+        for provider in [
+            PROVIDER_TWITTER,
+            PROVIDER_GITHUB,
+            PROVIDER_GOOGLE,
+            PROVIDER_DISCORD,
+        ] {
+            let prefix = provider.to_uppercase();
+            env::set_var(format!("{}_CLIENT_ID", prefix), "client");
+            env::set_var(format!("{}_CLIENT_SECRET", prefix), "secret");
+            env::set_var(
+                format!("{}_REDIRECT_URL", prefix),
+                "http://localhost/callback",
+            );
+            env::set_var(
+                format!("{}_OAUTH_TOKEN_URL", prefix),
+                format!("{}/token", base),
+            );
+            env::set_var(
+                format!("{}_USERINFO_URL", prefix),
+                format!("{}/{}/user", base, provider),
+            );
+        }
+        let registry = IdentityProviders::from_env();
+        assert_eq!(
+            registry.names(),
+            vec![
+                PROVIDER_DISCORD,
+                PROVIDER_GITHUB,
+                PROVIDER_GOOGLE,
+                PROVIDER_TWITTER
+            ]
+        );
+
+        let expected = [
+            (PROVIDER_TWITTER, "1001", "alice", "Alice"),
+            (PROVIDER_GITHUB, "2002", "alice-gh", "alice-gh"),
+            (PROVIDER_GOOGLE, "3003", "alice@example.com", "Alice G"),
+            (PROVIDER_DISCORD, "4004", "alice_d", "Alice D"),
+        ];
+        for (name, subject, username, display) in expected {
+            let provider = registry.get(name).unwrap();
+            let client = provider.oauth_client().unwrap();
+
+            let (_, verifier) = PkceCodeChallenge::new_random_sha256();
+            let rejected = client
+                .exchange_code(AuthorizationCode::new("bad-code".to_string()))
+                .set_pkce_verifier(verifier)
+                .request_async(oauth2::reqwest::async_http_client)
+                .await;
+            assert!(rejected.is_err(), "{} accepted a bad code", name);
+
+            let (_, verifier) = PkceCodeChallenge::new_random_sha256();
+            let response = client
+                .exchange_code(AuthorizationCode::new("good-code".to_string()))
+                .set_pkce_verifier(verifier)
+                .request_async(oauth2::reqwest::async_http_client)
+                .await
+                .unwrap();
+            let access_token = response.access_token().secret();
+            assert_eq!(access_token, "mock-access-token");
+
+            let identity = provider.fetch_identity(access_token).await.unwrap();
+            assert_eq!(identity.provider, name);
+            assert_eq!(identity.subject, subject);
+            assert_eq!(identity.username, username);
+            assert_eq!(identity.name, display);
+            assert!(provider.fetch_identity("wrong-token").await.is_err());
+        }
+    }
+
+    #[test]
+    fn identity_from_requires_subject_and_username() {
+        let settings = OAuthSettings {
+            client_id: "client".to_string(),
+            client_secret: String::new(),
+            redirect_url: String::new(),
+            authorize_url: String::new(),
+            token_url: String::new(),
+            userinfo_url: String::new(),
+        };
+        let twitter = TwitterProvider {
+            settings: settings.clone(),
+        };
+        let github = GithubProvider {
+            settings: settings.clone(),
+        };
+        let google = GoogleProvider {
+            settings: settings.clone(),
+        };
+        let discord = DiscordProvider { settings };
+
+        assert!(twitter
+            .identity_from(&json!({ "id": "1", "username": "alice" }))
+            .is_err());
+        assert!(github.identity_from(&json!({ "login": "alice" })).is_err());
+        assert!(google.identity_from(&json!({ "sub": "3" })).is_err());
+        assert!(discord
+            .identity_from(&json!({ "id": "4", "username": "" }))
+            .is_err());
+
+        let identity = discord
+            .identity_from(&json!({ "id": 4, "username": "alice_d", "global_name": null }))
+            .unwrap();
+        assert_eq!(identity.subject, "4");
+        assert_eq!(identity.name, "alice_d");
+    }
+
+    #[test]
+    fn sign_in_links_identities_and_rejects_conflicts() {
+        use_temp_db();
+        let github = identity(PROVIDER_GITHUB, "gh-1", "linker");
+        let discord = identity(PROVIDER_DISCORD, "dc-1", "linker");
+
+        auth::sign_in("linker", &github, false).unwrap();
+        // Signing in again with the same account is not a conflict.
+        auth::sign_in("linker", &github, false).unwrap();
+
+        let (status, _) = auth::sign_in("someone-else", &github, false).unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = auth::sign_in("linker", &discord, false).unwrap_err();
+        assert_eq!(status, StatusCode::FORBIDDEN);
+
+        auth::sign_in("linker", &discord, true).unwrap();
+        let db = LinkedIdentityDatabase::new().unwrap();
+        let providers: Vec<String> = db
+            .list_for_user("linker")
+            .unwrap()
+            .into_iter()
+            .map(|i| i.provider)
+            .collect();
+        assert_eq!(providers, vec![PROVIDER_GITHUB, PROVIDER_DISCORD]);
+
+        // A second account at a provider the profile already has is refused.
+        let other = identity(PROVIDER_GITHUB, "gh-2", "linker-alt");
+        let (status, _) = auth::sign_in("linker", &other, true).unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+    }
+
+    #[tokio::test]
+    async fn unlink_identity_keeps_the_last_provider() {
+        use_temp_db();
+        let github = identity(PROVIDER_GITHUB, "gh-last", "last-one");
+        auth::sign_in("last-one", &github, false).unwrap();
+
+        let unlink = |provider: &str| {
+            unlink_identity(
+                HeaderMap::new(),
+                Path(("last-one".to_string(), provider.to_string())),
+            )
+        };
+        let (status, _) = unlink(PROVIDER_GITHUB).await.unwrap_err();
+        assert_eq!(status, StatusCode::CONFLICT);
+        let (status, _) = unlink(PROVIDER_GOOGLE).await.unwrap_err();
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let google = identity(PROVIDER_GOOGLE, "go-last", "last-one@example.com");
+        auth::sign_in("last-one", &google, true).unwrap();
+        // With another provider left, only the re-authentication is missing.
+        let (status, _) = unlink(PROVIDER_GITHUB).await.unwrap_err();
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+}
@@ -0,0 +1,643 @@
+#![allow(dead_code)]
+
+use crate::chain;
+use crate::constants::{
+    DB_PATH, DEFAULT_CHAIN, INDEXER_BACKFILL_BLOCKS, INDEXER_BATCH_BLOCKS,
+    INDEXER_POLL_INTERVAL_SECS, INDEXER_REORG_DEPTH, NATIVE_TOKEN_ADDRESS,
+};
+use crate::deposits;
+use crate::external_wallets::ExternalWalletDatabase;
+use crate::wallets::WalletDatabase;
+use ethers::{
+    prelude::*,
+    providers::{Http, Provider},
+    types::{Address, Filter, ValueOrArray, H256, U256},
+    utils::keccak256,
+};
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::time::Duration;
+
+/// Log index used for native value transfers, which have no log.
+pub const NATIVE_LOG_INDEX: i64 = -1;
+
+pub const TRANSFER_COLUMNS: &str =
+    "id, chain, block_number, block_hash, tx_hash, log_index, token, \
+     from_address, to_address, amount, timestamp";
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct IndexedTransfer {
+    pub id: Option<i64>,
+    pub chain: String,
+    pub block_number: i64,
+    pub block_hash: String,
+    pub tx_hash: String,
+    pub log_index: i64,
+    /// Token contract, or the native token placeholder.
+    pub token: String,
+    pub from_address: String,
+    pub to_address: String,
+    /// Base units as a decimal string.
+    pub amount: String,
+    pub timestamp: i64,
+}
+
+#[derive(Debug, Default)]
+pub struct IndexReport {
+    pub from_block: u64,
+    pub to_block: u64,
+    pub transfers: usize,
+    /// First block removed by a reorg rollback, if one happened.
+    pub rolled_back_from: Option<u64>,
+}
+
+/// Where an address's history on a chain starts, and how much of it before
+/// the live scan picked the address up is still to be backfilled.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct AddressCursor {
+    pub chain: String,
+    pub address: String,
+    pub start_block: u64,
+    /// Next block to backfill.
+    pub next_block: u64,
+    /// Last block scanned before the address joined the live scan.
+    pub until_block: u64,
+}
+
+/// Blocks whose hashes are kept on `chain`: at least as many as deposits
+/// there need confirmations, so a reorg of a deposit still counting towards
+/// them is detected.
+fn reorg_depth(chain: &str) -> u64 {
+    INDEXER_REORG_DEPTH.max(deposits::required_confirmations(chain))
+}
+
+pub struct IndexerDatabase {
+    pub conn: Connection,
+}
+
+impl IndexerDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS indexed_blocks (
+                chain TEXT NOT NULL,
+                number INTEGER NOT NULL,
+                hash TEXT NOT NULL,
+                PRIMARY KEY (chain, number)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS indexed_transfers (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                chain TEXT NOT NULL,
+                block_number INTEGER NOT NULL,
+                block_hash TEXT NOT NULL,
+                tx_hash TEXT NOT NULL,
+                log_index INTEGER NOT NULL,
+                token TEXT NOT NULL,
+                from_address TEXT NOT NULL,
+                to_address TEXT NOT NULL,
+                amount TEXT NOT NULL,
+                timestamp INTEGER NOT NULL,
+                UNIQUE (chain, tx_hash, log_index)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS indexed_addresses (
+                chain TEXT NOT NULL,
+                address TEXT NOT NULL,
+                start_block INTEGER NOT NULL,
+                next_block INTEGER NOT NULL,
+                until_block INTEGER NOT NULL,
+                PRIMARY KEY (chain, address)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS indexed_transfers_from ON indexed_transfers (from_address)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS indexed_transfers_to ON indexed_transfers (to_address)",
+            [],
+        )?;
+
+        Ok(IndexerDatabase { conn })
+    }
+
+    /// Stored block hashes, newest first.
+    pub fn recent_blocks(&self, chain: &str) -> Result<Vec<(u64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT number, hash FROM indexed_blocks WHERE chain = ?1 ORDER BY number DESC",
+        )?;
+        let rows = stmt.query_map(params![chain], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    /// Addresses already known on `chain`.
+    pub fn known_addresses(&self, chain: &str) -> Result<HashSet<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT address FROM indexed_addresses WHERE chain = ?1")?;
+        let rows = stmt.query_map(params![chain], |row| row.get(0))?;
+        rows.collect()
+    }
+
+    /// Records the cursor of an address seen for the first time; existing
+    /// cursors are kept.
+    pub fn add_address(&self, cursor: &AddressCursor) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO indexed_addresses
+                (chain, address, start_block, next_block, until_block)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                cursor.chain,
+                cursor.address,
+                cursor.start_block as i64,
+                cursor.next_block as i64,
+                cursor.until_block as i64
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Cursors on `chain` with history left to backfill, oldest first.
+    pub fn pending_backfills(&self, chain: &str) -> Result<Vec<AddressCursor>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT chain, address, start_block, next_block, until_block FROM indexed_addresses
+             WHERE chain = ?1 AND next_block <= until_block
+             ORDER BY next_block, until_block, address",
+        )?;
+        let rows = stmt.query_map(params![chain], |row| {
+            Ok(AddressCursor {
+                chain: row.get(0)?,
+                address: row.get(1)?,
+                start_block: row.get::<_, i64>(2)? as u64,
+                next_block: row.get::<_, i64>(3)? as u64,
+                until_block: row.get::<_, i64>(4)? as u64,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Stores a backfilled range and moves the addresses' cursors past it.
+    pub fn commit_backfill(
+        &self,
+        chain: &str,
+        addresses: &[String],
+        to_block: u64,
+        transfers: &[IndexedTransfer],
+    ) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+        let inserted = insert_transfers(&tx, chain, transfers)?;
+        for address in addresses {
+            tx.execute(
+                "UPDATE indexed_addresses SET next_block = ?1 WHERE chain = ?2 AND address = ?3",
+                params![(to_block + 1) as i64, chain, address],
+            )?;
+        }
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// Stores a scanned range: its transfers and the hashes of its blocks,
+    /// dropping hashes older than the reorg window.
+    pub fn commit_range(
+        &self,
+        chain: &str,
+        blocks: &[(u64, String)],
+        transfers: &[IndexedTransfer],
+    ) -> Result<usize> {
+        let tx = self.conn.unchecked_transaction()?;
+
+        let inserted = insert_transfers(&tx, chain, transfers)?;
+        for (number, hash) in blocks {
+            tx.execute(
+                "INSERT OR REPLACE INTO indexed_blocks (chain, number, hash) VALUES (?1, ?2, ?3)",
+                params![chain, number, hash],
+            )?;
+        }
+        if let Some((tip, _)) = blocks.last() {
+            tx.execute(
+                "DELETE FROM indexed_blocks WHERE chain = ?1 AND number < ?2",
+                params![chain, tip.saturating_sub(reorg_depth(chain)) as i64],
+            )?;
+        }
+
+        tx.commit()?;
+        Ok(inserted)
+    }
+
+    /// Forgets everything from `from_block` up so it is scanned again.
+    pub fn rollback(&self, chain: &str, from_block: u64) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "DELETE FROM indexed_transfers WHERE chain = ?1 AND block_number >= ?2",
+            params![chain, from_block as i64],
+        )?;
+        tx.execute(
+            "DELETE FROM indexed_blocks WHERE chain = ?1 AND number >= ?2",
+            params![chain, from_block as i64],
+        )?;
+        tx.commit()
+    }
+
+    /// Transfers sent or received by `address`, newest first.
+    pub fn transfers_for(&self, address: &str) -> Result<Vec<IndexedTransfer>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM indexed_transfers WHERE from_address = ?1 OR to_address = ?1
+             ORDER BY block_number DESC, log_index DESC",
+            TRANSFER_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![address.to_lowercase()], transfer_from_row)?;
+        rows.collect()
+    }
+
+    pub fn transfers_between(
+        &self,
+        chain: &str,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<IndexedTransfer>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM indexed_transfers WHERE chain = ?1 AND block_number BETWEEN ?2 AND ?3
+             ORDER BY block_number, log_index",
+            TRANSFER_COLUMNS
+        ))?;
+        let rows = stmt.query_map(
+            params![chain, from_block as i64, to_block as i64],
+            transfer_from_row,
+        )?;
+        rows.collect()
+    }
+}
+
+fn insert_transfers(
+    conn: &Connection,
+    chain: &str,
+    transfers: &[IndexedTransfer],
+) -> Result<usize> {
+    let mut inserted = 0;
+    for transfer in transfers {
+        inserted += conn.execute(
+            "INSERT OR IGNORE INTO indexed_transfers (chain, block_number, block_hash, tx_hash,
+                log_index, token, from_address, to_address, amount, timestamp)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                chain,
+                transfer.block_number,
+                transfer.block_hash,
+                transfer.tx_hash,
+                transfer.log_index,
+                transfer.token,
+                transfer.from_address,
+                transfer.to_address,
+                transfer.amount,
+                transfer.timestamp
+            ],
+        )?;
+    }
+    Ok(inserted)
+}
+
+pub fn transfer_from_row(row: &Row) -> Result<IndexedTransfer> {
+    Ok(IndexedTransfer {
+        id: row.get(0)?,
+        chain: row.get(1)?,
+        block_number: row.get(2)?,
+        block_hash: row.get(3)?,
+        tx_hash: row.get(4)?,
+        log_index: row.get(5)?,
+        token: row.get(6)?,
+        from_address: row.get(7)?,
+        to_address: row.get(8)?,
+        amount: row.get(9)?,
+        timestamp: row.get(10)?,
+    })
+}
+
+/// Chains to index, from `INDEXER_CHAINS` (comma separated), defaulting to
+/// the default chain.
+pub fn configured_chains() -> Vec<String> {
+    env::var("INDEXER_CHAINS")
+        .ok()
+        .map(|chains| {
+            chains
+                .split(',')
+                .map(|c| c.trim().to_lowercase())
+                .filter(|c| !c.is_empty())
+                .collect::<Vec<_>>()
+        })
+        .filter(|chains| !chains.is_empty())
+        .unwrap_or_else(|| vec![DEFAULT_CHAIN.to_string()])
+}
+
+fn hex(hash: H256) -> String {
+    format!("{:#x}", hash)
+}
+
+fn address_topic(address: Address) -> H256 {
+    H256::from(address)
+}
+
+fn topic_address(topic: &H256) -> Address {
+    Address::from_slice(&topic.as_bytes()[12..])
+}
+
+/// First stored block whose hash the node no longer agrees with, walking
+/// back from the tip until a block matches.
+async fn find_fork(
+    provider: &Provider<Http>,
+    stored: &[(u64, String)],
+) -> std::result::Result<Option<u64>, String> {
+    let mut fork = None;
+
+    for (number, hash) in stored {
+        let block = provider
+            .get_block(*number)
+            .await
+            .map_err(|e| format!("Failed to fetch block {}: {}", number, e))?;
+        match block.and_then(|b| b.hash).map(hex) {
+            Some(current) if current == *hash => break,
+            _ => fork = Some(*number),
+        }
+    }
+
+    Ok(fork)
+}
+
+/// Transfers touching any of `addresses` in `from_block..=to_block`, with
+/// the hashes of the scanned blocks.
+async fn scan_range(
+    provider: &Provider<Http>,
+    chain: &str,
+    addresses: &HashSet<Address>,
+    from_block: u64,
+    to_block: u64,
+) -> std::result::Result<(Vec<(u64, String)>, Vec<IndexedTransfer>), String> {
+    let mut blocks = Vec::new();
+    let mut timestamps: HashMap<u64, (String, i64)> = HashMap::new();
+    let mut transfers = Vec::new();
+
+    for number in from_block..=to_block {
+        let block = provider
+            .get_block_with_txs(number)
+            .await
+            .map_err(|e| format!("Failed to fetch block {}: {}", number, e))?
+            .ok_or(format!("Block {} not found", number))?;
+        let hash = hex(block.hash.ok_or(format!("Block {} is pending", number))?);
+        let timestamp = block.timestamp.as_u64() as i64;
+
+        for tx in &block.transactions {
+            let touches = addresses.contains(&tx.from)
+                || tx.to.map(|to| addresses.contains(&to)).unwrap_or(false);
+            if tx.value.is_zero() || !touches {
+                continue;
+            }
+            transfers.push(IndexedTransfer {
+                chain: chain.to_string(),
+                block_number: number as i64,
+                block_hash: hash.clone(),
+                tx_hash: hex(tx.hash),
+                log_index: NATIVE_LOG_INDEX,
+                token: NATIVE_TOKEN_ADDRESS.to_string(),
+                from_address: format!("{:#x}", tx.from),
+                to_address: tx.to.map(|to| format!("{:#x}", to)).unwrap_or_default(),
+                amount: tx.value.to_string(),
+                timestamp,
+                ..Default::default()
+            });
+        }
+
+        timestamps.insert(number, (hash.clone(), timestamp));
+        blocks.push((number, hash));
+    }
+
+    if !addresses.is_empty() {
+        let transfer_topic = H256::from(keccak256("Transfer(address,address,uint256)"));
+        let topics: Vec<Option<H256>> = addresses.iter().map(|a| Some(address_topic(*a))).collect();
+        let outgoing = Filter::new()
+            .from_block(from_block)
+            .to_block(to_block)
+            .topic0(transfer_topic)
+            .topic1(ValueOrArray::Array(topics.clone()));
+        let incoming = Filter::new()
+            .from_block(from_block)
+            .to_block(to_block)
+            .topic0(transfer_topic)
+            .topic2(ValueOrArray::Array(topics));
+
+        let mut seen = HashSet::new();
+        for filter in [outgoing, incoming] {
+            let logs = provider
+                .get_logs(&filter)
+                .await
+                .map_err(|e| format!("Failed to fetch logs: {}", e))?;
+            for log in logs {
+                // ERC-721 Transfer shares the signature but indexes the token
+                // id as a third topic.
+                if log.topics.len() != 3 || log.removed == Some(true) {
+                    continue;
+                }
+                let (number, tx_hash, log_index) =
+                    match (log.block_number, log.transaction_hash, log.log_index) {
+                        (Some(n), Some(h), Some(i)) => (n.as_u64(), h, i.as_u64() as i64),
+                        _ => continue,
+                    };
+                let (hash, timestamp) = timestamps
+                    .get(&number)
+                    .ok_or(format!("Log from unscanned block {}", number))?;
+                if log.block_hash.map(hex).as_ref() != Some(hash) {
+                    return Err(format!("Block {} changed during scan", number));
+                }
+                if !seen.insert((tx_hash, log_index)) {
+                    continue;
+                }
+
+                transfers.push(IndexedTransfer {
+                    chain: chain.to_string(),
+                    block_number: number as i64,
+                    block_hash: hash.clone(),
+                    tx_hash: hex(tx_hash),
+                    log_index,
+                    token: format!("{:#x}", log.address),
+                    from_address: format!("{:#x}", topic_address(&log.topics[1])),
+                    to_address: format!("{:#x}", topic_address(&log.topics[2])),
+                    amount: U256::from_big_endian(&log.data).to_string(),
+                    timestamp: *timestamp,
+                    ..Default::default()
+                });
+            }
+        }
+    }
+
+    Ok((blocks, transfers))
+}
+
+/// `INDEXER_START_BLOCK_<CHAIN>`, where indexing on `chain` begins.
+fn configured_start_block(chain: &str) -> Option<u64> {
+    let key = format!(
+        "INDEXER_START_BLOCK_{}",
+        chain.to_uppercase().replace('-', "_")
+    );
+    env::var(key).ok().and_then(|b| b.parse().ok())
+}
+
+/// Records a cursor for each address the live scan has not seen on `chain`
+/// yet. Its history starts at the configured start block, or
+/// `INDEXER_BACKFILL_BLOCKS` before the tip, and is backfilled up to the
+/// block before `from_block`, where the live scan takes over.
+fn register_addresses(
+    db: &IndexerDatabase,
+    chain: &str,
+    addresses: &HashSet<Address>,
+    from_block: u64,
+    latest: u64,
+) -> std::result::Result<(), String> {
+    let known = db.known_addresses(chain).map_err(|e| e.to_string())?;
+    let start_block = configured_start_block(chain)
+        .unwrap_or_else(|| latest.saturating_sub(INDEXER_BACKFILL_BLOCKS));
+    for address in addresses {
+        let address = format!("{:#x}", address);
+        if known.contains(&address) {
+            continue;
+        }
+        db.add_address(&AddressCursor {
+            chain: chain.to_string(),
+            address,
+            start_block,
+            next_block: start_block,
+            // Nothing to backfill when the live scan starts at or before it.
+            until_block: from_block.saturating_sub(1),
+        })
+        .map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+/// Scans the next batch of blocks on `chain` for transfers touching any of
+/// `addresses`.
+pub async fn index_chain(
+    chain: &str,
+    addresses: &HashSet<Address>,
+) -> std::result::Result<IndexReport, String> {
+    let provider = chain::provider_for(chain)?;
+    let db = IndexerDatabase::new().map_err(|e| e.to_string())?;
+    let stored = db.recent_blocks(chain).map_err(|e| e.to_string())?;
+    let mut report = IndexReport {
+        rolled_back_from: find_fork(&provider, &stored).await?,
+        ..Default::default()
+    };
+    if let Some(fork) = report.rolled_back_from {
+        db.rollback(chain, fork).map_err(|e| e.to_string())?;
+        println!("Reorg on {}: rolled back from block {}", chain, fork);
+    }
+
+    let latest = provider
+        .get_block_number()
+        .await
+        .map_err(|e| format!("Failed to fetch block number: {}", e))?
+        .as_u64();
+    let remaining = db.recent_blocks(chain).map_err(|e| e.to_string())?;
+    let from_block = match (remaining.first(), report.rolled_back_from) {
+        (Some((tip, _)), _) => tip + 1,
+        // A reorg deeper than the stored window: rescan from the fork.
+        (None, Some(fork)) => fork,
+        (None, None) => configured_start_block(chain).unwrap_or(latest),
+    };
+    register_addresses(&db, chain, addresses, from_block, latest)?;
+    if from_block > latest {
+        return Ok(report);
+    }
+    let to_block = latest.min(from_block + INDEXER_BATCH_BLOCKS - 1);
+    report.from_block = from_block;
+    report.to_block = to_block;
+
+    let (blocks, transfers) = scan_range(&provider, chain, addresses, from_block, to_block).await?;
+    report.transfers = db
+        .commit_range(chain, &blocks, &transfers)
+        .map_err(|e| e.to_string())?;
+    Ok(report)
+}
+
+/// Scans the next batch of history for the addresses added to `chain`
+/// together longest ago, or returns `None` when nothing is left.
+pub async fn backfill_chain(chain: &str) -> std::result::Result<Option<IndexReport>, String> {
+    let db = IndexerDatabase::new().map_err(|e| e.to_string())?;
+    let pending = db.pending_backfills(chain).map_err(|e| e.to_string())?;
+    let Some(first) = pending.first() else {
+        return Ok(None);
+    };
+    let (from_block, until_block) = (first.next_block, first.until_block);
+    let batch: Vec<String> = pending
+        .iter()
+        .filter(|c| c.next_block == from_block && c.until_block == until_block)
+        .map(|c| c.address.clone())
+        .collect();
+    let addresses: HashSet<Address> = batch.iter().filter_map(|a| a.parse().ok()).collect();
+    let to_block = until_block.min(from_block + INDEXER_BATCH_BLOCKS - 1);
+
+    let provider = chain::provider_for(chain)?;
+    let (_, transfers) = scan_range(&provider, chain, &addresses, from_block, to_block).await?;
+    let transfers = db
+        .commit_backfill(chain, &batch, to_block, &transfers)
+        .map_err(|e| e.to_string())?;
+    Ok(Some(IndexReport {
+        from_block,
+        to_block,
+        transfers,
+        rolled_back_from: None,
+    }))
+}
+
+pub fn managed_addresses() -> Result<HashSet<Address>> {
+    Ok(WalletDatabase::new()?
+        .addresses()?
+        .iter()
+        .filter_map(|a| a.parse::<Address>().ok())
+        .collect())
+}
+
+/// Custody wallets plus linked watch-only wallets.
+pub fn indexed_addresses() -> Result<HashSet<Address>> {
+    let mut addresses = managed_addresses()?;
+    addresses.extend(
+        ExternalWalletDatabase::new()?
+            .addresses()?
+            .iter()
+            .filter_map(|a| a.parse::<Address>().ok()),
+    );
+    Ok(addresses)
+}
+
+pub async fn run_indexer() {
+    let mut interval = tokio::time::interval(Duration::from_secs(INDEXER_POLL_INTERVAL_SECS));
+    let chains = configured_chains();
+
+    loop {
+        interval.tick().await;
+
+        let addresses = match indexed_addresses() {
+            Ok(addresses) => addresses,
+            Err(e) => {
+                println!("Indexer failed to load wallets: {}", e);
+                continue;
+            }
+        };
+
+        for chain in &chains {
+            if let Err(e) = index_chain(chain, &addresses).await {
+                println!("Indexer failed on {}: {}", chain, e);
+                continue;
+            }
+            if let Err(e) = backfill_chain(chain).await {
+                println!("Indexer backfill failed on {}: {}", chain, e);
+            }
+        }
+    }
+}
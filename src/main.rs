@@ -1,50 +1,227 @@
+mod admin;
 mod auth;
+mod chain;
 mod constants;
+mod custody;
 mod defi;
+mod deposits;
+mod events;
+mod external_wallets;
+mod github_activity;
+mod guardrails;
+mod history;
+mod identity;
+mod indexer;
 mod models;
+mod orders;
+mod permits;
+mod portfolio;
+mod prices;
 mod profiles;
+mod projects;
+mod reauth;
+mod sentiment;
+mod siwe;
+mod subscriptions;
 mod swap;
+mod swap_history;
+mod token_metadata;
+mod tvl;
+mod utils;
 mod wallets;
+mod webhooks;
+mod whitepaper;
 
-use auth::{callback, login};
+use auth::{callback, callback_with, login, login_with};
 use axum::{
-    extract::Path,
+    extract::{DefaultBodyLimit, Path, Query, State},
     http::StatusCode,
     response::IntoResponse,
-    routing::{get, post},
+    routing::{delete, get, post, put},
     Json, Router,
 };
 use dotenvy::dotenv;
-use models::{
-    AppState, BalanceResponse, Project, ProjectSummary, TransactionResponse, TransferForm,
-};
+use models::{AppState, BalanceResponse, TransactionResponse, TransferForm};
 use profiles::{Profile, ProfileDatabase};
+use std::env;
 use std::sync::Arc;
-use std::{env, fs};
 use tower_http::cors::CorsLayer;
 
 #[tokio::main]
 async fn main() {
     dotenv().ok();
 
+    if env::args().nth(1).as_deref() == Some("recover-wallets") {
+        std::process::exit(wallets::run_recovery());
+    }
+
+    siwe::expected_domain().unwrap();
+    let magpie = defi::magpiefi::MagpieClient::new(&env::var("MAGPIEFI_API_URL").unwrap());
     let state = AppState {
         oauth: Arc::new(tokio::sync::Mutex::new(None)),
-        magpie: defi::magpiefi::MagpieClient::new(&env::var("MAGPIEFI_API_URL").unwrap()),
+        identities: Arc::new(identity::IdentityProviders::from_env()),
+        prices: Arc::new(prices::PriceService::from_env(magpie.clone())),
+        magpie,
+        swap_policy: guardrails::SwapPolicy::from_env(),
+        events: events::EventBus::new(),
     };
 
+    match projects::import_from_json(constants::PROJECTS_JSON_PATH) {
+        Ok(0) => {}
+        Ok(count) => println!(
+            "Imported {} projects from {}",
+            count,
+            constants::PROJECTS_JSON_PATH
+        ),
+        Err(e) => println!("Project import failed: {}", e),
+    }
+    if let Err(e) = projects::import_summary_from_json(constants::PROJECT_JSON_PATH) {
+        println!("Project summary import failed: {}", e);
+    }
+
+    tokio::spawn(swap_history::run_status_poller(
+        state.magpie.clone(),
+        state.prices.clone(),
+        state.events.clone(),
+    ));
+    tokio::spawn(orders::run_evaluator(state.clone()));
+    tokio::spawn(subscriptions::run_scheduler(state.clone()));
+    tokio::spawn(token_metadata::run_enrichment_job());
+    tokio::spawn(tvl::run_snapshot_job(state.prices.clone()));
+    tokio::spawn(portfolio::run_snapshot_job(state.prices.clone()));
+    tokio::spawn(github_activity::run_collector());
+    tokio::spawn(sentiment::run_ingest_job());
+    tokio::spawn(indexer::run_indexer());
+    tokio::spawn(deposits::run_detector(state.events.clone()));
+    tokio::spawn(events::run_balance_watcher(state.events.clone()));
+    tokio::spawn(webhooks::run_dispatcher());
+
     let app = Router::new()
         .route("/profile/:id", get(get_profile))
         .route("/login/:id", get(login))
+        .route("/login/:id/:provider", get(login_with))
         .route("/callback", get(callback))
-        .route("/projects", get(get_projects))
-        .route("/projects/:chain/:pid", get(get_project))
+        .route("/callback/:provider", get(callback_with))
+        .route("/auth/providers", get(identity::list_providers))
+        .route("/identities/:id", get(identity::list_identities))
+        .route(
+            "/identities/:id/:provider",
+            delete(identity::unlink_identity),
+        )
+        .route("/auth/siwe/nonce", get(siwe::get_nonce))
+        .route("/auth/siwe/login", post(siwe::login))
+        .route("/projects", get(projects::get_projects))
+        .route("/projects/search", get(projects::search_projects))
+        .route("/admin/projects", post(projects::create_project))
+        .route(
+            "/admin/projects/enrich",
+            post(token_metadata::trigger_enrichment),
+        )
+        .route(
+            "/admin/projects/:pid",
+            put(projects::update_project).delete(projects::delete_project),
+        )
+        .route(
+            "/admin/projects/:pid/tvl-contracts",
+            get(tvl::list_tvl_contracts).post(tvl::add_tvl_contract),
+        )
+        .route(
+            "/admin/projects/:pid/tvl-contracts/:contract_id",
+            delete(tvl::delete_tvl_contract),
+        )
+        .route(
+            "/admin/projects/:pid/summaries/:chain",
+            put(projects::upsert_project_summary),
+        )
+        .route(
+            "/admin/projects/:pid/whitepaper",
+            put(whitepaper::upload_whitepaper)
+                .layer(DefaultBodyLimit::max(constants::WHITEPAPER_MAX_BYTES)),
+        )
+        .route(
+            "/admin/webhooks",
+            get(webhooks::list_webhooks).post(webhooks::create_webhook),
+        )
+        .route("/admin/webhooks/:id", delete(webhooks::delete_webhook))
+        .route(
+            "/admin/webhooks/:id/deliveries",
+            get(webhooks::list_deliveries),
+        )
+        .route("/admin/wallet-audit", get(custody::list_audit_log))
+        .route(
+            "/admin/wallet-export-locks",
+            get(custody::list_export_locks),
+        )
+        .route(
+            "/admin/wallet-export-locks/:id",
+            put(custody::lock_exports).delete(custody::unlock_exports),
+        )
+        .route("/projects/:chain/:pid", get(projects::get_project_summary))
+        .route("/whitepapers/:pid", get(whitepaper::get_whitepaper))
+        .route("/prices", get(prices::get_prices))
         .route("/balance/:id", get(get_balance))
+        .route(
+            "/wallets/:id",
+            get(wallets::list_wallets).post(wallets::create_user_wallet),
+        )
+        .route("/wallets/:id/import", post(custody::import_wallet))
+        .route(
+            "/wallets/:id/external",
+            get(external_wallets::list_external_wallets).post(external_wallets::link_wallet),
+        )
+        .route(
+            "/wallets/:id/external/challenge",
+            post(external_wallets::create_challenge),
+        )
+        .route(
+            "/wallets/:id/external/:wallet",
+            delete(external_wallets::unlink_wallet),
+        )
+        .route(
+            "/wallets/:id/:wallet",
+            put(wallets::update_wallet).delete(wallets::archive_wallet),
+        )
+        .route("/wallets/:id/:wallet/export", post(custody::export_wallet))
+        .route("/portfolio/:id", get(portfolio::get_portfolio))
+        .route(
+            "/portfolio/:id/reconcile",
+            post(portfolio::reconcile_portfolio),
+        )
+        .route(
+            "/portfolio/:id/history",
+            get(portfolio::get_portfolio_history),
+        )
+        .route("/history/:id", get(history::get_history))
+        .route("/deposits/:id", get(deposits::get_deposits))
+        .route("/stream/:id", get(events::stream_sse))
+        .route("/stream/:id/ws", get(events::stream_ws))
         .route("/transfer", post(execute_transfer))
         .route("/swap/quote", post(swap::get_quote))
-        .route("/swap/execute", post(swap::execute_swap))
+        .route("/swap/:id", post(swap::quote_and_execute_swap))
+        .route("/swap/:id/quote", post(swap::get_user_quote))
+        .route("/swap/:id/execute", post(swap::execute_swap))
         .route("/swap/status", get(swap::get_swap_status))
         .route("/swap/details", get(swap::get_swap_details))
         .route("/swap/distributions", get(swap::get_distributions))
+        .route("/swaps/:id", get(swap::list_swaps))
+        .route(
+            "/orders/:id",
+            get(orders::list_orders).post(orders::create_order),
+        )
+        .route("/orders/:id/:order_id", delete(orders::cancel_order))
+        .route("/orders/:id/:order_id/logs", get(orders::get_order_logs))
+        .route(
+            "/subscriptions/:id",
+            get(subscriptions::list_subscriptions).post(subscriptions::create_subscription),
+        )
+        .route(
+            "/subscriptions/:id/:subscription_id",
+            delete(subscriptions::cancel_subscription),
+        )
+        .route(
+            "/subscriptions/:id/:subscription_id/runs",
+            get(subscriptions::get_subscription_runs),
+        )
         .layer(CorsLayer::permissive())
         .with_state(state);
 
@@ -56,18 +233,6 @@ async fn main() {
         .unwrap();
 }
 
-async fn get_projects() -> Json<Vec<Project>> {
-    let file_content = fs::read_to_string("projects.json").unwrap_or("[]".to_string());
-    let items: Vec<Project> = serde_json::from_str(&file_content).unwrap_or_else(|_| vec![]);
-    Json(items)
-}
-
-async fn get_project() -> Json<ProjectSummary> {
-    let file_content = fs::read_to_string("project.json").unwrap_or("{}".to_string());
-    let summ: ProjectSummary = serde_json::from_str(&file_content).unwrap();
-    Json(summ)
-}
-
 async fn get_profile(Path(user_id): Path<String>) -> Json<Profile> {
     let db = ProfileDatabase::new().unwrap();
     let profile = db.get(&user_id).unwrap();
@@ -78,8 +243,15 @@ async fn get_profile(Path(user_id): Path<String>) -> Json<Profile> {
     Json(Profile::default())
 }
 
-async fn get_balance(Path(user_id): Path<String>) -> impl IntoResponse {
-    let balance = wallets::get_balance(&user_id).await;
+async fn get_balance(
+    Path(user_id): Path<String>,
+    Query(query): Query<wallets::WalletQuery>,
+) -> impl IntoResponse {
+    let address = match wallets::resolve_address(&user_id, query.wallet.as_deref()) {
+        Ok(address) => address,
+        Err(e) => return e.into_response(),
+    };
+    let balance = wallets::get_balance(&address).await;
     let my_balance = BalanceResponse {
         balance: balance.unwrap(),
     };
@@ -87,9 +259,39 @@ async fn get_balance(Path(user_id): Path<String>) -> impl IntoResponse {
     (StatusCode::OK, Json(my_balance)).into_response()
 }
 
-async fn execute_transfer(Json(payload): Json<TransferForm>) -> impl IntoResponse {
-    let trx = wallets::transfer(&payload.user_id, &payload.recipient, &payload.amount).await;
+async fn execute_transfer(
+    State(state): State<AppState>,
+    Json(payload): Json<TransferForm>,
+) -> impl IntoResponse {
+    let wallet = match wallets::resolve_wallet(&payload.user_id, payload.wallet.as_deref()) {
+        Ok(wallet) => wallet,
+        Err(e) => return e.into_response(),
+    };
+    // The transfer can confirm before it is booked below; keep the portfolio
+    // from reconciling it as a withdrawal meanwhile.
+    let hold = format!("transfer:{}", hex::encode(rand::random::<[u8; 16]>()));
+    if let Err(e) = portfolio::hold(&payload.user_id, &hold) {
+        return (StatusCode::INTERNAL_SERVER_ERROR, e).into_response();
+    }
+    let trx = wallets::transfer(
+        &state.events,
+        &payload.user_id,
+        &wallet,
+        &payload.recipient,
+        &payload.amount,
+    )
+    .await;
     let my_transaction = TransactionResponse { trx: trx.unwrap() };
+    if my_transaction.trx != wallets::TRANSFER_NOT_SENT {
+        if let Err(e) =
+            portfolio::record_transfer_out(&payload.user_id, &payload.amount, &my_transaction.trx)
+        {
+            println!("Failed to book transfer into portfolio: {}", e);
+        }
+    }
+    if let Err(e) = portfolio::release(&hold) {
+        println!("Failed to release portfolio hold {}: {}", hold, e);
+    }
 
     (StatusCode::OK, Json(my_transaction)).into_response()
 }
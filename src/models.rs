@@ -1,15 +1,24 @@
 #![allow(dead_code)]
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct AppState {
     pub oauth: Arc<tokio::sync::Mutex<Option<crate::auth::OAuthState>>>,
+    pub identities: Arc<crate::identity::IdentityProviders>,
     pub magpie: crate::defi::magpiefi::MagpieClient,
+    pub swap_policy: crate::guardrails::SwapPolicy,
+    pub prices: Arc<crate::prices::PriceService>,
+    pub events: crate::events::EventBus,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct Project {
+    #[serde(default)]
+    pub id: Option<i64>,
+    #[serde(default)]
+    pub chain: String,
     pub name: String,
     pub description: String,
     pub website: String,
@@ -17,32 +26,152 @@ pub struct Project {
     pub symbol: String,
     pub decimals: u64,
     pub address: String,
-    pub category: String,
+    pub category: ProjectCategory,
+    #[serde(default)]
+    pub created_at: i64,
+    #[serde(default)]
+    pub updated_at: i64,
+    /// On-chain token metadata, present once the enrichment job has read it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub token_metadata: Option<TokenMetadata>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct TokenMetadata {
+    pub name: String,
+    pub symbol: String,
+    pub decimals: u64,
+    pub total_supply: String,
+    /// Catalog fields that disagree with the chain, e.g. `symbol` or `decimals`.
+    pub mismatches: Vec<String>,
+    pub verified: bool,
+    pub checked_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct ProjectQuery {
+    pub category: Option<String>,
+    pub chain: Option<String>,
+    /// Free-text search over name and description.
+    pub q: Option<String>,
+    /// One of `name` (default), `tvl` or `activity`.
+    pub sort: Option<String>,
+    pub cursor: Option<String>,
+    pub limit: Option<i64>,
+}
+
+impl ProjectQuery {
+    /// Whether no filter, sort or paging was asked for.
+    pub fn is_empty(&self) -> bool {
+        self.category.is_none()
+            && self.chain.is_none()
+            && self.q.is_none()
+            && self.sort.is_none()
+            && self.cursor.is_none()
+            && self.limit.is_none()
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
+pub struct ProjectPage {
+    pub projects: Vec<Project>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProjectCategory {
+    Dex,
+    Lending,
+    Launchpad,
+    Education,
+    Gaming,
+    Nft,
+    Bridge,
+    Infrastructure,
+    Wallet,
+    #[default]
+    Other,
+}
+
+impl ProjectCategory {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectCategory::Dex => "dex",
+            ProjectCategory::Lending => "lending",
+            ProjectCategory::Launchpad => "launchpad",
+            ProjectCategory::Education => "education",
+            ProjectCategory::Gaming => "gaming",
+            ProjectCategory::Nft => "nft",
+            ProjectCategory::Bridge => "bridge",
+            ProjectCategory::Infrastructure => "infrastructure",
+            ProjectCategory::Wallet => "wallet",
+            ProjectCategory::Other => "other",
+        }
+    }
+}
+
+impl std::str::FromStr for ProjectCategory {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        serde_json::from_value(serde_json::Value::String(s.to_string()))
+            .map_err(|_| format!("Unknown project category: {}", s))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct ProjectSummary {
+    #[serde(default)]
+    pub project_id: Option<i64>,
+    #[serde(default)]
+    pub chain: String,
     pub name: String,
     pub tvl: f64,
     pub sentiment: String,
     pub whitepaper_summary: String,
     pub github_activity: GithubActivity,
-    pub address: Address,
+    /// Contract address keyed by chain name, e.g. "mainnet" or "educhain".
+    pub address: BTreeMap<String, String>,
+    #[serde(default)]
+    pub updated_at: i64,
+    /// Computed TVL snapshots, oldest first. When present, `tvl` is the latest.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tvl_history: Vec<TvlPoint>,
+    /// Computed from ingested posts. When present, `sentiment` is its label.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sentiment_score: Option<SentimentScore>,
+    /// Link to the uploaded whitepaper, whose summary fills `whitepaper_summary`
+    /// when one could be drawn from it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub whitepaper_url: Option<String>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct SentimentScore {
+    /// Mean post score over the window, from -1 to 1.
+    pub score: f64,
+    /// "positive", "neutral" or "negative".
+    pub label: String,
+    /// "improving", "stable" or "declining" against the previous window.
+    pub trend: String,
+    pub posts: i64,
+    pub window_secs: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct TvlPoint {
+    pub timestamp: i64,
+    pub tvl: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
 pub struct GithubActivity {
     pub commits_last_30_days: u64,
     pub contributors: u64,
     pub repo_url: String,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
-pub struct Address {
-    pub mainnet: String,
-    pub testnet: String,
-}
-
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BalanceResponse {
     pub balance: String,
@@ -58,4 +187,7 @@ pub struct TransferForm {
     pub user_id: String,
     pub recipient: String,
     pub amount: String,
+    /// Address or label of the sending wallet; the default wallet if omitted.
+    #[serde(default)]
+    pub wallet: Option<String>,
 }
@@ -0,0 +1,512 @@
+#![allow(dead_code)]
+
+use crate::constants::{DB_PATH, ORDER_EVALUATION_INTERVAL_SECS};
+use crate::defi::models::{ExecuteSwapRequest, UserQuoteRequest};
+use crate::models::AppState;
+use crate::profiles::ProfileDatabase;
+use crate::swap;
+use crate::utils;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use ethers::types::U256;
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+pub const KIND_LIMIT: &str = "limit";
+pub const KIND_SCHEDULED: &str = "scheduled";
+
+pub const STATUS_OPEN: &str = "open";
+pub const STATUS_EXECUTING: &str = "executing";
+pub const STATUS_FILLED: &str = "filled";
+pub const STATUS_CANCELLED: &str = "cancelled";
+pub const STATUS_EXPIRED: &str = "expired";
+pub const STATUS_FAILED: &str = "failed";
+
+const ORDER_COLUMNS: &str = "id, user_id, kind, from_token, to_token, amount, slippage, \
+     min_amount_out, execute_at, expires_at, network_name, status, swap_id, created_at, updated_at";
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Order {
+    pub id: Option<i64>,
+    pub user_id: String,
+    pub kind: String,
+    pub from_token: String,
+    pub to_token: String,
+    pub amount: String,
+    pub slippage: String,
+    /// Limit orders fill once a quote returns at least this many to-token base units.
+    pub min_amount_out: Option<String>,
+    /// Scheduled orders fill at or after this unix timestamp.
+    pub execute_at: Option<i64>,
+    pub expires_at: Option<i64>,
+    pub network_name: String,
+    pub status: String,
+    pub swap_id: Option<String>,
+    pub created_at: i64,
+    pub updated_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct OrderLog {
+    pub id: Option<i64>,
+    pub order_id: i64,
+    pub status: String,
+    pub message: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateOrderRequest {
+    pub kind: String,
+    pub from_token: String,
+    pub to_token: String,
+    pub amount: String,
+    #[serde(default)]
+    pub slippage: String,
+    pub min_amount_out: Option<String>,
+    pub execute_at: Option<i64>,
+    pub expires_at: Option<i64>,
+    pub network_name: String,
+}
+
+pub struct OrderDatabase {
+    pub conn: Connection,
+}
+
+impl OrderDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS orders (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                from_token TEXT NOT NULL,
+                to_token TEXT NOT NULL,
+                amount TEXT NOT NULL,
+                slippage TEXT NOT NULL,
+                min_amount_out TEXT NULL,
+                execute_at INTEGER NULL,
+                expires_at INTEGER NULL,
+                network_name TEXT NOT NULL,
+                status TEXT NOT NULL,
+                swap_id TEXT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS order_logs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                order_id INTEGER NOT NULL REFERENCES orders (id),
+                status TEXT NOT NULL,
+                message TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+
+        Ok(OrderDatabase { conn })
+    }
+
+    pub fn create(&self, order: &Order) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO orders (user_id, kind, from_token, to_token, amount, slippage, min_amount_out,
+                execute_at, expires_at, network_name, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                order.user_id,
+                order.kind,
+                order.from_token,
+                order.to_token,
+                order.amount,
+                order.slippage,
+                order.min_amount_out,
+                order.execute_at,
+                order.expires_at,
+                order.network_name,
+                order.status
+            ],
+        )?;
+
+        let id = self.conn.last_insert_rowid();
+        self.log(id, &order.status, "Order created")?;
+        Ok(id)
+    }
+
+    pub fn get(&self, user_id: &str, order_id: i64) -> Result<Option<Order>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM orders WHERE id = ?1 AND user_id = ?2",
+                    ORDER_COLUMNS
+                ),
+                params![order_id, user_id],
+                from_row,
+            )
+            .optional()
+    }
+
+    pub fn list_for_user(&self, user_id: &str) -> Result<Vec<Order>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM orders WHERE user_id = ?1 ORDER BY created_at DESC, id DESC",
+            ORDER_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![user_id], from_row)?;
+        rows.collect()
+    }
+
+    pub fn list_open(&self) -> Result<Vec<Order>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM orders WHERE status = ?1 ORDER BY id",
+            ORDER_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![STATUS_OPEN], from_row)?;
+        rows.collect()
+    }
+
+    /// Atomically moves an order from `from` to `to`. Returns false if the
+    /// order was no longer in `from`, e.g. cancelled while being evaluated.
+    pub fn transition(&self, order_id: i64, from: &str, to: &str, message: &str) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE orders SET status = ?1, updated_at = strftime('%s', 'now')
+             WHERE id = ?2 AND status = ?3",
+            params![to, order_id, from],
+        )?;
+        if updated == 1 {
+            self.log(order_id, to, message)?;
+        }
+        Ok(updated == 1)
+    }
+
+    pub fn mark_filled(&self, order_id: i64, swap_id: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE orders SET status = ?1, swap_id = ?2, updated_at = strftime('%s', 'now')
+             WHERE id = ?3",
+            params![STATUS_FILLED, swap_id, order_id],
+        )?;
+        self.log(
+            order_id,
+            STATUS_FILLED,
+            &format!("Swap {} submitted", swap_id),
+        )
+    }
+
+    pub fn log(&self, order_id: i64, status: &str, message: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO order_logs (order_id, status, message) VALUES (?1, ?2, ?3)",
+            params![order_id, status, message],
+        )?;
+        Ok(())
+    }
+
+    /// Logs `message` unless it is already the order's latest entry, so a
+    /// condition that persists across evaluations is logged once.
+    pub fn log_once(&self, order_id: i64, status: &str, message: &str) -> Result<()> {
+        let latest: Option<String> = self
+            .conn
+            .query_row(
+                "SELECT message FROM order_logs WHERE order_id = ?1 ORDER BY id DESC LIMIT 1",
+                params![order_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        if latest.as_deref() == Some(message) {
+            return Ok(());
+        }
+        self.log(order_id, status, message)
+    }
+
+    pub fn logs(&self, order_id: i64) -> Result<Vec<OrderLog>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, order_id, status, message, created_at FROM order_logs
+             WHERE order_id = ?1 ORDER BY id",
+        )?;
+        let rows = stmt.query_map(params![order_id], |row| {
+            Ok(OrderLog {
+                id: row.get(0)?,
+                order_id: row.get(1)?,
+                status: row.get(2)?,
+                message: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+fn from_row(row: &Row) -> Result<Order> {
+    Ok(Order {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        kind: row.get(2)?,
+        from_token: row.get(3)?,
+        to_token: row.get(4)?,
+        amount: row.get(5)?,
+        slippage: row.get(6)?,
+        min_amount_out: row.get(7)?,
+        execute_at: row.get(8)?,
+        expires_at: row.get(9)?,
+        network_name: row.get(10)?,
+        status: row.get(11)?,
+        swap_id: row.get(12)?,
+        created_at: row.get(13)?,
+        updated_at: row.get(14)?,
+    })
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Order database error: {}", e),
+    )
+}
+
+pub async fn create_order(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Json(req): Json<CreateOrderRequest>,
+) -> Result<Json<Order>, (StatusCode, String)> {
+    let now = utils::now();
+
+    ProfileDatabase::new()
+        .and_then(|db| db.get(&user_id))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+
+    match req.kind.as_str() {
+        KIND_LIMIT => {
+            let min_amount_out = req.min_amount_out.as_deref().unwrap_or_default();
+            if U256::from_dec_str(min_amount_out).is_err() {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Limit orders require min_amount_out in to-token base units".to_string(),
+                ));
+            }
+        }
+        KIND_SCHEDULED => match req.execute_at {
+            Some(at) if at > now => {}
+            _ => {
+                return Err((
+                    StatusCode::BAD_REQUEST,
+                    "Scheduled orders require a future execute_at".to_string(),
+                ))
+            }
+        },
+        other => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown order kind: {}", other),
+            ))
+        }
+    }
+
+    if matches!(req.expires_at, Some(at) if at <= now) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "expires_at must be in the future".to_string(),
+        ));
+    }
+    if U256::from_dec_str(&req.amount).is_err() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid amount: {}", req.amount),
+        ));
+    }
+    let slippage = state
+        .swap_policy
+        .parse_slippage(&req.slippage)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut order = Order {
+        user_id,
+        kind: req.kind,
+        from_token: req.from_token,
+        to_token: req.to_token,
+        amount: req.amount,
+        slippage: slippage.to_string(),
+        min_amount_out: req.min_amount_out,
+        execute_at: req.execute_at,
+        expires_at: req.expires_at,
+        network_name: req.network_name,
+        status: STATUS_OPEN.to_string(),
+        created_at: now,
+        updated_at: now,
+        ..Default::default()
+    };
+
+    let db = OrderDatabase::new().map_err(db_error)?;
+    order.id = Some(db.create(&order).map_err(db_error)?);
+
+    Ok(Json(order))
+}
+
+pub async fn list_orders(
+    Path(user_id): Path<String>,
+) -> Result<Json<Vec<Order>>, (StatusCode, String)> {
+    let db = OrderDatabase::new().map_err(db_error)?;
+    let orders = db.list_for_user(&user_id).map_err(db_error)?;
+    Ok(Json(orders))
+}
+
+pub async fn cancel_order(
+    Path((user_id, order_id)): Path<(String, i64)>,
+) -> Result<Json<Order>, (StatusCode, String)> {
+    let db = OrderDatabase::new().map_err(db_error)?;
+    let order = db
+        .get(&user_id, order_id)
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Order not found".to_string()))?;
+
+    let cancelled = db
+        .transition(order_id, STATUS_OPEN, STATUS_CANCELLED, "Cancelled by user")
+        .map_err(db_error)?;
+    if !cancelled {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Order is {} and can no longer be cancelled", order.status),
+        ));
+    }
+
+    let order = db.get(&user_id, order_id).map_err(db_error)?.unwrap();
+    Ok(Json(order))
+}
+
+pub async fn get_order_logs(
+    Path((user_id, order_id)): Path<(String, i64)>,
+) -> Result<Json<Vec<OrderLog>>, (StatusCode, String)> {
+    let db = OrderDatabase::new().map_err(db_error)?;
+    db.get(&user_id, order_id)
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Order not found".to_string()))?;
+
+    let logs = db.logs(order_id).map_err(db_error)?;
+    Ok(Json(logs))
+}
+
+/// Polls open orders and executes those whose trigger condition holds.
+pub async fn run_evaluator(state: AppState) {
+    let mut interval = tokio::time::interval(Duration::from_secs(ORDER_EVALUATION_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        let open = match OrderDatabase::new().and_then(|db| db.list_open()) {
+            Ok(orders) => orders,
+            Err(e) => {
+                println!("Order evaluator failed to load orders: {}", e);
+                continue;
+            }
+        };
+
+        for order in open {
+            if let Err(e) = evaluate(&state, &order).await {
+                println!("Order evaluator failed on order {:?}: {}", order.id, e);
+            }
+        }
+    }
+}
+
+async fn evaluate(state: &AppState, order: &Order) -> Result<()> {
+    let db = OrderDatabase::new()?;
+    let order_id = order.id.unwrap_or_default();
+    let now = utils::now();
+
+    if matches!(order.expires_at, Some(at) if at <= now) {
+        db.transition(order_id, STATUS_OPEN, STATUS_EXPIRED, "Order expired")?;
+        return Ok(());
+    }
+
+    if order.kind == KIND_SCHEDULED && !matches!(order.execute_at, Some(at) if at <= now) {
+        return Ok(());
+    }
+
+    // Limit orders are checked against a quote that is neither recorded nor
+    // guarded; only a triggered order gets a real one.
+    if order.kind == KIND_LIMIT {
+        match swap::preview_for_user(state, &order.user_id, quote_request(order)).await {
+            Ok(preview) if meets_limit(order, &preview.to_token_amount) => {}
+            Ok(_) => return Ok(()),
+            Err((_, e)) => return db.log_once(order_id, STATUS_OPEN, &e),
+        }
+    }
+
+    let quote = match swap::quote_for_user(state, &order.user_id, quote_request(order)).await {
+        Ok(quote) => quote,
+        Err((_, e)) => {
+            // Scheduled orders are due now, so a failed quote is final. Limit
+            // orders wait for the next evaluation; the reason, e.g. a price
+            // impact over policy, goes to the order log.
+            if order.kind == KIND_SCHEDULED {
+                db.transition(order_id, STATUS_OPEN, STATUS_FAILED, &e)?;
+                return Ok(());
+            }
+            return db.log_once(order_id, STATUS_OPEN, &e);
+        }
+    };
+    if order.kind == KIND_LIMIT && !meets_limit(order, &quote.quote.to_token_amount) {
+        return Ok(());
+    }
+
+    let message = format!(
+        "Triggered with quote {} for {}",
+        quote.quote.quote_id, quote.quote.to_token_amount
+    );
+    if !db.transition(order_id, STATUS_OPEN, STATUS_EXECUTING, &message)? {
+        return Ok(());
+    }
+
+    let result = swap::execute(
+        state,
+        &order.user_id,
+        ExecuteSwapRequest {
+            quote_id: quote.quote.quote_id,
+            network_name: order.network_name.clone(),
+            wallet_key: None,
+            permit_deadline: None,
+            from_token: None,
+            amount: None,
+            wallet: None,
+        },
+    )
+    .await;
+
+    match result {
+        Ok(response) => db.mark_filled(order_id, &response.swap_id),
+        Err((_, e)) => db
+            .transition(order_id, STATUS_EXECUTING, STATUS_FAILED, &e)
+            .map(|_| ()),
+    }
+}
+
+/// Whether `amount_out` reaches the order's `min_amount_out`.
+fn meets_limit(order: &Order, amount_out: &str) -> bool {
+    let target = order
+        .min_amount_out
+        .as_deref()
+        .and_then(|v| U256::from_dec_str(v).ok())
+        .unwrap_or_default();
+    U256::from_dec_str(amount_out).unwrap_or_default() >= target
+}
+
+fn quote_request(order: &Order) -> UserQuoteRequest {
+    UserQuoteRequest {
+        from_token: order.from_token.clone(),
+        to_token: order.to_token.clone(),
+        amount: order.amount.clone(),
+        slippage: order.slippage.clone(),
+        to_address: None,
+        gasless: true,
+        affiliate_address: None,
+        affiliate_fee: None,
+        accept_high_impact: false,
+        wallet: None,
+    }
+}
@@ -0,0 +1,250 @@
+#![allow(dead_code)]
+
+use crate::chain;
+use crate::constants::PERMIT2_ADDRESS;
+use ethers::{prelude::*, types::transaction::eip712::TypedData, utils::hex};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+abigen!(
+    PermitToken,
+    r#"[
+        function name() external view returns (string)
+        function version() external view returns (string)
+        function nonces(address owner) external view returns (uint256)
+        function DOMAIN_SEPARATOR() external view returns (bytes32)
+        function allowance(address owner, address spender) external view returns (uint256)
+    ]"#
+);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PermitKind {
+    Eip2612,
+    Permit2,
+}
+
+#[derive(Debug, Clone)]
+pub struct SignedPermit {
+    pub kind: PermitKind,
+    pub signature: String,
+    /// The token's EIP-2612 nonce, or the unordered Permit2 nonce; the
+    /// relayer needs it to rebuild the signed struct.
+    pub nonce: U256,
+    pub deadline: u64,
+}
+
+/// Signs whatever permit the from-token needs so the gasless relayer can pull
+/// `amount` for `spender`. Returns `None` when the existing allowance already
+/// covers the swap.
+pub async fn sign_permit(
+    wallet: &LocalWallet,
+    token: &str,
+    spender: &str,
+    amount: &str,
+    deadline: u64,
+) -> Result<Option<SignedPermit>, String> {
+    let provider = chain::provider()?;
+
+    let token = token
+        .parse::<Address>()
+        .map_err(|_| format!("Invalid token address: {}", token))?;
+    let spender = spender
+        .parse::<Address>()
+        .map_err(|_| format!("Invalid spender address: {}", spender))?;
+    let value = U256::from_dec_str(amount).map_err(|_| format!("Invalid amount: {}", amount))?;
+    let owner = wallet.address();
+    let contract = PermitToken::new(token, provider.clone());
+
+    let allowance = contract
+        .allowance(owner, spender)
+        .call()
+        .await
+        .map_err(|e| format!("Failed to read allowance: {}", e))?;
+    if allowance >= value {
+        return Ok(None);
+    }
+
+    let chain_id = wallet.chain_id();
+    let kind = detect_permit_kind(&contract, owner).await?;
+
+    let (typed_data, nonce) = match &kind {
+        PermitKind::Eip2612 => {
+            let name = contract
+                .name()
+                .call()
+                .await
+                .map_err(|e| format!("Failed to read token name: {}", e))?;
+            // Most OpenZeppelin tokens expose version(); older ones implicitly use "1".
+            let version = contract
+                .version()
+                .call()
+                .await
+                .unwrap_or_else(|_| "1".to_string());
+            let nonce = contract
+                .nonces(owner)
+                .call()
+                .await
+                .map_err(|e| format!("Failed to read permit nonce: {}", e))?;
+
+            let typed_data = eip2612_typed_data(
+                &name, &version, chain_id, token, owner, spender, value, nonce, deadline,
+            );
+            (typed_data, nonce)
+        }
+        PermitKind::Permit2 => {
+            let permit2 = PERMIT2_ADDRESS.parse::<Address>().unwrap();
+            let permit2_allowance = contract
+                .allowance(owner, permit2)
+                .call()
+                .await
+                .map_err(|e| format!("Failed to read Permit2 allowance: {}", e))?;
+            if permit2_allowance < value {
+                return Err(
+                    "Token does not support EIP-2612 and is not approved for Permit2".to_string(),
+                );
+            }
+
+            // Permit2 signature transfers use unordered nonces, so any unused value works.
+            let nonce = U256::from(rand::random::<u64>());
+            let typed_data =
+                permit2_typed_data(chain_id, permit2, token, spender, value, nonce, deadline);
+            (typed_data, nonce)
+        }
+    };
+
+    let signature = wallet
+        .sign_typed_data(&typed_data)
+        .await
+        .map_err(|e| format!("Failed to sign permit: {}", e))?;
+
+    Ok(Some(SignedPermit {
+        kind,
+        signature: format!("0x{}", hex::encode(signature.to_vec())),
+        nonce,
+        deadline,
+    }))
+}
+
+/// EIP-2612 when the token has both `DOMAIN_SEPARATOR` and `nonces`,
+/// otherwise Permit2. A probe that fails for any other reason than the
+/// function being missing is an error, not a vote for Permit2.
+async fn detect_permit_kind(
+    contract: &PermitToken<Provider<Http>>,
+    owner: Address,
+) -> Result<PermitKind, String> {
+    let has_domain = supports(contract.domain_separator().call().await)?;
+    let has_nonces = supports(contract.nonces(owner).call().await)?;
+
+    if has_domain && has_nonces {
+        Ok(PermitKind::Eip2612)
+    } else {
+        Ok(PermitKind::Permit2)
+    }
+}
+
+/// Whether a probe call found the function: a revert or undecodable return
+/// data means the token lacks it, anything else is a failed call.
+fn supports<T>(result: Result<T, ContractError<Provider<Http>>>) -> Result<bool, String> {
+    match result {
+        Ok(_) => Ok(true),
+        Err(
+            ContractError::Revert(_)
+            | ContractError::DecodingError(_)
+            | ContractError::DetokenizationError(_),
+        ) => Ok(false),
+        Err(e) => Err(format!("Failed to probe permit support: {}", e)),
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+fn eip2612_typed_data(
+    name: &str,
+    version: &str,
+    chain_id: u64,
+    token: Address,
+    owner: Address,
+    spender: Address,
+    value: U256,
+    nonce: U256,
+    deadline: u64,
+) -> TypedData {
+    serde_json::from_value(json!({
+        "domain": {
+            "name": name,
+            "version": version,
+            "chainId": chain_id,
+            "verifyingContract": format!("{:#x}", token),
+        },
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "version", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" },
+            ],
+            "Permit": [
+                { "name": "owner", "type": "address" },
+                { "name": "spender", "type": "address" },
+                { "name": "value", "type": "uint256" },
+                { "name": "nonce", "type": "uint256" },
+                { "name": "deadline", "type": "uint256" },
+            ],
+        },
+        "primaryType": "Permit",
+        "message": {
+            "owner": format!("{:#x}", owner),
+            "spender": format!("{:#x}", spender),
+            "value": value.to_string(),
+            "nonce": nonce.to_string(),
+            "deadline": deadline.to_string(),
+        },
+    }))
+    .expect("Invalid EIP-2612 typed data")
+}
+
+fn permit2_typed_data(
+    chain_id: u64,
+    permit2: Address,
+    token: Address,
+    spender: Address,
+    value: U256,
+    nonce: U256,
+    deadline: u64,
+) -> TypedData {
+    serde_json::from_value(json!({
+        "domain": {
+            "name": "Permit2",
+            "chainId": chain_id,
+            "verifyingContract": format!("{:#x}", permit2),
+        },
+        "types": {
+            "EIP712Domain": [
+                { "name": "name", "type": "string" },
+                { "name": "chainId", "type": "uint256" },
+                { "name": "verifyingContract", "type": "address" },
+            ],
+            "PermitTransferFrom": [
+                { "name": "permitted", "type": "TokenPermissions" },
+                { "name": "spender", "type": "address" },
+                { "name": "nonce", "type": "uint256" },
+                { "name": "deadline", "type": "uint256" },
+            ],
+            "TokenPermissions": [
+                { "name": "token", "type": "address" },
+                { "name": "amount", "type": "uint256" },
+            ],
+        },
+        "primaryType": "PermitTransferFrom",
+        "message": {
+            "permitted": {
+                "token": format!("{:#x}", token),
+                "amount": value.to_string(),
+            },
+            "spender": format!("{:#x}", spender),
+            "nonce": nonce.to_string(),
+            "deadline": deadline.to_string(),
+        },
+    }))
+    .expect("Invalid Permit2 typed data")
+}
@@ -0,0 +1,717 @@
+#![allow(dead_code)]
+
+use crate::chain;
+use crate::constants::{
+    DB_PATH, DEFAULT_CHAIN, NATIVE_TOKEN_ADDRESS, PORTFOLIO_HISTORY_DEFAULT_DAYS,
+    PORTFOLIO_HISTORY_MAX_DAYS, PORTFOLIO_SETTLEMENT_HOLD_SECS, PORTFOLIO_SNAPSHOT_INTERVAL_SECS,
+};
+use crate::external_wallets::{ExternalWallet, ExternalWalletDatabase};
+use crate::models::AppState;
+use crate::prices::PriceService;
+use crate::profiles::ProfileDatabase;
+use crate::projects::ProjectDatabase;
+use crate::swap_history::SwapRecord;
+use crate::utils;
+use crate::wallets;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use ethers::providers::{Http, Provider};
+use ethers::types::U256;
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const EVENT_BUY: &str = "buy";
+pub const EVENT_SELL: &str = "sell";
+pub const EVENT_TRANSFER_OUT: &str = "transfer_out";
+pub const EVENT_DEPOSIT: &str = "deposit";
+pub const EVENT_WITHDRAWAL: &str = "withdrawal";
+
+/// Balance differences below this many whole tokens are treated as rounding.
+const RECONCILE_EPSILON: f64 = 1e-9;
+
+/// Average-cost position in one token.
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Position {
+    pub token: String,
+    pub quantity: f64,
+    pub cost_usd: f64,
+    pub realized_pnl: f64,
+}
+
+impl Position {
+    fn acquire(&mut self, quantity: f64, cost_usd: f64) {
+        self.quantity += quantity;
+        self.cost_usd += cost_usd;
+    }
+
+    /// Removes `quantity` at average cost. With proceeds the difference to
+    /// cost is realized; without (transfers, withdrawals) it is not. Selling
+    /// more than is tracked realizes nothing on the excess.
+    fn dispose(&mut self, quantity: f64, proceeds_usd: Option<f64>) {
+        if quantity <= 0.0 {
+            return;
+        }
+        let held = quantity.min(self.quantity);
+        let basis = if self.quantity > 0.0 {
+            self.cost_usd * held / self.quantity
+        } else {
+            0.0
+        };
+
+        self.quantity -= held;
+        self.cost_usd -= basis;
+        if self.quantity <= RECONCILE_EPSILON {
+            self.quantity = 0.0;
+            self.cost_usd = 0.0;
+        }
+        if let Some(proceeds) = proceeds_usd {
+            self.realized_pnl += proceeds * held / quantity - basis;
+        }
+    }
+
+    /// Applies one `EVENT_*` to the position.
+    fn apply(&mut self, kind: &str, quantity: f64, value_usd: Option<f64>) {
+        match kind {
+            EVENT_BUY | EVENT_DEPOSIT => self.acquire(quantity, value_usd.unwrap_or_default()),
+            EVENT_SELL => self.dispose(quantity, value_usd),
+            _ => self.dispose(quantity, None),
+        }
+    }
+
+    pub fn average_cost(&self) -> f64 {
+        if self.quantity > 0.0 {
+            self.cost_usd / self.quantity
+        } else {
+            0.0
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct AssetValuation {
+    pub token: String,
+    /// Raw on-chain balance in base units.
+    pub balance: String,
+    pub amount: f64,
+    pub price_usd: Option<f64>,
+    pub value_usd: f64,
+    pub cost_basis_usd: f64,
+    pub average_cost_usd: f64,
+    /// `None` when the token has no price.
+    pub unrealized_pnl: Option<f64>,
+    pub realized_pnl: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct PortfolioValuation {
+    pub user_id: String,
+    /// The default wallet.
+    pub wallet: String,
+    /// Every custody wallet whose balances make up the totals.
+    #[serde(default)]
+    pub wallets: Vec<String>,
+    pub total_value_usd: f64,
+    pub cost_basis_usd: f64,
+    pub unrealized_pnl: f64,
+    pub realized_pnl: f64,
+    pub assets: Vec<AssetValuation>,
+    /// Held tokens left out of the totals for lack of a price.
+    pub unpriced: Vec<String>,
+    /// Linked self-custody wallets, valued separately and left out of the
+    /// totals and cost basis above.
+    #[serde(default)]
+    pub watch_only: Vec<WatchOnlyValuation>,
+    /// A swap or transfer is still settling, so balance drift was shown but
+    /// not booked.
+    #[serde(default)]
+    pub settling: bool,
+    pub valued_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct WatchOnlyAsset {
+    pub token: String,
+    pub balance: String,
+    pub amount: f64,
+    pub price_usd: Option<f64>,
+    pub value_usd: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct WatchOnlyValuation {
+    pub address: String,
+    pub label: Option<String>,
+    pub total_value_usd: f64,
+    pub assets: Vec<WatchOnlyAsset>,
+    pub unpriced: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct ValuePoint {
+    pub timestamp: i64,
+    pub value_usd: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    pub days: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HistoryResponse {
+    pub user_id: String,
+    pub points: Vec<ValuePoint>,
+}
+
+pub struct PortfolioDatabase {
+    pub conn: Connection,
+}
+
+impl PortfolioDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS portfolio_positions (
+                user_id TEXT NOT NULL,
+                token TEXT NOT NULL,
+                quantity REAL NOT NULL,
+                cost_usd REAL NOT NULL,
+                realized_pnl REAL NOT NULL,
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                PRIMARY KEY (user_id, token)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS portfolio_events (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                token TEXT NOT NULL,
+                kind TEXT NOT NULL,
+                quantity REAL NOT NULL,
+                value_usd REAL,
+                reference TEXT,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS portfolio_snapshots (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                total_value_usd REAL NOT NULL,
+                unrealized_pnl REAL NOT NULL,
+                realized_pnl REAL NOT NULL,
+                assets TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS portfolio_snapshots_user ON portfolio_snapshots (user_id, created_at)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS portfolio_holds (
+                reference TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+
+        Ok(PortfolioDatabase { conn })
+    }
+
+    pub fn position(&self, user_id: &str, token: &str) -> Result<Position> {
+        let position = self
+            .conn
+            .query_row(
+                "SELECT token, quantity, cost_usd, realized_pnl FROM portfolio_positions
+                 WHERE user_id = ?1 AND token = ?2",
+                params![user_id, token],
+                |row| {
+                    Ok(Position {
+                        token: row.get(0)?,
+                        quantity: row.get(1)?,
+                        cost_usd: row.get(2)?,
+                        realized_pnl: row.get(3)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(position.unwrap_or(Position {
+            token: token.to_string(),
+            ..Default::default()
+        }))
+    }
+
+    pub fn positions(&self, user_id: &str) -> Result<Vec<Position>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT token, quantity, cost_usd, realized_pnl FROM portfolio_positions
+             WHERE user_id = ?1 ORDER BY token",
+        )?;
+        let rows = stmt.query_map(params![user_id], |row| {
+            Ok(Position {
+                token: row.get(0)?,
+                quantity: row.get(1)?,
+                cost_usd: row.get(2)?,
+                realized_pnl: row.get(3)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Applies an event to the user's position and logs it, atomically.
+    pub fn apply(
+        &self,
+        user_id: &str,
+        token: &str,
+        kind: &str,
+        quantity: f64,
+        value_usd: Option<f64>,
+        reference: Option<&str>,
+    ) -> Result<Position> {
+        let token = token.to_lowercase();
+        let tx = self.conn.unchecked_transaction()?;
+
+        let mut position = self.position(user_id, &token)?;
+        position.apply(kind, quantity, value_usd);
+
+        tx.execute(
+            "INSERT INTO portfolio_positions (user_id, token, quantity, cost_usd, realized_pnl)
+             VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (user_id, token) DO UPDATE SET
+                quantity = excluded.quantity, cost_usd = excluded.cost_usd,
+                realized_pnl = excluded.realized_pnl, updated_at = strftime('%s', 'now')",
+            params![
+                user_id,
+                token,
+                position.quantity,
+                position.cost_usd,
+                position.realized_pnl
+            ],
+        )?;
+        tx.execute(
+            "INSERT INTO portfolio_events (user_id, token, kind, quantity, value_usd, reference)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![user_id, token, kind, quantity, value_usd, reference],
+        )?;
+        tx.commit()?;
+
+        Ok(position)
+    }
+
+    pub fn add_hold(&self, user_id: &str, reference: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR REPLACE INTO portfolio_holds (reference, user_id) VALUES (?1, ?2)",
+            params![reference, user_id],
+        )?;
+        Ok(())
+    }
+
+    pub fn release_hold(&self, reference: &str) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM portfolio_holds WHERE reference = ?1",
+            params![reference],
+        )?;
+        Ok(())
+    }
+
+    /// Whether the user has a hold placed since `since`.
+    pub fn has_hold(&self, user_id: &str, since: i64) -> Result<bool> {
+        self.conn
+            .prepare("SELECT 1 FROM portfolio_holds WHERE user_id = ?1 AND created_at >= ?2")?
+            .exists(params![user_id, since])
+    }
+
+    pub fn record_snapshot(&self, valuation: &PortfolioValuation) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO portfolio_snapshots (user_id, total_value_usd, unrealized_pnl, realized_pnl, assets)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                valuation.user_id,
+                valuation.total_value_usd,
+                valuation.unrealized_pnl,
+                valuation.realized_pnl,
+                serde_json::to_string(&valuation.assets).unwrap_or_default()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Snapshot values since `since`, oldest first.
+    pub fn history(&self, user_id: &str, since: i64) -> Result<Vec<ValuePoint>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT created_at, total_value_usd FROM portfolio_snapshots
+             WHERE user_id = ?1 AND created_at >= ?2 ORDER BY created_at",
+        )?;
+        let rows = stmt.query_map(params![user_id, since], |row| {
+            Ok(ValuePoint {
+                timestamp: row.get(0)?,
+                value_usd: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+/// Holds off reconciliation for the user while the swap or transfer named
+/// by `reference` settles: its balance change can land on chain before it
+/// is booked, and reconciling in between would book it twice.
+pub fn hold(user_id: &str, reference: &str) -> std::result::Result<(), String> {
+    PortfolioDatabase::new()
+        .and_then(|db| db.add_hold(user_id, reference))
+        .map_err(|e| e.to_string())
+}
+
+/// Lifts the hold once the swap or transfer is booked or has failed.
+pub fn release(reference: &str) -> std::result::Result<(), String> {
+    PortfolioDatabase::new()
+        .and_then(|db| db.release_hold(reference))
+        .map_err(|e| e.to_string())
+}
+
+/// Books an executed swap as a sale of the input token and a purchase of the
+/// output token. Both legs share one USD value, taken from whichever side
+/// has a price.
+pub async fn record_swap(
+    prices: &PriceService,
+    user_id: &str,
+    swap: &SwapRecord,
+) -> std::result::Result<(), String> {
+    let provider = chain::provider_for(DEFAULT_CHAIN)?;
+    let amount_in = parse_amount(provider.clone(), &swap.from_token, &swap.amount_in).await?;
+    let amount_out = parse_amount(provider, &swap.to_token, &swap.amount_out).await?;
+
+    let value_usd = match prices.price(DEFAULT_CHAIN, &swap.from_token).await {
+        Some(price) => Some(amount_in * price.usd),
+        None => prices
+            .price(DEFAULT_CHAIN, &swap.to_token)
+            .await
+            .map(|price| amount_out * price.usd),
+    };
+    if value_usd.is_none() {
+        println!(
+            "No price for swap {}, booked without a USD value",
+            swap.quote_id
+        );
+    }
+
+    let db = PortfolioDatabase::new().map_err(|e| e.to_string())?;
+    let reference = Some(swap.quote_id.as_str());
+    db.apply(
+        user_id,
+        &swap.from_token,
+        EVENT_SELL,
+        amount_in,
+        value_usd,
+        reference,
+    )
+    .and_then(|_| {
+        db.apply(
+            user_id,
+            &swap.to_token,
+            EVENT_BUY,
+            amount_out,
+            value_usd,
+            reference,
+        )
+    })
+    .map_err(|e| e.to_string())?;
+
+    Ok(())
+}
+
+/// Books a native transfer sent from the user's wallet. Cost basis leaves with
+/// the tokens; nothing is realized.
+pub fn record_transfer_out(
+    user_id: &str,
+    amount: &str,
+    reference: &str,
+) -> std::result::Result<(), String> {
+    let amount = amount
+        .parse::<f64>()
+        .map_err(|_| format!("Invalid amount: {}", amount))?;
+    PortfolioDatabase::new()
+        .and_then(|db| {
+            db.apply(
+                user_id,
+                NATIVE_TOKEN_ADDRESS,
+                EVENT_TRANSFER_OUT,
+                amount,
+                None,
+                Some(reference),
+            )
+        })
+        .map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+async fn parse_amount(
+    provider: Arc<ethers::providers::Provider<ethers::providers::Http>>,
+    token: &str,
+    amount: &str,
+) -> std::result::Result<f64, String> {
+    let amount = U256::from_dec_str(amount).map_err(|_| format!("Invalid amount: {}", amount))?;
+    let decimals = chain::token_decimals(provider, token).await?;
+    Ok(chain::to_units(amount, decimals))
+}
+
+/// Tokens worth checking for a user: the native token, anything they hold a
+/// position in, and the catalog's tokens on the default chain.
+fn tracked_tokens(db: &PortfolioDatabase, user_id: &str) -> Result<BTreeSet<String>> {
+    let mut tokens = BTreeSet::new();
+    tokens.insert(NATIVE_TOKEN_ADDRESS.to_string());
+    for position in db.positions(user_id)? {
+        tokens.insert(position.token);
+    }
+    for project in ProjectDatabase::new()?.list()? {
+        if project.chain.eq_ignore_ascii_case(DEFAULT_CHAIN) && !project.address.is_empty() {
+            tokens.insert(project.address.to_lowercase());
+        }
+    }
+    Ok(tokens)
+}
+
+/// Values a user's custody wallets at current prices. Positions are kept per
+/// user, so balances are summed over every wallet before being compared:
+/// moving funds or trading between wallets is not a deposit or withdrawal.
+/// Balances that moved outside the API are reconciled first: inflows count
+/// as deposits at the current price, outflows as withdrawals at cost. They
+/// are only booked when `reconcile` is set and nothing of the user's is
+/// settling; otherwise the valuation shows the result without writing
+/// anything.
+pub async fn value_portfolio(
+    prices: &PriceService,
+    user_id: &str,
+    reconcile: bool,
+) -> std::result::Result<PortfolioValuation, (StatusCode, String)> {
+    let internal = |e: String| (StatusCode::INTERNAL_SERVER_ERROR, e);
+
+    let profile = ProfileDatabase::new()
+        .and_then(|db| db.get(user_id))
+        .map_err(|e| internal(e.to_string()))?
+        .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+    let addresses = wallets::custody_addresses(user_id).map_err(|e| internal(e.to_string()))?;
+    if addresses.is_empty() {
+        return Err((StatusCode::NOT_FOUND, "Wallet not found".to_string()));
+    }
+
+    let provider = chain::provider_for(DEFAULT_CHAIN).map_err(internal)?;
+    let db = PortfolioDatabase::new().map_err(|e| internal(e.to_string()))?;
+    let tokens = tracked_tokens(&db, user_id).map_err(|e| internal(e.to_string()))?;
+    let settling = db
+        .has_hold(user_id, utils::now() - PORTFOLIO_SETTLEMENT_HOLD_SECS)
+        .map_err(|e| internal(e.to_string()))?;
+    let reconcile = reconcile && !settling;
+
+    let mut valuation = PortfolioValuation {
+        user_id: user_id.to_string(),
+        wallet: profile.wallet.clone(),
+        wallets: addresses.clone(),
+        settling,
+        valued_at: utils::now(),
+        ..Default::default()
+    };
+
+    let external = ExternalWalletDatabase::new()
+        .and_then(|db| db.list_for_user(user_id))
+        .map_err(|e| internal(e.to_string()))?;
+    for wallet in external {
+        valuation
+            .watch_only
+            .push(value_watch_only(prices, provider.clone(), &tokens, wallet).await?);
+    }
+
+    for token in tokens {
+        let mut balance = U256::zero();
+        for address in &addresses {
+            balance += chain::token_balance(provider.clone(), &token, address)
+                .await
+                .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+        }
+        let mut position = db
+            .position(user_id, &token)
+            .map_err(|e| internal(e.to_string()))?;
+        if balance.is_zero() && position.quantity == 0.0 && position.realized_pnl == 0.0 {
+            continue;
+        }
+
+        let decimals = chain::token_decimals(provider.clone(), &token)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+        let amount = chain::to_units(balance, decimals);
+        let price = prices.price(DEFAULT_CHAIN, &token).await.map(|p| p.usd);
+
+        let drift = amount - position.quantity;
+        if drift.abs() > RECONCILE_EPSILON {
+            let (kind, value) = if drift > 0.0 {
+                (EVENT_DEPOSIT, price.map(|p| p * drift))
+            } else {
+                (EVENT_WITHDRAWAL, None)
+            };
+            if reconcile {
+                position = db
+                    .apply(user_id, &token, kind, drift.abs(), value, None)
+                    .map_err(|e| internal(e.to_string()))?;
+            } else {
+                position.apply(kind, drift.abs(), value);
+            }
+        }
+
+        let value_usd = price.map(|p| p * amount).unwrap_or_default();
+        let unrealized_pnl = price.map(|_| value_usd - position.cost_usd);
+        match unrealized_pnl {
+            Some(pnl) => {
+                valuation.total_value_usd += value_usd;
+                valuation.cost_basis_usd += position.cost_usd;
+                valuation.unrealized_pnl += pnl;
+            }
+            None if amount > 0.0 => valuation.unpriced.push(token.clone()),
+            None => {}
+        }
+        valuation.realized_pnl += position.realized_pnl;
+
+        valuation.assets.push(AssetValuation {
+            token,
+            balance: balance.to_string(),
+            amount,
+            price_usd: price,
+            value_usd,
+            cost_basis_usd: position.cost_usd,
+            average_cost_usd: position.average_cost(),
+            unrealized_pnl,
+            realized_pnl: position.realized_pnl,
+        });
+    }
+
+    Ok(valuation)
+}
+
+/// Values a linked wallet at current prices. Nothing is booked: the user
+/// trades from it elsewhere, so there is no cost basis to track.
+async fn value_watch_only(
+    prices: &PriceService,
+    provider: Arc<Provider<Http>>,
+    tokens: &BTreeSet<String>,
+    wallet: ExternalWallet,
+) -> std::result::Result<WatchOnlyValuation, (StatusCode, String)> {
+    let mut valuation = WatchOnlyValuation {
+        address: wallet.address,
+        label: wallet.label,
+        ..Default::default()
+    };
+
+    for token in tokens {
+        let balance = chain::token_balance(provider.clone(), token, &valuation.address)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+        if balance.is_zero() {
+            continue;
+        }
+
+        let decimals = chain::token_decimals(provider.clone(), token)
+            .await
+            .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+        let amount = chain::to_units(balance, decimals);
+        let price = prices.price(DEFAULT_CHAIN, token).await.map(|p| p.usd);
+        let value_usd = price.map(|p| p * amount).unwrap_or_default();
+        match price {
+            Some(_) => valuation.total_value_usd += value_usd,
+            None => valuation.unpriced.push(token.clone()),
+        }
+
+        valuation.assets.push(WatchOnlyAsset {
+            token: token.clone(),
+            balance: balance.to_string(),
+            amount,
+            price_usd: price,
+            value_usd,
+        });
+    }
+
+    Ok(valuation)
+}
+
+pub async fn snapshot_all(prices: &PriceService) -> std::result::Result<usize, String> {
+    let profiles = ProfileDatabase::new()
+        .and_then(|db| db.list())
+        .map_err(|e| e.to_string())?;
+    let db = PortfolioDatabase::new().map_err(|e| e.to_string())?;
+
+    let mut recorded = 0;
+    for profile in profiles.iter().filter(|p| !p.wallet.is_empty()) {
+        match value_portfolio(prices, &profile.user_id, true).await {
+            Ok(valuation) => {
+                db.record_snapshot(&valuation).map_err(|e| e.to_string())?;
+                recorded += 1;
+            }
+            Err((_, e)) => println!("Portfolio snapshot failed for {}: {}", profile.user_id, e),
+        }
+    }
+
+    Ok(recorded)
+}
+
+pub async fn run_snapshot_job(prices: Arc<PriceService>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(PORTFOLIO_SNAPSHOT_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = snapshot_all(prices.as_ref()).await {
+            println!("Portfolio snapshot job failed: {}", e);
+        }
+    }
+}
+
+pub async fn get_portfolio(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+) -> std::result::Result<Json<PortfolioValuation>, (StatusCode, String)> {
+    let valuation = value_portfolio(state.prices.as_ref(), &user_id, false).await?;
+    Ok(Json(valuation))
+}
+
+/// Books balance changes made outside the API into the positions now,
+/// rather than at the next snapshot. Nothing is booked while a swap or
+/// transfer is settling; `settling` is set in the response then.
+pub async fn reconcile_portfolio(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+) -> std::result::Result<Json<PortfolioValuation>, (StatusCode, String)> {
+    let valuation = value_portfolio(state.prices.as_ref(), &user_id, true).await?;
+    Ok(Json(valuation))
+}
+
+pub async fn get_portfolio_history(
+    Path(user_id): Path<String>,
+    Query(query): Query<HistoryQuery>,
+) -> std::result::Result<Json<HistoryResponse>, (StatusCode, String)> {
+    let days = query
+        .days
+        .unwrap_or(PORTFOLIO_HISTORY_DEFAULT_DAYS)
+        .clamp(1, PORTFOLIO_HISTORY_MAX_DAYS);
+    let since = utils::now() - days * 24 * 60 * 60;
+
+    let points = PortfolioDatabase::new()
+        .and_then(|db| db.history(&user_id, since))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to load portfolio history: {}", e),
+            )
+        })?;
+
+    Ok(Json(HistoryResponse { user_id, points }))
+}
@@ -0,0 +1,378 @@
+#![allow(dead_code)]
+
+use crate::chain;
+use crate::constants::{
+    DEFAULT_CHAIN, PRICES_JSON_PATH, PRICE_CACHE_TTL_SECS, PRICE_MAX_STALENESS_SECS,
+    PRICE_POOLS_JSON_PATH, PRICE_QUOTE_FROM_ADDRESS,
+};
+use crate::defi::magpiefi::MagpieClient;
+use crate::defi::models::QuoteParams;
+use crate::models::AppState;
+use crate::utils;
+use async_trait::async_trait;
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    Json,
+};
+use ethers::{
+    prelude::*,
+    types::{Address, U256},
+};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::env;
+use std::fs;
+use tokio::sync::Mutex;
+
+abigen!(
+    UniswapV2Pair,
+    r#"[
+        function token0() external view returns (address)
+        function token1() external view returns (address)
+        function getReserves() external view returns (uint112, uint112, uint32)
+    ]"#
+);
+
+/// Source of USD token prices used to value balances.
+#[async_trait]
+pub trait PriceSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn usd_price(&self, chain: &str, token: &str) -> Option<f64>;
+}
+
+/// Prices read from `prices.json`, keyed by lowercase token address. Used as
+/// manual overrides ahead of every other source.
+pub struct StaticPrices {
+    prices: HashMap<String, f64>,
+}
+
+impl StaticPrices {
+    pub fn load(path: &str) -> Self {
+        let prices: HashMap<String, f64> = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+
+        Self {
+            prices: prices
+                .into_iter()
+                .map(|(token, price)| (token.to_lowercase(), price))
+                .collect(),
+        }
+    }
+}
+
+#[async_trait]
+impl PriceSource for StaticPrices {
+    fn name(&self) -> &'static str {
+        "override"
+    }
+
+    async fn usd_price(&self, _chain: &str, token: &str) -> Option<f64> {
+        self.prices.get(&token.to_lowercase()).copied()
+    }
+}
+
+/// Quotes one whole token against a USD stable token through the aggregator.
+/// Magpie is configured for a single network, so only the default chain is
+/// priced.
+pub struct MagpieQuoteSource {
+    magpie: MagpieClient,
+    stable_token: String,
+}
+
+impl MagpieQuoteSource {
+    /// Enabled when `PRICE_STABLE_TOKEN` is set.
+    pub fn from_env(magpie: MagpieClient) -> Option<Self> {
+        let stable_token = env::var("PRICE_STABLE_TOKEN").ok()?;
+        if stable_token.is_empty() {
+            return None;
+        }
+        Some(Self {
+            magpie,
+            stable_token,
+        })
+    }
+
+    async fn quote(&self, token: &str) -> std::result::Result<f64, String> {
+        let provider = chain::provider_for(DEFAULT_CHAIN)?;
+        let decimals = chain::token_decimals(provider.clone(), token).await?;
+        let stable_decimals = chain::token_decimals(provider, &self.stable_token).await?;
+        let amount = U256::exp10(decimals as usize);
+
+        let params = QuoteParams {
+            from_token_address: token.to_string(),
+            to_token_address: self.stable_token.clone(),
+            amount: amount.to_string(),
+            slippage: "0.005".to_string(),
+            from_address: PRICE_QUOTE_FROM_ADDRESS.to_string(),
+            to_address: PRICE_QUOTE_FROM_ADDRESS.to_string(),
+            gasless: false,
+            affiliate_address: None,
+            affiliate_fee: None,
+        };
+        let quote = self
+            .magpie
+            .get_quote(&params)
+            .await
+            .map_err(|e| format!("Quote failed: {}", e))?;
+        let out = U256::from_dec_str(&quote.to_token_amount)
+            .map_err(|_| format!("Invalid quote amount: {}", quote.to_token_amount))?;
+
+        Ok(chain::to_units(out, stable_decimals))
+    }
+}
+
+#[async_trait]
+impl PriceSource for MagpieQuoteSource {
+    fn name(&self) -> &'static str {
+        "aggregator"
+    }
+
+    async fn usd_price(&self, chain: &str, token: &str) -> Option<f64> {
+        if !chain.eq_ignore_ascii_case(DEFAULT_CHAIN) {
+            return None;
+        }
+        if token.eq_ignore_ascii_case(&self.stable_token) {
+            return Some(1.0);
+        }
+
+        match self.quote(token).await {
+            Ok(price) if price > 0.0 => Some(price),
+            Ok(_) => None,
+            Err(e) => {
+                println!("Aggregator price for {} failed: {}", token, e);
+                None
+            }
+        }
+    }
+}
+
+/// Pool used to price `token`, quoted in `quote_token`.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct PricePool {
+    pub chain: String,
+    pub token: String,
+    /// Uniswap V2 style pair holding `token` and `quote_token`.
+    pub pool: String,
+    pub quote_token: String,
+    /// USD price of `quote_token`; 1.0 for stable coins.
+    #[serde(default = "default_quote_price")]
+    pub quote_price: f64,
+}
+
+fn default_quote_price() -> f64 {
+    1.0
+}
+
+/// Spot price from the reserves of a configured constant-product pool.
+pub struct DexPoolSource {
+    pools: Vec<PricePool>,
+}
+
+impl DexPoolSource {
+    pub fn load(path: &str) -> Self {
+        let pools = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { pools }
+    }
+
+    async fn spot_price(&self, pool: &PricePool) -> std::result::Result<f64, String> {
+        let provider = chain::provider_for(&pool.chain)?;
+        let address = pool
+            .pool
+            .parse::<Address>()
+            .map_err(|_| format!("Invalid pool address: {}", pool.pool))?;
+        let pair = UniswapV2Pair::new(address, provider.clone());
+
+        let token0 = pair.token_0().call().await.map_err(|e| e.to_string())?;
+        let (reserve0, reserve1, _) = pair
+            .get_reserves()
+            .call()
+            .await
+            .map_err(|e| e.to_string())?;
+        let token = pool
+            .token
+            .parse::<Address>()
+            .map_err(|_| format!("Invalid token address: {}", pool.token))?;
+        let (token_reserve, quote_reserve) = if token0 == token {
+            (reserve0, reserve1)
+        } else {
+            (reserve1, reserve0)
+        };
+
+        let token_decimals = chain::token_decimals(provider.clone(), &pool.token).await?;
+        let quote_decimals = chain::token_decimals(provider, &pool.quote_token).await?;
+        let token_amount = chain::to_units(U256::from(token_reserve), token_decimals);
+        let quote_amount = chain::to_units(U256::from(quote_reserve), quote_decimals);
+        if token_amount <= 0.0 {
+            return Err(format!("Pool {} has no liquidity", pool.pool));
+        }
+
+        Ok(quote_amount / token_amount * pool.quote_price)
+    }
+}
+
+#[async_trait]
+impl PriceSource for DexPoolSource {
+    fn name(&self) -> &'static str {
+        "dex"
+    }
+
+    async fn usd_price(&self, chain: &str, token: &str) -> Option<f64> {
+        let pool = self
+            .pools
+            .iter()
+            .find(|p| p.chain.eq_ignore_ascii_case(chain) && p.token.eq_ignore_ascii_case(token))?;
+
+        match self.spot_price(pool).await {
+            Ok(price) => Some(price),
+            Err(e) => {
+                println!("Pool price for {} failed: {}", token, e);
+                None
+            }
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct TokenPrice {
+    pub chain: String,
+    pub token: String,
+    pub usd: f64,
+    pub source: String,
+    pub updated_at: i64,
+    /// True when every source failed and a cached price within the staleness
+    /// limit was served instead.
+    pub stale: bool,
+}
+
+/// Asks each source in order and caches the first answer. Cached prices are
+/// reused for `PRICE_CACHE_TTL_SECS` and, if a refresh fails, served as stale
+/// for up to `PRICE_MAX_STALENESS_SECS`.
+pub struct PriceService {
+    sources: Vec<Box<dyn PriceSource>>,
+    cache: Mutex<HashMap<(String, String), TokenPrice>>,
+}
+
+impl PriceService {
+    pub fn new(sources: Vec<Box<dyn PriceSource>>) -> Self {
+        Self {
+            sources,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn from_env(magpie: MagpieClient) -> Self {
+        let mut sources: Vec<Box<dyn PriceSource>> =
+            vec![Box::new(StaticPrices::load(PRICES_JSON_PATH))];
+        if let Some(source) = MagpieQuoteSource::from_env(magpie) {
+            sources.push(Box::new(source));
+        }
+        sources.push(Box::new(DexPoolSource::load(PRICE_POOLS_JSON_PATH)));
+        Self::new(sources)
+    }
+
+    pub async fn price(&self, chain: &str, token: &str) -> Option<TokenPrice> {
+        let key = (chain.to_lowercase(), token.to_lowercase());
+        let now = utils::now();
+
+        let cached = self.cache.lock().await.get(&key).cloned();
+        if let Some(price) = &cached {
+            if now - price.updated_at < PRICE_CACHE_TTL_SECS {
+                return Some(price.clone());
+            }
+        }
+
+        for source in &self.sources {
+            if let Some(usd) = source.usd_price(&key.0, &key.1).await {
+                let price = TokenPrice {
+                    chain: key.0.clone(),
+                    token: key.1.clone(),
+                    usd,
+                    source: source.name().to_string(),
+                    updated_at: now,
+                    stale: false,
+                };
+                self.cache.lock().await.insert(key, price.clone());
+                return Some(price);
+            }
+        }
+
+        cached
+            .filter(|price| now - price.updated_at < PRICE_MAX_STALENESS_SECS)
+            .map(|price| TokenPrice {
+                stale: true,
+                ..price
+            })
+    }
+}
+
+#[async_trait]
+impl PriceSource for PriceService {
+    fn name(&self) -> &'static str {
+        "oracle"
+    }
+
+    async fn usd_price(&self, chain: &str, token: &str) -> Option<f64> {
+        self.price(chain, token).await.map(|price| price.usd)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PriceQuery {
+    /// Comma separated token addresses.
+    pub tokens: String,
+    pub chain: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PriceResponse {
+    pub prices: Vec<TokenPrice>,
+    /// Tokens no source could price.
+    pub missing: Vec<String>,
+}
+
+pub async fn get_prices(
+    State(state): State<AppState>,
+    Query(query): Query<PriceQuery>,
+) -> Result<Json<PriceResponse>, (StatusCode, String)> {
+    let chain = query.chain.unwrap_or(DEFAULT_CHAIN.to_string());
+    let tokens: Vec<&str> = query
+        .tokens
+        .split(',')
+        .map(|t| t.trim())
+        .filter(|t| !t.is_empty())
+        .collect();
+    if tokens.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "At least one token is required".to_string(),
+        ));
+    }
+    for token in &tokens {
+        if !chain::is_native_token(token) && token.parse::<Address>().is_err() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid token address: {}", token),
+            ));
+        }
+    }
+
+    let mut response = PriceResponse {
+        prices: Vec::new(),
+        missing: Vec::new(),
+    };
+    for token in tokens {
+        match state.prices.price(&chain, token).await {
+            Some(price) => response.prices.push(price),
+            None => response.missing.push(token.to_lowercase()),
+        }
+    }
+
+    Ok(Json(response))
+}
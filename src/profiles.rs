@@ -71,6 +71,35 @@ impl ProfileDatabase {
         Ok(profile)
     }
 
+    pub fn get_by_wallet(&self, wallet: &str) -> Result<Option<Profile>> {
+        let profile = self
+            .conn
+            .query_row(
+                "SELECT id, user_id, username, name, wallet FROM profiles WHERE wallet = ?1 COLLATE NOCASE",
+                params![wallet],
+                |row| {
+                    Ok(Profile {
+                        id: row.get(0)?,
+                        user_id: row.get(1)?,
+                        username: row.get(2)?,
+                        name: row.get(3)?,
+                        wallet: row.get(4)?,
+                    })
+                },
+            )
+            .optional()?;
+
+        Ok(profile)
+    }
+
+    pub fn username_taken(&self, username: &str) -> Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS(SELECT 1 FROM profiles WHERE username = ?1)",
+            params![username],
+            |row| row.get(0),
+        )
+    }
+
     pub fn update(&self, profile: &Profile) -> Result<()> {
         self.conn.execute(
             "UPDATE profiles SET username = ?1, name = ?2, wallet = ?3 WHERE user_id = ?4",
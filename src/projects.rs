@@ -0,0 +1,822 @@
+#![allow(dead_code)]
+
+use crate::admin;
+use crate::constants::{DB_PATH, DEFAULT_CHAIN, PROJECTS_DEFAULT_LIMIT, PROJECTS_MAX_LIMIT};
+use crate::github_activity::GithubActivityDatabase;
+use crate::models::{
+    GithubActivity, Project, ProjectCategory, ProjectPage, ProjectQuery, ProjectSummary,
+};
+use crate::sentiment;
+use crate::token_metadata;
+use crate::tvl;
+use crate::whitepaper::WhitepaperDatabase;
+use axum::{
+    extract::{Path, Query},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use ethers::{types::Address, utils::to_checksum};
+use reqwest::Url;
+use rusqlite::OptionalExtension;
+use rusqlite::{params, params_from_iter, Connection, Result, Row, ToSql};
+use std::fs;
+
+const PROJECT_COLUMNS: &str = "id, chain, name, description, website, logo_uri, symbol, decimals, \
+     address, category, created_at, updated_at";
+
+const PROJECT_SUMMARIES_SCHEMA: &str = "CREATE TABLE IF NOT EXISTS project_summaries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        project_id INTEGER NOT NULL REFERENCES projects (id),
+        chain TEXT NOT NULL,
+        name TEXT NOT NULL,
+        tvl REAL NOT NULL,
+        sentiment TEXT NOT NULL,
+        whitepaper_summary TEXT NOT NULL,
+        commits_last_30_days INTEGER NOT NULL,
+        contributors INTEGER NOT NULL,
+        repo_url TEXT NOT NULL,
+        addresses TEXT NOT NULL,
+        updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+        UNIQUE (project_id, chain)
+    )";
+
+pub struct ProjectDatabase {
+    pub conn: Connection,
+}
+
+impl ProjectDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS projects (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                chain TEXT NOT NULL,
+                name TEXT NOT NULL,
+                description TEXT NOT NULL,
+                website TEXT NOT NULL,
+                logo_uri TEXT NOT NULL,
+                symbol TEXT NOT NULL,
+                decimals INTEGER NOT NULL,
+                address TEXT NOT NULL,
+                category TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                UNIQUE (chain, name)
+            )",
+            [],
+        )?;
+        // Search joins summaries for TVL and activity sorting.
+        conn.execute(PROJECT_SUMMARIES_SCHEMA, [])?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS project_imports (
+                source TEXT PRIMARY KEY,
+                imported_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+
+        let fts_exists: bool = conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM sqlite_master WHERE name = 'projects_fts')",
+            [],
+            |row| row.get(0),
+        )?;
+        if !fts_exists {
+            conn.execute_batch(
+                "CREATE VIRTUAL TABLE projects_fts USING fts5 (
+                    name, description, content = 'projects', content_rowid = 'id'
+                );
+                CREATE TRIGGER projects_fts_insert AFTER INSERT ON projects BEGIN
+                    INSERT INTO projects_fts (rowid, name, description)
+                    VALUES (new.id, new.name, new.description);
+                END;
+                CREATE TRIGGER projects_fts_delete AFTER DELETE ON projects BEGIN
+                    INSERT INTO projects_fts (projects_fts, rowid, name, description)
+                    VALUES ('delete', old.id, old.name, old.description);
+                END;
+                CREATE TRIGGER projects_fts_update AFTER UPDATE ON projects BEGIN
+                    INSERT INTO projects_fts (projects_fts, rowid, name, description)
+                    VALUES ('delete', old.id, old.name, old.description);
+                    INSERT INTO projects_fts (rowid, name, description)
+                    VALUES (new.id, new.name, new.description);
+                END;
+                INSERT INTO projects_fts (projects_fts) VALUES ('rebuild');",
+            )?;
+        }
+
+        Ok(ProjectDatabase { conn })
+    }
+
+    pub fn create(&self, project: &Project) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO projects (chain, name, description, website, logo_uri, symbol, decimals, address, category)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+            params![
+                project.chain,
+                project.name,
+                project.description,
+                project.website,
+                project.logo_uri,
+                project.symbol,
+                project.decimals,
+                project.address,
+                project.category.as_str()
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn get(&self, id: i64) -> Result<Option<Project>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM projects WHERE id = ?1", PROJECT_COLUMNS),
+                params![id],
+                from_row,
+            )
+            .optional()
+    }
+
+    pub fn update(&self, id: i64, project: &Project) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE projects SET chain = ?1, name = ?2, description = ?3, website = ?4, logo_uri = ?5,
+                symbol = ?6, decimals = ?7, address = ?8, category = ?9, updated_at = strftime('%s', 'now')
+             WHERE id = ?10",
+            params![
+                project.chain,
+                project.name,
+                project.description,
+                project.website,
+                project.logo_uri,
+                project.symbol,
+                project.decimals,
+                project.address,
+                project.category.as_str(),
+                id
+            ],
+        )?;
+        Ok(updated == 1)
+    }
+
+    pub fn delete(&self, id: i64) -> Result<bool> {
+        let deleted = self
+            .conn
+            .execute("DELETE FROM projects WHERE id = ?1", params![id])?;
+        Ok(deleted == 1)
+    }
+
+    pub fn list(&self) -> Result<Vec<Project>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM projects ORDER BY id",
+            PROJECT_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], from_row)?;
+        rows.collect()
+    }
+
+    /// Filters and sorts the catalog, returning one page and the cursor for the
+    /// next one. Cursors encode the last row's sort key and id (keyset paging).
+    pub fn search(
+        &self,
+        query: &ProjectQuery,
+    ) -> std::result::Result<ProjectPage, (StatusCode, String)> {
+        let sort = ProjectSort::parse(query.sort.as_deref()).map_err(bad_request)?;
+        let limit = query
+            .limit
+            .unwrap_or(PROJECTS_DEFAULT_LIMIT)
+            .clamp(1, PROJECTS_MAX_LIMIT);
+
+        let mut sql = format!(
+            "SELECT {}, {} AS sort_key FROM projects p
+             LEFT JOIN project_summaries s ON s.project_id = p.id AND s.chain = p.chain",
+            PROJECT_COLUMNS
+                .split(", ")
+                .map(|c| format!("p.{}", c))
+                .collect::<Vec<_>>()
+                .join(", "),
+            sort.key()
+        );
+        let mut conditions: Vec<String> = Vec::new();
+        let mut args: Vec<Box<dyn ToSql>> = Vec::new();
+
+        if let Some(text) = query.q.as_deref().and_then(fts_query) {
+            sql.push_str(" JOIN projects_fts f ON f.rowid = p.id");
+            conditions.push("projects_fts MATCH ?".to_string());
+            args.push(Box::new(text));
+        }
+        if let Some(category) = &query.category {
+            let category = category.parse::<ProjectCategory>().map_err(bad_request)?;
+            conditions.push("p.category = ?".to_string());
+            args.push(Box::new(category.as_str()));
+        }
+        if let Some(chain) = &query.chain {
+            conditions.push("p.chain = ?".to_string());
+            args.push(Box::new(chain.to_lowercase()));
+        }
+        if let Some(cursor) = &query.cursor {
+            let (key, id) = decode_cursor(cursor).map_err(bad_request)?;
+            conditions.push(format!(
+                "({key} {op} ? OR ({key} = ? AND p.id > ?))",
+                key = sort.key(),
+                op = if sort.descending() { "<" } else { ">" }
+            ));
+            match sort {
+                ProjectSort::Name => {
+                    args.push(Box::new(key.clone()));
+                    args.push(Box::new(key));
+                }
+                _ => {
+                    let key = key
+                        .parse::<f64>()
+                        .map_err(|_| bad_request("Invalid cursor".to_string()))?;
+                    args.push(Box::new(key));
+                    args.push(Box::new(key));
+                }
+            }
+            args.push(Box::new(id));
+        }
+
+        if !conditions.is_empty() {
+            sql.push_str(" WHERE ");
+            sql.push_str(&conditions.join(" AND "));
+        }
+        sql.push_str(&format!(
+            " ORDER BY sort_key {}, p.id LIMIT ?",
+            if sort.descending() { "DESC" } else { "ASC" }
+        ));
+        args.push(Box::new(limit + 1));
+
+        let mut stmt = self.conn.prepare(&sql).map_err(db_error)?;
+        let rows = stmt
+            .query_map(params_from_iter(args.iter()), |row| {
+                let key: rusqlite::types::Value = row.get(12)?;
+                Ok((from_row(row)?, key))
+            })
+            .map_err(db_error)?;
+        let mut rows = rows.collect::<Result<Vec<_>>>().map_err(db_error)?;
+
+        let next_cursor = if rows.len() as i64 > limit {
+            rows.truncate(limit as usize);
+            rows.last()
+                .map(|(project, key)| encode_cursor(key, project.id.unwrap_or_default()))
+        } else {
+            None
+        };
+
+        Ok(ProjectPage {
+            projects: rows.into_iter().map(|(project, _)| project).collect(),
+            next_cursor,
+        })
+    }
+
+    pub fn get_by_name(&self, chain: &str, name: &str) -> Result<Option<Project>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM projects WHERE chain = ?1 AND name = ?2",
+                    PROJECT_COLUMNS
+                ),
+                params![chain, name],
+                from_row,
+            )
+            .optional()
+    }
+
+    pub fn count(&self) -> Result<i64> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM projects", [], |row| row.get(0))
+    }
+
+    /// Whether the seed file at `source` was already imported.
+    pub fn imported(&self, source: &str) -> Result<bool> {
+        self.conn
+            .prepare("SELECT 1 FROM project_imports WHERE source = ?1")?
+            .exists(params![source])
+    }
+
+    pub fn mark_imported(&self, source: &str) -> Result<()> {
+        self.conn.execute(
+            "INSERT OR IGNORE INTO project_imports (source) VALUES (?1)",
+            params![source],
+        )?;
+        Ok(())
+    }
+}
+
+pub struct ProjectSummaryDatabase {
+    pub conn: Connection,
+}
+
+impl ProjectSummaryDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(PROJECT_SUMMARIES_SCHEMA, [])?;
+
+        Ok(ProjectSummaryDatabase { conn })
+    }
+
+    pub fn get(&self, project_id: i64, chain: &str) -> Result<Option<ProjectSummary>> {
+        self.conn
+            .query_row(
+                "SELECT project_id, chain, name, tvl, sentiment, whitepaper_summary,
+                    commits_last_30_days, contributors, repo_url, addresses, updated_at
+                 FROM project_summaries WHERE project_id = ?1 AND chain = ?2",
+                params![project_id, chain],
+                |row| {
+                    let addresses: String = row.get(9)?;
+                    Ok(ProjectSummary {
+                        project_id: row.get(0)?,
+                        chain: row.get(1)?,
+                        name: row.get(2)?,
+                        tvl: row.get(3)?,
+                        sentiment: row.get(4)?,
+                        whitepaper_summary: row.get(5)?,
+                        github_activity: GithubActivity {
+                            commits_last_30_days: row.get(6)?,
+                            contributors: row.get(7)?,
+                            repo_url: row.get(8)?,
+                        },
+                        address: serde_json::from_str(&addresses).unwrap_or_default(),
+                        updated_at: row.get(10)?,
+                        tvl_history: Vec::new(),
+                        sentiment_score: None,
+                        whitepaper_url: None,
+                    })
+                },
+            )
+            .optional()
+    }
+
+    pub fn upsert(&self, summary: &ProjectSummary) -> Result<()> {
+        let addresses = serde_json::to_string(&summary.address).unwrap_or_default();
+        self.conn.execute(
+            "INSERT INTO project_summaries (project_id, chain, name, tvl, sentiment, whitepaper_summary,
+                commits_last_30_days, contributors, repo_url, addresses)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+             ON CONFLICT (project_id, chain) DO UPDATE SET
+                name = excluded.name, tvl = excluded.tvl, sentiment = excluded.sentiment,
+                whitepaper_summary = excluded.whitepaper_summary,
+                commits_last_30_days = excluded.commits_last_30_days,
+                contributors = excluded.contributors, repo_url = excluded.repo_url,
+                addresses = excluded.addresses, updated_at = strftime('%s', 'now')",
+            params![
+                summary.project_id,
+                summary.chain,
+                summary.name,
+                summary.tvl,
+                summary.sentiment,
+                summary.whitepaper_summary,
+                summary.github_activity.commits_last_30_days,
+                summary.github_activity.contributors,
+                summary.github_activity.repo_url,
+                addresses
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Stores the latest computed TVL; a no-op without a summary row.
+    pub fn set_tvl(&self, project_id: i64, chain: &str, tvl: f64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE project_summaries SET tvl = ?1, updated_at = strftime('%s', 'now')
+             WHERE project_id = ?2 AND chain = ?3",
+            params![tvl, project_id, chain],
+        )?;
+        Ok(())
+    }
+
+    /// Stores collected activity on every summary of the project that
+    /// points at `repo_url`.
+    pub fn set_activity(
+        &self,
+        project_id: i64,
+        repo_url: &str,
+        commits_last_30_days: u64,
+        contributors: u64,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE project_summaries SET commits_last_30_days = ?1, contributors = ?2,
+                updated_at = strftime('%s', 'now')
+             WHERE project_id = ?3 AND repo_url = ?4",
+            params![commits_last_30_days, contributors, project_id, repo_url],
+        )?;
+        Ok(())
+    }
+
+    pub fn delete_for_project(&self, project_id: i64) -> Result<()> {
+        self.conn.execute(
+            "DELETE FROM project_summaries WHERE project_id = ?1",
+            params![project_id],
+        )?;
+        Ok(())
+    }
+
+    /// Distinct repositories referenced by summaries, per project.
+    pub fn repositories(&self) -> Result<Vec<(i64, String)>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT DISTINCT project_id, repo_url FROM project_summaries
+             WHERE repo_url != '' ORDER BY project_id",
+        )?;
+        let rows = stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?;
+        rows.collect()
+    }
+
+    pub fn count(&self) -> Result<i64> {
+        self.conn
+            .query_row("SELECT COUNT(*) FROM project_summaries", [], |row| {
+                row.get(0)
+            })
+    }
+}
+
+fn from_row(row: &Row) -> Result<Project> {
+    let category: String = row.get(9)?;
+    Ok(Project {
+        id: row.get(0)?,
+        chain: row.get(1)?,
+        name: row.get(2)?,
+        description: row.get(3)?,
+        website: row.get(4)?,
+        logo_uri: row.get(5)?,
+        symbol: row.get(6)?,
+        decimals: row.get(7)?,
+        address: row.get(8)?,
+        category: category.parse().unwrap_or_default(),
+        created_at: row.get(10)?,
+        updated_at: row.get(11)?,
+        token_metadata: None,
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ProjectSort {
+    Name,
+    Tvl,
+    Activity,
+}
+
+impl ProjectSort {
+    fn parse(sort: Option<&str>) -> std::result::Result<Self, String> {
+        match sort.unwrap_or("name") {
+            "name" => Ok(ProjectSort::Name),
+            "tvl" => Ok(ProjectSort::Tvl),
+            "activity" => Ok(ProjectSort::Activity),
+            other => Err(format!("Unknown sort: {}", other)),
+        }
+    }
+
+    fn key(&self) -> &'static str {
+        match self {
+            ProjectSort::Name => "p.name",
+            ProjectSort::Tvl => "COALESCE(s.tvl, 0)",
+            // Recent activity is the GitHub commit count over the last 30 days.
+            ProjectSort::Activity => "COALESCE(s.commits_last_30_days, 0)",
+        }
+    }
+
+    fn descending(&self) -> bool {
+        *self != ProjectSort::Name
+    }
+}
+
+fn encode_cursor(key: &rusqlite::types::Value, id: i64) -> String {
+    let key = match key {
+        rusqlite::types::Value::Text(text) => text.clone(),
+        rusqlite::types::Value::Integer(value) => value.to_string(),
+        rusqlite::types::Value::Real(value) => value.to_string(),
+        _ => String::new(),
+    };
+    hex::encode(format!("{}\n{}", id, key))
+}
+
+fn decode_cursor(cursor: &str) -> std::result::Result<(String, i64), String> {
+    let decoded = hex::decode(cursor)
+        .ok()
+        .and_then(|bytes| String::from_utf8(bytes).ok())
+        .ok_or("Invalid cursor")?;
+    let (id, key) = decoded.split_once('\n').ok_or("Invalid cursor")?;
+    let id = id.parse::<i64>().map_err(|_| "Invalid cursor")?;
+    Ok((key.to_string(), id))
+}
+
+/// Turns free text into an FTS5 prefix query, dropping characters FTS5
+/// would treat as syntax.
+fn fts_query(text: &str) -> Option<String> {
+    let terms: Vec<String> = text
+        .split_whitespace()
+        .map(|term| {
+            term.chars()
+                .filter(|c| c.is_alphanumeric())
+                .collect::<String>()
+        })
+        .filter(|term| !term.is_empty())
+        .map(|term| format!("\"{}\"*", term))
+        .collect();
+
+    if terms.is_empty() {
+        None
+    } else {
+        Some(terms.join(" "))
+    }
+}
+
+/// Normalizes a project in place and rejects invalid fields. Addresses are
+/// stored in EIP-55 form; mixed-case input must already carry a valid checksum.
+pub fn validate_project(project: &mut Project) -> std::result::Result<(), String> {
+    project.name = project.name.trim().to_string();
+    if project.name.is_empty() {
+        return Err("Project name is required".to_string());
+    }
+
+    project.chain = project.chain.trim().to_lowercase();
+    if project.chain.is_empty() {
+        project.chain = DEFAULT_CHAIN.to_string();
+    }
+
+    let address = project.address.trim();
+    if !address.is_empty() {
+        let parsed = address
+            .parse::<Address>()
+            .map_err(|_| format!("Invalid address: {}", address))?;
+        let checksummed = to_checksum(&parsed, None);
+        let hex = address.trim_start_matches("0x");
+        let mixed_case = hex.chars().any(|c| c.is_ascii_lowercase())
+            && hex.chars().any(|c| c.is_ascii_uppercase());
+        if mixed_case && address != checksummed {
+            return Err(format!("Address checksum mismatch: {}", address));
+        }
+        project.address = checksummed;
+    }
+
+    if !is_http_url(&project.logo_uri) {
+        return Err(format!("Invalid logo_uri: {}", project.logo_uri));
+    }
+    if !project.website.is_empty() && !is_http_url(&project.website) {
+        return Err(format!("Invalid website: {}", project.website));
+    }
+
+    if project.decimals > 36 {
+        return Err(format!("Invalid decimals: {}", project.decimals));
+    }
+
+    Ok(())
+}
+
+fn is_http_url(value: &str) -> bool {
+    matches!(Url::parse(value), Ok(url) if url.scheme() == "https" || url.scheme() == "http")
+}
+
+/// Seeds the catalog from the legacy JSON file once; emptying the catalog
+/// later does not bring the seed back. Entries that fail validation are
+/// skipped and reported.
+pub fn import_from_json(path: &str) -> std::result::Result<usize, String> {
+    let db = ProjectDatabase::new().map_err(|e| e.to_string())?;
+    if db.imported(path).map_err(|e| e.to_string())? {
+        return Ok(0);
+    }
+    // Seeded before imports were recorded.
+    if db.count().map_err(|e| e.to_string())? > 0 {
+        db.mark_imported(path).map_err(|e| e.to_string())?;
+        return Ok(0);
+    }
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return Ok(0),
+    };
+    let items: Vec<Project> =
+        serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", path, e))?;
+
+    let tx = db.conn.unchecked_transaction().map_err(|e| e.to_string())?;
+    let mut imported = 0;
+    for mut project in items {
+        if let Err(e) = validate_project(&mut project) {
+            println!("Skipping project {}: {}", project.name, e);
+            continue;
+        }
+        db.create(&project).map_err(|e| e.to_string())?;
+        imported += 1;
+    }
+    db.mark_imported(path).map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+
+    Ok(imported)
+}
+
+/// Seeds the legacy single `project.json` summary onto the catalog entry with
+/// the same name on the default chain, once.
+pub fn import_summary_from_json(path: &str) -> std::result::Result<bool, String> {
+    let projects = ProjectDatabase::new().map_err(|e| e.to_string())?;
+    if projects.imported(path).map_err(|e| e.to_string())? {
+        return Ok(false);
+    }
+    let db = ProjectSummaryDatabase::new().map_err(|e| e.to_string())?;
+    if db.count().map_err(|e| e.to_string())? > 0 {
+        projects.mark_imported(path).map_err(|e| e.to_string())?;
+        return Ok(false);
+    }
+
+    let content = match fs::read_to_string(path) {
+        Ok(content) => content,
+        Err(_) => return Ok(false),
+    };
+    let mut summary: ProjectSummary =
+        serde_json::from_str(&content).map_err(|e| format!("Invalid {}: {}", path, e))?;
+
+    let project = projects
+        .get_by_name(DEFAULT_CHAIN, &summary.name)
+        .map_err(|e| e.to_string())?
+        .ok_or(format!(
+            "No project named {} to attach {} to",
+            summary.name, path
+        ))?;
+
+    summary.project_id = project.id;
+    summary.chain = project.chain;
+    db.upsert(&summary).map_err(|e| e.to_string())?;
+    projects.mark_imported(path).map_err(|e| e.to_string())?;
+
+    Ok(true)
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Project database error: {}", e),
+    )
+}
+
+fn bad_request(e: String) -> (StatusCode, String) {
+    (StatusCode::BAD_REQUEST, e)
+}
+
+fn write_error(e: rusqlite::Error) -> (StatusCode, String) {
+    match e {
+        rusqlite::Error::SqliteFailure(err, _)
+            if err.code == rusqlite::ErrorCode::ConstraintViolation =>
+        {
+            (
+                StatusCode::CONFLICT,
+                "A project with this name already exists on this chain".to_string(),
+            )
+        }
+        e => db_error(e),
+    }
+}
+
+/// The whole catalog as a plain array, as this endpoint always returned,
+/// or a page of it when any filter, sort or cursor is given.
+pub async fn get_projects(
+    Query(query): Query<ProjectQuery>,
+) -> Result<Response, (StatusCode, String)> {
+    if !query.is_empty() {
+        return Ok(Json(project_page(&query)?).into_response());
+    }
+
+    let db = ProjectDatabase::new().map_err(db_error)?;
+    let mut projects = db.list().map_err(db_error)?;
+    token_metadata::attach(&mut projects).map_err(db_error)?;
+    Ok(Json(projects).into_response())
+}
+
+/// Searches, filters and sorts the catalog a page at a time.
+pub async fn search_projects(
+    Query(query): Query<ProjectQuery>,
+) -> Result<Json<ProjectPage>, (StatusCode, String)> {
+    Ok(Json(project_page(&query)?))
+}
+
+fn project_page(query: &ProjectQuery) -> Result<ProjectPage, (StatusCode, String)> {
+    let db = ProjectDatabase::new().map_err(db_error)?;
+    let mut page = db.search(query)?;
+    token_metadata::attach(&mut page.projects).map_err(db_error)?;
+    Ok(page)
+}
+
+pub async fn create_project(
+    headers: HeaderMap,
+    Json(mut project): Json<Project>,
+) -> Result<Json<Project>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+    validate_project(&mut project).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let db = ProjectDatabase::new().map_err(db_error)?;
+    let id = db.create(&project).map_err(write_error)?;
+    let project = db.get(id).map_err(db_error)?.unwrap();
+
+    Ok(Json(project))
+}
+
+pub async fn update_project(
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+    Json(mut project): Json<Project>,
+) -> Result<Json<Project>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+    validate_project(&mut project).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let db = ProjectDatabase::new().map_err(db_error)?;
+    if !db.update(id, &project).map_err(write_error)? {
+        return Err((StatusCode::NOT_FOUND, "Project not found".to_string()));
+    }
+    let project = db.get(id).map_err(db_error)?.unwrap();
+
+    Ok(Json(project))
+}
+
+pub async fn delete_project(
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let db = ProjectDatabase::new().map_err(db_error)?;
+    if !db.delete(id).map_err(db_error)? {
+        return Err((StatusCode::NOT_FOUND, "Project not found".to_string()));
+    }
+    ProjectSummaryDatabase::new()
+        .and_then(|db| db.delete_for_project(id))
+        .map_err(db_error)?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn get_project_summary(
+    Path((chain, pid)): Path<(String, i64)>,
+) -> Result<Json<ProjectSummary>, (StatusCode, String)> {
+    let chain = chain.to_lowercase();
+    let db = ProjectSummaryDatabase::new().map_err(db_error)?;
+    let mut summary = db.get(pid, &chain).map_err(db_error)?.ok_or((
+        StatusCode::NOT_FOUND,
+        "Project summary not found".to_string(),
+    ))?;
+
+    summary.tvl_history = tvl::recent_history(pid, &chain).map_err(db_error)?;
+    if let Some(latest) = summary.tvl_history.last() {
+        summary.tvl = latest.tvl;
+    }
+
+    let activity = GithubActivityDatabase::new()
+        .and_then(|db| db.latest(pid, &summary.github_activity.repo_url))
+        .map_err(db_error)?;
+    if let Some(activity) = activity {
+        summary.github_activity = activity;
+    }
+
+    summary.sentiment_score = sentiment::aggregate(pid).map_err(db_error)?;
+    if let Some(score) = &summary.sentiment_score {
+        summary.sentiment = score.label.clone();
+    }
+
+    let whitepaper = WhitepaperDatabase::new()
+        .and_then(|db| db.get(pid))
+        .map_err(db_error)?;
+    if let Some(whitepaper) = whitepaper {
+        summary.whitepaper_url = Some(whitepaper.url());
+        // No sentence may qualify for the summary; keep the stored one then.
+        if !whitepaper.summary.trim().is_empty() {
+            summary.whitepaper_summary = whitepaper.summary;
+        }
+    }
+
+    Ok(Json(summary))
+}
+
+pub async fn upsert_project_summary(
+    headers: HeaderMap,
+    Path((pid, chain)): Path<(i64, String)>,
+    Json(mut summary): Json<ProjectSummary>,
+) -> Result<Json<ProjectSummary>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let project = ProjectDatabase::new()
+        .and_then(|db| db.get(pid))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Project not found".to_string()))?;
+
+    summary.project_id = project.id;
+    summary.chain = chain.trim().to_lowercase();
+    if summary.name.trim().is_empty() {
+        summary.name = project.name;
+    }
+    for (network, address) in summary.address.iter_mut() {
+        if address.is_empty() {
+            continue;
+        }
+        let parsed = address.parse::<Address>().map_err(|_| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Invalid {} address: {}", network, address),
+            )
+        })?;
+        *address = to_checksum(&parsed, None);
+    }
+
+    let db = ProjectSummaryDatabase::new().map_err(db_error)?;
+    db.upsert(&summary).map_err(db_error)?;
+    let summary = db.get(pid, &summary.chain).map_err(db_error)?.unwrap();
+
+    Ok(Json(summary))
+}
@@ -0,0 +1,137 @@
+use crate::constants::{DB_PATH, REAUTH_TOKEN_TTL_SECS, STREAM_TOKEN_TTL_SECS};
+use crate::utils;
+use axum::http::{HeaderMap, StatusCode};
+use rusqlite::{params, Connection, Result};
+use sha2::{Digest, Sha256};
+
+pub const REAUTH_TOKEN_HEADER: &str = "x-reauth-token";
+
+/// Purpose of a token that unlocks wallet import and export.
+pub const PURPOSE_WALLET: &str = "wallet";
+/// Purpose of a token that lets another sign-in provider be attached to, or
+/// removed from, a profile.
+pub const PURPOSE_LINK: &str = "link";
+/// Purpose of the token issued at every sign-in that opens the event
+/// streams. Unlike the others it is reusable until it expires, so clients
+/// can reconnect.
+pub const PURPOSE_STREAM: &str = "stream";
+
+pub fn is_known_purpose(purpose: &str) -> bool {
+    purpose == PURPOSE_WALLET || purpose == PURPOSE_LINK
+}
+
+/// Short-lived, single-use tokens proving the user just signed in again.
+/// Only a hash of each token is stored.
+pub struct ReauthDatabase {
+    pub conn: Connection,
+}
+
+impl ReauthDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS reauth_tokens (
+                token_hash TEXT PRIMARY KEY,
+                user_id TEXT NOT NULL,
+                purpose TEXT NOT NULL,
+                expires_at INTEGER NOT NULL,
+                used_at INTEGER
+            )",
+            [],
+        )?;
+
+        Ok(ReauthDatabase { conn })
+    }
+
+    pub fn issue(&self, user_id: &str, purpose: &str) -> Result<String> {
+        let token = hex::encode(rand::random::<[u8; 24]>());
+        self.conn.execute(
+            "DELETE FROM reauth_tokens WHERE expires_at < ?1",
+            params![utils::now()],
+        )?;
+        self.conn.execute(
+            "INSERT INTO reauth_tokens (token_hash, user_id, purpose, expires_at)
+             VALUES (?1, ?2, ?3, ?4)",
+            params![hash(&token), user_id, purpose, utils::now() + ttl(purpose)],
+        )?;
+        Ok(token)
+    }
+
+    /// Marks the token used if it is valid for `user_id` and `purpose`.
+    pub fn consume(&self, user_id: &str, purpose: &str, token: &str) -> Result<bool> {
+        let now = utils::now();
+        let updated = self.conn.execute(
+            "UPDATE reauth_tokens SET used_at = ?1
+             WHERE token_hash = ?2 AND user_id = ?3 AND purpose = ?4
+                AND used_at IS NULL AND expires_at >= ?1",
+            params![now, hash(token), user_id, purpose],
+        )?;
+        Ok(updated == 1)
+    }
+
+    /// Whether the token is valid for `user_id` and `purpose`, without
+    /// spending it.
+    pub fn verify(&self, user_id: &str, purpose: &str, token: &str) -> Result<bool> {
+        let count: i64 = self.conn.query_row(
+            "SELECT COUNT(*) FROM reauth_tokens
+             WHERE token_hash = ?1 AND user_id = ?2 AND purpose = ?3
+                AND used_at IS NULL AND expires_at >= ?4",
+            params![hash(token), user_id, purpose, utils::now()],
+            |row| row.get(0),
+        )?;
+        Ok(count > 0)
+    }
+}
+
+fn ttl(purpose: &str) -> i64 {
+    if purpose == PURPOSE_STREAM {
+        STREAM_TOKEN_TTL_SECS
+    } else {
+        REAUTH_TOKEN_TTL_SECS
+    }
+}
+
+fn hash(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Rejects the request unless it carries an unused re-authentication token
+/// issued to `user_id` for `purpose`. The token is spent either way.
+pub fn require_reauth(
+    headers: &HeaderMap,
+    user_id: &str,
+    purpose: &str,
+) -> std::result::Result<(), (StatusCode, String)> {
+    let token = headers
+        .get(REAUTH_TOKEN_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if token.is_empty() {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            format!(
+                "Re-authentication required: sign in again via /login/{}/<provider>?purpose={} \
+                 or /auth/siwe/login with purpose {}",
+                user_id, purpose, purpose
+            ),
+        ));
+    }
+
+    let valid = ReauthDatabase::new()
+        .and_then(|db| db.consume(user_id, purpose, token))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to check re-authentication: {}", e),
+            )
+        })?;
+    if !valid {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Re-authentication token is invalid or expired".to_string(),
+        ));
+    }
+
+    Ok(())
+}
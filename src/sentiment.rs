@@ -0,0 +1,464 @@
+#![allow(dead_code)]
+
+use crate::constants::{
+    DB_PATH, POSTS_JSON_PATH, SENTIMENT_INGEST_INTERVAL_SECS, SENTIMENT_WINDOW_SECS,
+    TWITTER_SEARCH_URL,
+};
+use crate::models::{Project, SentimentScore};
+use crate::projects::ProjectDatabase;
+use crate::utils;
+use async_trait::async_trait;
+use reqwest::Client;
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::env;
+use std::fs;
+use std::time::Duration;
+
+/// Word valences on a -4..4 scale, general terms plus crypto slang.
+const LEXICON: &[(&str, f64)] = &[
+    ("amazing", 4.0),
+    ("awesome", 3.5),
+    ("excellent", 3.5),
+    ("great", 3.0),
+    ("love", 3.0),
+    ("bullish", 3.0),
+    ("moon", 2.5),
+    ("mooning", 3.0),
+    ("impressive", 3.0),
+    ("innovative", 2.5),
+    ("good", 2.0),
+    ("nice", 2.0),
+    ("solid", 2.0),
+    ("strong", 2.0),
+    ("growth", 2.0),
+    ("growing", 2.0),
+    ("launch", 1.5),
+    ("launched", 1.5),
+    ("partnership", 2.0),
+    ("upgrade", 1.5),
+    ("audited", 2.0),
+    ("secure", 2.0),
+    ("gains", 2.0),
+    ("pump", 1.5),
+    ("gem", 2.5),
+    ("undervalued", 2.0),
+    ("recommend", 2.0),
+    ("like", 1.5),
+    ("happy", 2.5),
+    ("excited", 2.5),
+    ("win", 2.5),
+    ("success", 2.5),
+    ("useful", 2.0),
+    ("easy", 1.5),
+    ("fast", 1.5),
+    ("cheap", 1.0),
+    ("bad", -2.5),
+    ("terrible", -3.5),
+    ("awful", -3.5),
+    ("hate", -3.0),
+    ("bearish", -3.0),
+    ("scam", -4.0),
+    ("rug", -4.0),
+    ("rugged", -4.0),
+    ("rugpull", -4.0),
+    ("hack", -3.5),
+    ("hacked", -3.5),
+    ("exploit", -3.5),
+    ("exploited", -3.5),
+    ("drained", -3.5),
+    ("ponzi", -4.0),
+    ("fraud", -4.0),
+    ("dump", -2.5),
+    ("dumping", -2.5),
+    ("crash", -3.0),
+    ("crashed", -3.0),
+    ("dead", -3.0),
+    ("broken", -2.5),
+    ("bug", -1.5),
+    ("buggy", -2.5),
+    ("slow", -1.5),
+    ("expensive", -1.5),
+    ("overvalued", -2.0),
+    ("risky", -2.0),
+    ("fud", -1.5),
+    ("down", -1.0),
+    ("loss", -2.5),
+    ("losses", -2.5),
+    ("lost", -2.0),
+    ("fail", -2.5),
+    ("failed", -2.5),
+    ("delay", -1.5),
+    ("delayed", -1.5),
+    ("worried", -2.0),
+    ("avoid", -2.0),
+    ("worst", -3.5),
+    ("useless", -3.0),
+];
+
+const NEGATORS: &[&str] = &[
+    "not", "no", "never", "dont", "don't", "isnt", "isn't", "cant", "can't", "wont", "won't",
+    "without", "aint", "ain't",
+];
+
+const INTENSIFIERS: &[&str] = &[
+    "very",
+    "really",
+    "extremely",
+    "super",
+    "so",
+    "incredibly",
+    "totally",
+];
+
+/// How far a negator reaches forward, in tokens.
+const NEGATION_SCOPE: usize = 3;
+const NEGATION_FACTOR: f64 = -0.75;
+const INTENSIFIER_FACTOR: f64 = 1.3;
+/// Normalisation constant mapping raw valence sums into -1..1.
+const NORMALIZATION_ALPHA: f64 = 15.0;
+const NEUTRAL_THRESHOLD: f64 = 0.05;
+const TREND_THRESHOLD: f64 = 0.1;
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Post {
+    pub id: String,
+    #[serde(default)]
+    pub author: String,
+    pub text: String,
+    pub created_at: i64,
+}
+
+/// Where posts mentioning a project come from.
+#[async_trait]
+pub trait PostSource: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    async fn fetch(&self, project: &Project, since: i64) -> std::result::Result<Vec<Post>, String>;
+}
+
+/// Recent search on the Twitter v2 API using the app bearer token.
+pub struct TwitterSource {
+    client: Client,
+    bearer_token: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct TwitterSearchResponse {
+    #[serde(default)]
+    data: Vec<Tweet>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Tweet {
+    id: String,
+    text: String,
+    #[serde(default)]
+    author_id: String,
+    created_at: String,
+}
+
+impl TwitterSource {
+    pub fn new(bearer_token: String) -> Self {
+        Self {
+            client: Client::new(),
+            bearer_token,
+        }
+    }
+}
+
+#[async_trait]
+impl PostSource for TwitterSource {
+    fn name(&self) -> &'static str {
+        "twitter"
+    }
+
+    async fn fetch(&self, project: &Project, since: i64) -> std::result::Result<Vec<Post>, String> {
+        let mut terms = vec![format!("\"{}\"", project.name)];
+        if !project.symbol.is_empty() {
+            terms.push(project.symbol.clone());
+        }
+        let query = format!("({}) -is:retweet", terms.join(" OR "));
+        // Recent search only covers the last seven days.
+        let since = since.max(utils::now() - 7 * 24 * 60 * 60 + 60);
+        let start_time = chrono::DateTime::from_timestamp(since, 0)
+            .ok_or("Invalid timestamp")?
+            .to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+
+        let response = self
+            .client
+            .get(TWITTER_SEARCH_URL)
+            .bearer_auth(&self.bearer_token)
+            .query(&[
+                ("query", query.as_str()),
+                ("start_time", start_time.as_str()),
+                ("max_results", "100"),
+                ("tweet.fields", "created_at,author_id"),
+            ])
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if !response.status().is_success() {
+            return Err(format!("Twitter search returned {}", response.status()));
+        }
+
+        let body: TwitterSearchResponse = response.json().await.map_err(|e| e.to_string())?;
+        Ok(body
+            .data
+            .into_iter()
+            .map(|tweet| Post {
+                created_at: chrono::DateTime::parse_from_rfc3339(&tweet.created_at)
+                    .map(|t| t.timestamp())
+                    .unwrap_or_else(|_| utils::now()),
+                id: tweet.id,
+                author: tweet.author_id,
+                text: tweet.text,
+            })
+            .collect())
+    }
+}
+
+/// Posts read from `posts.json`, matched to projects by name or symbol.
+pub struct FilePostSource {
+    posts: Vec<Post>,
+}
+
+impl FilePostSource {
+    pub fn load(path: &str) -> Self {
+        let posts = fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok())
+            .unwrap_or_default();
+        Self { posts }
+    }
+}
+
+#[async_trait]
+impl PostSource for FilePostSource {
+    fn name(&self) -> &'static str {
+        "file"
+    }
+
+    async fn fetch(&self, project: &Project, since: i64) -> std::result::Result<Vec<Post>, String> {
+        let name = project.name.to_lowercase();
+        let symbol = project.symbol.to_lowercase();
+        Ok(self
+            .posts
+            .iter()
+            .filter(|post| post.created_at >= since)
+            .filter(|post| {
+                let text = post.text.to_lowercase();
+                text.contains(&name) || (!symbol.is_empty() && tokenize(&text).any(|t| t == symbol))
+            })
+            .cloned()
+            .collect())
+    }
+}
+
+/// Twitter when `TWITTER_BEARER_TOKEN` is set, otherwise the local file.
+pub fn default_source() -> Box<dyn PostSource> {
+    match env::var("TWITTER_BEARER_TOKEN") {
+        Ok(token) if !token.is_empty() => Box::new(TwitterSource::new(token)),
+        _ => Box::new(FilePostSource::load(POSTS_JSON_PATH)),
+    }
+}
+
+fn tokenize(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !(c.is_alphanumeric() || c == '\''))
+        .filter(|t| !t.is_empty())
+}
+
+/// Scores text in -1..1 from lexicon valences, flipping and damping words
+/// shortly after a negator and boosting words after an intensifier.
+pub fn score_text(text: &str) -> f64 {
+    let text = text.to_lowercase();
+    let mut sum = 0.0;
+    let mut negated_for = 0;
+    let mut boost = 1.0;
+
+    for token in tokenize(&text) {
+        if NEGATORS.contains(&token) {
+            negated_for = NEGATION_SCOPE;
+            continue;
+        }
+        if INTENSIFIERS.contains(&token) {
+            boost = INTENSIFIER_FACTOR;
+            continue;
+        }
+
+        let word = token.trim_matches('\'');
+        if let Some((_, valence)) = LEXICON.iter().find(|(w, _)| *w == word) {
+            let mut valence = valence * boost;
+            if negated_for > 0 {
+                valence *= NEGATION_FACTOR;
+            }
+            sum += valence;
+        }
+
+        boost = 1.0;
+        negated_for = negated_for.saturating_sub(1);
+    }
+
+    sum / (sum * sum + NORMALIZATION_ALPHA).sqrt()
+}
+
+pub fn label(score: f64) -> &'static str {
+    if score >= NEUTRAL_THRESHOLD {
+        "positive"
+    } else if score <= -NEUTRAL_THRESHOLD {
+        "negative"
+    } else {
+        "neutral"
+    }
+}
+
+pub struct SentimentDatabase {
+    pub conn: Connection,
+}
+
+impl SentimentDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS sentiment_posts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                project_id INTEGER NOT NULL REFERENCES projects (id),
+                source TEXT NOT NULL,
+                post_id TEXT NOT NULL,
+                author TEXT NOT NULL,
+                text TEXT NOT NULL,
+                score REAL NOT NULL,
+                created_at INTEGER NOT NULL,
+                UNIQUE (project_id, source, post_id)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS sentiment_posts_project ON sentiment_posts (project_id, created_at)",
+            [],
+        )?;
+
+        Ok(SentimentDatabase { conn })
+    }
+
+    /// Stores a scored post, returning false when it was already ingested.
+    pub fn insert(&self, project_id: i64, source: &str, post: &Post, score: f64) -> Result<bool> {
+        let inserted = self.conn.execute(
+            "INSERT OR IGNORE INTO sentiment_posts (project_id, source, post_id, author, text, score, created_at)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)",
+            params![
+                project_id,
+                source,
+                post.id,
+                post.author,
+                post.text,
+                score,
+                post.created_at
+            ],
+        )?;
+        Ok(inserted == 1)
+    }
+
+    pub fn latest_post_at(&self, project_id: i64, source: &str) -> Result<Option<i64>> {
+        self.conn.query_row(
+            "SELECT MAX(created_at) FROM sentiment_posts WHERE project_id = ?1 AND source = ?2",
+            params![project_id, source],
+            |row| row.get(0),
+        )
+    }
+
+    /// Average score and post count for `from <= created_at < to`.
+    pub fn window(&self, project_id: i64, from: i64, to: i64) -> Result<(f64, i64)> {
+        self.conn.query_row(
+            "SELECT COALESCE(AVG(score), 0), COUNT(*) FROM sentiment_posts
+             WHERE project_id = ?1 AND created_at >= ?2 AND created_at < ?3",
+            params![project_id, from, to],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )
+    }
+}
+
+/// Compares the latest window with the one before it. `None` when no posts
+/// fall into the latest window.
+pub fn aggregate(project_id: i64) -> Result<Option<SentimentScore>> {
+    let db = SentimentDatabase::new()?;
+    let now = utils::now();
+    let (score, posts) = db.window(project_id, now - SENTIMENT_WINDOW_SECS, now + 1)?;
+    if posts == 0 {
+        return Ok(None);
+    }
+
+    let (previous, previous_posts) = db.window(
+        project_id,
+        now - 2 * SENTIMENT_WINDOW_SECS,
+        now - SENTIMENT_WINDOW_SECS,
+    )?;
+    let trend = if previous_posts == 0 {
+        "stable"
+    } else if score - previous >= TREND_THRESHOLD {
+        "improving"
+    } else if previous - score >= TREND_THRESHOLD {
+        "declining"
+    } else {
+        "stable"
+    };
+
+    Ok(Some(SentimentScore {
+        score,
+        label: label(score).to_string(),
+        trend: trend.to_string(),
+        posts,
+        window_secs: SENTIMENT_WINDOW_SECS,
+    }))
+}
+
+pub async fn ingest_all(source: &dyn PostSource) -> std::result::Result<usize, String> {
+    let projects = ProjectDatabase::new()
+        .and_then(|db| db.list())
+        .map_err(|e| e.to_string())?;
+    let db = SentimentDatabase::new().map_err(|e| e.to_string())?;
+    let default_since = utils::now() - 2 * SENTIMENT_WINDOW_SECS;
+
+    let mut ingested = 0;
+    for project in &projects {
+        let project_id = project.id.unwrap_or_default();
+        let since = db
+            .latest_post_at(project_id, source.name())
+            .map_err(|e| e.to_string())?
+            .map(|latest| latest + 1)
+            .unwrap_or(default_since);
+
+        let posts = match source.fetch(project, since).await {
+            Ok(posts) => posts,
+            Err(e) => {
+                println!("Sentiment ingest failed for {}: {}", project.name, e);
+                continue;
+            }
+        };
+
+        for post in posts {
+            if db
+                .insert(project_id, source.name(), &post, score_text(&post.text))
+                .map_err(|e| e.to_string())?
+            {
+                ingested += 1;
+            }
+        }
+    }
+
+    Ok(ingested)
+}
+
+pub async fn run_ingest_job() {
+    let mut interval = tokio::time::interval(Duration::from_secs(SENTIMENT_INGEST_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        let source = default_source();
+        if let Err(e) = ingest_all(source.as_ref()).await {
+            println!("Sentiment ingest job failed: {}", e);
+        }
+    }
+}
@@ -0,0 +1,318 @@
+use crate::chain;
+use crate::constants::{DB_PATH, DEFAULT_WALLET_LABEL, SIWE_NONCE_TTL_SECS};
+use crate::profiles::{Profile, ProfileDatabase};
+use crate::reauth::{self, ReauthDatabase};
+use crate::utils;
+use crate::wallets;
+use axum::{http::StatusCode, Json};
+use chrono::DateTime;
+use ethers::{
+    types::{Address, Signature},
+    utils::to_checksum,
+};
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::{env, str::FromStr};
+
+const PREAMBLE_SUFFIX: &str = " wants you to sign in with your Ethereum account:";
+
+/// Single-use nonces handed out for Sign-In with Ethereum messages.
+pub struct SiweDatabase {
+    pub conn: Connection,
+}
+
+impl SiweDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS siwe_nonces (
+                nonce TEXT PRIMARY KEY,
+                expires_at INTEGER NOT NULL,
+                used_at INTEGER
+            )",
+            [],
+        )?;
+
+        Ok(SiweDatabase { conn })
+    }
+
+    pub fn issue(&self) -> Result<(String, i64)> {
+        let nonce = hex::encode(rand::random::<[u8; 16]>());
+        let expires_at = utils::now() + SIWE_NONCE_TTL_SECS;
+        self.conn.execute(
+            "DELETE FROM siwe_nonces WHERE expires_at < ?1",
+            params![utils::now()],
+        )?;
+        self.conn.execute(
+            "INSERT INTO siwe_nonces (nonce, expires_at) VALUES (?1, ?2)",
+            params![nonce, expires_at],
+        )?;
+        Ok((nonce, expires_at))
+    }
+
+    /// Marks the nonce used if it was issued here and is still open.
+    pub fn consume(&self, nonce: &str) -> Result<bool> {
+        let now = utils::now();
+        let updated = self.conn.execute(
+            "UPDATE siwe_nonces SET used_at = ?1
+             WHERE nonce = ?2 AND used_at IS NULL AND expires_at >= ?1",
+            params![now, nonce],
+        )?;
+        Ok(updated == 1)
+    }
+}
+
+/// The fields of an EIP-4361 message this server checks.
+#[derive(Debug, Default, Clone)]
+pub struct SiweMessage {
+    pub domain: String,
+    pub address: String,
+    pub uri: String,
+    pub version: String,
+    pub chain_id: u64,
+    pub nonce: String,
+    pub issued_at: i64,
+    pub expiration_time: Option<i64>,
+    pub not_before: Option<i64>,
+}
+
+fn parse_time(field: &str, value: &str) -> std::result::Result<i64, String> {
+    DateTime::parse_from_rfc3339(value)
+        .map(|t| t.timestamp())
+        .map_err(|_| format!("Invalid {}: {}", field, value))
+}
+
+impl FromStr for SiweMessage {
+    type Err = String;
+
+    /// Parses the message layout from EIP-4361. The optional statement,
+    /// request id and resources are accepted but not kept.
+    fn from_str(text: &str) -> std::result::Result<Self, Self::Err> {
+        let mut lines = text.lines();
+        let domain = lines
+            .next()
+            .and_then(|l| l.strip_suffix(PREAMBLE_SUFFIX))
+            .filter(|d| !d.is_empty())
+            .ok_or("Message does not start with a sign-in preamble")?;
+        let address = lines.next().ok_or("Message has no address")?;
+
+        let mut message = SiweMessage {
+            domain: domain.to_string(),
+            address: address.to_string(),
+            ..Default::default()
+        };
+        let mut issued_at = None;
+        for line in lines {
+            let Some((key, value)) = line.split_once(": ") else {
+                continue;
+            };
+            match key {
+                "URI" => message.uri = value.to_string(),
+                "Version" => message.version = value.to_string(),
+                "Chain ID" => {
+                    message.chain_id = value
+                        .parse()
+                        .map_err(|_| format!("Invalid Chain ID: {}", value))?
+                }
+                "Nonce" => message.nonce = value.to_string(),
+                "Issued At" => issued_at = Some(parse_time("Issued At", value)?),
+                "Expiration Time" => {
+                    message.expiration_time = Some(parse_time("Expiration Time", value)?)
+                }
+                "Not Before" => message.not_before = Some(parse_time("Not Before", value)?),
+                _ => {}
+            }
+        }
+
+        message.issued_at = issued_at.ok_or("Message has no Issued At")?;
+        for (field, value) in [
+            ("URI", &message.uri),
+            ("Version", &message.version),
+            ("Nonce", &message.nonce),
+        ] {
+            if value.is_empty() {
+                return Err(format!("Message has no {}", field));
+            }
+        }
+        if message.chain_id == 0 {
+            return Err("Message has no Chain ID".to_string());
+        }
+        Ok(message)
+    }
+}
+
+/// Domain sign-in messages must be bound to: `SIWE_DOMAIN`, the public host
+/// clients sign in from. Required, as the bind address in `SERVER_HOST` is
+/// not what a wallet shows the user.
+pub fn expected_domain() -> std::result::Result<String, String> {
+    env::var("SIWE_DOMAIN")
+        .ok()
+        .map(|d| d.trim().to_string())
+        .filter(|d| !d.is_empty())
+        .ok_or("SIWE_DOMAIN must be set to the domain clients sign in from".to_string())
+}
+
+/// Checks everything in the parsed message except the nonce, and that
+/// `signature` is a `personal_sign` of `text` by its address.
+fn verify(
+    text: &str,
+    message: &SiweMessage,
+    signature: &str,
+) -> std::result::Result<Address, String> {
+    if message.domain != expected_domain()? {
+        return Err(format!("Unexpected domain: {}", message.domain));
+    }
+    if message.version != "1" {
+        return Err(format!("Unsupported version: {}", message.version));
+    }
+    let chain_id = chain::chain_id()?;
+    if message.chain_id != chain_id {
+        return Err(format!(
+            "Chain ID {} does not match {}",
+            message.chain_id, chain_id
+        ));
+    }
+
+    let now = utils::now();
+    if message.expiration_time.is_some_and(|t| t <= now) {
+        return Err("Message has expired".to_string());
+    }
+    if message.not_before.is_some_and(|t| t > now) {
+        return Err("Message is not valid yet".to_string());
+    }
+
+    let address = Address::from_str(&message.address)
+        .map_err(|_| format!("Invalid address: {}", message.address))?;
+    if message.address != to_checksum(&address, None) {
+        return Err("Address must use EIP-55 checksum casing".to_string());
+    }
+
+    let signer = Signature::from_str(signature.trim())
+        .and_then(|s| s.recover(text))
+        .map_err(|_| "Invalid signature".to_string())?;
+    if signer != address {
+        return Err("Signature does not match the message address".to_string());
+    }
+    Ok(address)
+}
+
+fn internal(e: impl ToString) -> (StatusCode, String) {
+    (StatusCode::INTERNAL_SERVER_ERROR, e.to_string())
+}
+
+/// Finds the profile of a verified address, creating it and its default
+/// custody wallet on first sign-in, as the Twitter callback does.
+fn profile_for(address: &Address) -> std::result::Result<Profile, (StatusCode, String)> {
+    let user_id = format!("{:#x}", address);
+    let username = to_checksum(address, None);
+    let db = ProfileDatabase::new().map_err(internal)?;
+
+    if let Some(profile) = db.get(&user_id).map_err(internal)? {
+        // Only a profile this flow created belongs to the address.
+        if profile.username != username {
+            return Err((
+                StatusCode::CONFLICT,
+                format!("Profile {} belongs to another account", user_id),
+            ));
+        }
+        return Ok(profile);
+    }
+
+    let wallet = wallets::create_wallet(&user_id, DEFAULT_WALLET_LABEL, true).map_err(internal)?;
+    let mut profile = Profile {
+        id: None,
+        user_id,
+        name: format!("{}…{}", &username[..6], &username[username.len() - 4..]),
+        username,
+        wallet: wallet.address,
+    };
+    profile.id = Some(db.create(&profile).map_err(internal)?);
+    Ok(profile)
+}
+
+#[derive(Debug, Serialize)]
+pub struct NonceResponse {
+    pub nonce: String,
+    pub expires_at: i64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SiweLoginRequest {
+    /// The EIP-4361 message exactly as signed.
+    pub message: String,
+    pub signature: String,
+    /// Set to re-authenticate for a sensitive action, like
+    /// `/login/:id?purpose=`; the response then carries a token.
+    pub purpose: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SiweLoginResponse {
+    pub profile: Profile,
+    /// Opens `/stream/:id` and `/stream/:id/ws` until it expires.
+    pub stream_token: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reauth_token: Option<String>,
+}
+
+pub async fn get_nonce() -> std::result::Result<Json<NonceResponse>, (StatusCode, String)> {
+    let (nonce, expires_at) = SiweDatabase::new()
+        .and_then(|db| db.issue())
+        .map_err(internal)?;
+    Ok(Json(NonceResponse { nonce, expires_at }))
+}
+
+/// Signs in with a SIWE message. The profile's `user_id` is the lowercase
+/// address and is used with the rest of the API like a Twitter login's.
+pub async fn login(
+    Json(req): Json<SiweLoginRequest>,
+) -> std::result::Result<Json<SiweLoginResponse>, (StatusCode, String)> {
+    let purpose = req.purpose.filter(|p| !p.is_empty());
+    if let Some(purpose) = &purpose {
+        if !reauth::is_known_purpose(purpose) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Unknown login purpose: {}", purpose),
+            ));
+        }
+    }
+
+    let message = req
+        .message
+        .parse::<SiweMessage>()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let address = verify(&req.message, &message, &req.signature)
+        .map_err(|e| (StatusCode::UNAUTHORIZED, e))?;
+
+    // Spent only once the signature checks out, so a forged message cannot
+    // burn someone else's nonce.
+    let fresh = SiweDatabase::new()
+        .and_then(|db| db.consume(&message.nonce))
+        .map_err(internal)?;
+    if !fresh {
+        return Err((
+            StatusCode::UNAUTHORIZED,
+            "Nonce is unknown, expired or already used".to_string(),
+        ));
+    }
+
+    let profile = profile_for(&address)?;
+    let reauth_token = match &purpose {
+        Some(purpose) => Some(
+            ReauthDatabase::new()
+                .and_then(|db| db.issue(&profile.user_id, purpose))
+                .map_err(internal)?,
+        ),
+        None => None,
+    };
+    let stream_token = ReauthDatabase::new()
+        .and_then(|db| db.issue(&profile.user_id, reauth::PURPOSE_STREAM))
+        .map_err(internal)?;
+
+    Ok(Json(SiweLoginResponse {
+        profile,
+        stream_token,
+        reauth_token,
+    }))
+}
@@ -0,0 +1,613 @@
+#![allow(dead_code)]
+
+use crate::chain;
+use crate::constants::{
+    DB_PATH, SUBSCRIPTION_MAX_ATTEMPTS, SUBSCRIPTION_RETRY_DELAY_SECS,
+    SUBSCRIPTION_SCHEDULER_INTERVAL_SECS,
+};
+use crate::defi::models::{QuoteAndSwapRequest, UserQuoteRequest};
+use crate::models::AppState;
+use crate::profiles::ProfileDatabase;
+use crate::swap;
+use crate::utils;
+use crate::wallets::WalletDatabase;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{TimeZone, Utc};
+use cron::Schedule;
+use ethers::types::U256;
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+use std::time::Duration;
+
+pub const STATUS_ACTIVE: &str = "active";
+pub const STATUS_CANCELLED: &str = "cancelled";
+pub const STATUS_COMPLETED: &str = "completed";
+
+pub const RUN_SUCCEEDED: &str = "succeeded";
+pub const RUN_SKIPPED: &str = "skipped";
+pub const RUN_FAILED: &str = "failed";
+
+const SUBSCRIPTION_COLUMNS: &str =
+    "id, user_id, schedule, from_token, to_token, amount, slippage, \
+     network_name, next_run_at, end_at, max_runs, runs_completed, status, created_at, updated_at, \
+     retry_attempt";
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct Subscription {
+    pub id: Option<i64>,
+    pub user_id: String,
+    /// Cron expression in UTC, either 5 fields (minute first) or 6 with seconds.
+    pub schedule: String,
+    pub from_token: String,
+    pub to_token: String,
+    pub amount: String,
+    pub slippage: String,
+    pub network_name: String,
+    pub next_run_at: i64,
+    pub end_at: Option<i64>,
+    pub max_runs: Option<i64>,
+    pub runs_completed: i64,
+    pub status: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Failed attempts of the current run; while set, `next_run_at` is the
+    /// retry rather than the next scheduled run.
+    #[serde(default)]
+    pub retry_attempt: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct SubscriptionRun {
+    pub id: Option<i64>,
+    pub subscription_id: i64,
+    pub status: String,
+    pub attempts: i64,
+    pub swap_id: Option<String>,
+    pub message: String,
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CreateSubscriptionRequest {
+    pub schedule: String,
+    pub from_token: String,
+    pub to_token: String,
+    pub amount: String,
+    #[serde(default)]
+    pub slippage: String,
+    pub network_name: String,
+    pub end_at: Option<i64>,
+    pub max_runs: Option<i64>,
+}
+
+pub struct SubscriptionDatabase {
+    pub conn: Connection,
+}
+
+impl SubscriptionDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS subscriptions (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NOT NULL,
+                schedule TEXT NOT NULL,
+                from_token TEXT NOT NULL,
+                to_token TEXT NOT NULL,
+                amount TEXT NOT NULL,
+                slippage TEXT NOT NULL,
+                network_name TEXT NOT NULL,
+                next_run_at INTEGER NOT NULL,
+                end_at INTEGER NULL,
+                max_runs INTEGER NULL,
+                runs_completed INTEGER NOT NULL DEFAULT 0,
+                status TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS subscription_runs (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                subscription_id INTEGER NOT NULL REFERENCES subscriptions (id),
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL,
+                swap_id TEXT NULL,
+                message TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+        utils::add_column_if_missing(
+            &conn,
+            "subscriptions",
+            "retry_attempt",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+
+        Ok(SubscriptionDatabase { conn })
+    }
+
+    pub fn create(&self, subscription: &Subscription) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO subscriptions (user_id, schedule, from_token, to_token, amount, slippage,
+                network_name, next_run_at, end_at, max_runs, status)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)",
+            params![
+                subscription.user_id,
+                subscription.schedule,
+                subscription.from_token,
+                subscription.to_token,
+                subscription.amount,
+                subscription.slippage,
+                subscription.network_name,
+                subscription.next_run_at,
+                subscription.end_at,
+                subscription.max_runs,
+                subscription.status
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn get(&self, user_id: &str, subscription_id: i64) -> Result<Option<Subscription>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM subscriptions WHERE id = ?1 AND user_id = ?2",
+                    SUBSCRIPTION_COLUMNS
+                ),
+                params![subscription_id, user_id],
+                from_row,
+            )
+            .optional()
+    }
+
+    pub fn list_for_user(&self, user_id: &str) -> Result<Vec<Subscription>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM subscriptions WHERE user_id = ?1 ORDER BY id DESC",
+            SUBSCRIPTION_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![user_id], from_row)?;
+        rows.collect()
+    }
+
+    pub fn list_due(&self, now: i64) -> Result<Vec<Subscription>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM subscriptions WHERE status = ?1 AND next_run_at <= ?2 ORDER BY next_run_at",
+            SUBSCRIPTION_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![STATUS_ACTIVE, now], from_row)?;
+        rows.collect()
+    }
+
+    /// Moves an active subscription to `status`. Returns false if it was no
+    /// longer active, e.g. cancelled while a run was executing.
+    pub fn set_status(&self, subscription_id: i64, status: &str) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE subscriptions SET status = ?1, updated_at = strftime('%s', 'now')
+             WHERE id = ?2 AND status = ?3",
+            params![status, subscription_id, STATUS_ACTIVE],
+        )?;
+        Ok(updated == 1)
+    }
+
+    /// Schedules the next run of an active subscription and counts the
+    /// current one if it succeeded.
+    pub fn advance(&self, subscription_id: i64, next_run_at: i64, succeeded: bool) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE subscriptions SET next_run_at = ?1, runs_completed = runs_completed + ?2,
+                retry_attempt = 0, updated_at = strftime('%s', 'now')
+             WHERE id = ?3 AND status = ?4",
+            params![
+                next_run_at,
+                succeeded as i64,
+                subscription_id,
+                STATUS_ACTIVE
+            ],
+        )?;
+        Ok(updated == 1)
+    }
+
+    /// Completes an active subscription after its final run, counting that
+    /// run if it succeeded.
+    pub fn complete(&self, subscription_id: i64, succeeded: bool) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE subscriptions SET status = ?1, runs_completed = runs_completed + ?2,
+                retry_attempt = 0, updated_at = strftime('%s', 'now')
+             WHERE id = ?3 AND status = ?4",
+            params![
+                STATUS_COMPLETED,
+                succeeded as i64,
+                subscription_id,
+                STATUS_ACTIVE
+            ],
+        )?;
+        Ok(updated == 1)
+    }
+
+    /// Makes the retry of the current run, after `attempt` failed attempts,
+    /// the next run of an active subscription.
+    pub fn schedule_retry(
+        &self,
+        subscription_id: i64,
+        retry_at: i64,
+        attempt: i64,
+    ) -> Result<bool> {
+        let updated = self.conn.execute(
+            "UPDATE subscriptions SET next_run_at = ?1, retry_attempt = ?2,
+                updated_at = strftime('%s', 'now')
+             WHERE id = ?3 AND status = ?4",
+            params![retry_at, attempt, subscription_id, STATUS_ACTIVE],
+        )?;
+        Ok(updated == 1)
+    }
+
+    pub fn record_run(&self, run: &SubscriptionRun) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO subscription_runs (subscription_id, status, attempts, swap_id, message)
+             VALUES (?1, ?2, ?3, ?4, ?5)",
+            params![
+                run.subscription_id,
+                run.status,
+                run.attempts,
+                run.swap_id,
+                run.message
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn runs(&self, subscription_id: i64) -> Result<Vec<SubscriptionRun>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, subscription_id, status, attempts, swap_id, message, created_at
+             FROM subscription_runs WHERE subscription_id = ?1 ORDER BY id DESC",
+        )?;
+        let rows = stmt.query_map(params![subscription_id], |row| {
+            Ok(SubscriptionRun {
+                id: row.get(0)?,
+                subscription_id: row.get(1)?,
+                status: row.get(2)?,
+                attempts: row.get(3)?,
+                swap_id: row.get(4)?,
+                message: row.get(5)?,
+                created_at: row.get(6)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+fn from_row(row: &Row) -> Result<Subscription> {
+    Ok(Subscription {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        schedule: row.get(2)?,
+        from_token: row.get(3)?,
+        to_token: row.get(4)?,
+        amount: row.get(5)?,
+        slippage: row.get(6)?,
+        network_name: row.get(7)?,
+        next_run_at: row.get(8)?,
+        end_at: row.get(9)?,
+        max_runs: row.get(10)?,
+        runs_completed: row.get(11)?,
+        status: row.get(12)?,
+        created_at: row.get(13)?,
+        updated_at: row.get(14)?,
+        retry_attempt: row.get(15)?,
+    })
+}
+
+fn parse_schedule(expr: &str) -> std::result::Result<Schedule, String> {
+    // The cron crate expects a leading seconds field; accept classic 5-field crontabs too.
+    let expr = expr.trim();
+    let expr = if expr.split_whitespace().count() == 5 {
+        format!("0 {}", expr)
+    } else {
+        expr.to_string()
+    };
+
+    Schedule::from_str(&expr).map_err(|e| format!("Invalid schedule: {}", e))
+}
+
+fn next_run_after(schedule: &Schedule, after: i64) -> Option<i64> {
+    let after = Utc.timestamp_opt(after, 0).single()?;
+    schedule.after(&after).next().map(|t| t.timestamp())
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Subscription database error: {}", e),
+    )
+}
+
+pub async fn create_subscription(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Json(req): Json<CreateSubscriptionRequest>,
+) -> Result<Json<Subscription>, (StatusCode, String)> {
+    ProfileDatabase::new()
+        .and_then(|db| db.get(&user_id))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+
+    let now = utils::now();
+    let schedule = parse_schedule(&req.schedule).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    let next_run_at = next_run_after(&schedule, now).ok_or((
+        StatusCode::BAD_REQUEST,
+        "Schedule has no upcoming runs".to_string(),
+    ))?;
+
+    if U256::from_dec_str(&req.amount).is_err() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Invalid amount: {}", req.amount),
+        ));
+    }
+    if matches!(req.end_at, Some(at) if at <= now) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "end_at must be in the future".to_string(),
+        ));
+    }
+    if matches!(req.max_runs, Some(runs) if runs <= 0) {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "max_runs must be positive".to_string(),
+        ));
+    }
+    let slippage = state
+        .swap_policy
+        .parse_slippage(&req.slippage)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let mut subscription = Subscription {
+        user_id,
+        schedule: req.schedule,
+        from_token: req.from_token,
+        to_token: req.to_token,
+        amount: req.amount,
+        slippage: slippage.to_string(),
+        network_name: req.network_name,
+        next_run_at,
+        end_at: req.end_at,
+        max_runs: req.max_runs,
+        status: STATUS_ACTIVE.to_string(),
+        created_at: now,
+        updated_at: now,
+        ..Default::default()
+    };
+
+    let db = SubscriptionDatabase::new().map_err(db_error)?;
+    subscription.id = Some(db.create(&subscription).map_err(db_error)?);
+
+    Ok(Json(subscription))
+}
+
+pub async fn list_subscriptions(
+    Path(user_id): Path<String>,
+) -> Result<Json<Vec<Subscription>>, (StatusCode, String)> {
+    let db = SubscriptionDatabase::new().map_err(db_error)?;
+    let subscriptions = db.list_for_user(&user_id).map_err(db_error)?;
+    Ok(Json(subscriptions))
+}
+
+pub async fn cancel_subscription(
+    Path((user_id, subscription_id)): Path<(String, i64)>,
+) -> Result<Json<Subscription>, (StatusCode, String)> {
+    let db = SubscriptionDatabase::new().map_err(db_error)?;
+    let subscription = db
+        .get(&user_id, subscription_id)
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Subscription not found".to_string()))?;
+
+    if subscription.status != STATUS_ACTIVE {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("Subscription is already {}", subscription.status),
+        ));
+    }
+
+    if !db
+        .set_status(subscription_id, STATUS_CANCELLED)
+        .map_err(db_error)?
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            "Subscription is no longer active".to_string(),
+        ));
+    }
+    let subscription = db
+        .get(&user_id, subscription_id)
+        .map_err(db_error)?
+        .unwrap();
+    Ok(Json(subscription))
+}
+
+pub async fn get_subscription_runs(
+    Path((user_id, subscription_id)): Path<(String, i64)>,
+) -> Result<Json<Vec<SubscriptionRun>>, (StatusCode, String)> {
+    let db = SubscriptionDatabase::new().map_err(db_error)?;
+    db.get(&user_id, subscription_id)
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Subscription not found".to_string()))?;
+
+    let runs = db.runs(subscription_id).map_err(db_error)?;
+    Ok(Json(runs))
+}
+
+/// Executes due subscriptions through the regular swap path.
+pub async fn run_scheduler(state: AppState) {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(SUBSCRIPTION_SCHEDULER_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        let due = match SubscriptionDatabase::new().and_then(|db| db.list_due(utils::now())) {
+            Ok(subscriptions) => subscriptions,
+            Err(e) => {
+                println!("Subscription scheduler failed to load subscriptions: {}", e);
+                continue;
+            }
+        };
+
+        for subscription in due {
+            if let Err(e) = run_subscription(&state, &subscription).await {
+                println!(
+                    "Subscription scheduler failed on subscription {:?}: {}",
+                    subscription.id, e
+                );
+            }
+        }
+    }
+}
+
+/// Whether the subscription is done once `runs_completed` runs succeeded,
+/// as of `at`.
+fn is_finished(subscription: &Subscription, at: i64, runs_completed: i64) -> bool {
+    matches!(subscription.end_at, Some(end) if end <= at)
+        || matches!(subscription.max_runs, Some(max) if runs_completed >= max)
+}
+
+async fn run_subscription(state: &AppState, subscription: &Subscription) -> Result<()> {
+    let db = SubscriptionDatabase::new()?;
+    let subscription_id = subscription.id.unwrap_or_default();
+    let now = utils::now();
+
+    // Cancelled since the due list was loaded.
+    let current = db.get(&subscription.user_id, subscription_id)?;
+    if current.map(|s| s.status).as_deref() != Some(STATUS_ACTIVE) {
+        return Ok(());
+    }
+    if is_finished(subscription, now, subscription.runs_completed) {
+        return db.set_status(subscription_id, STATUS_COMPLETED).map(|_| ());
+    }
+
+    let mut run = SubscriptionRun {
+        subscription_id,
+        attempts: subscription.retry_attempt + 1,
+        ..Default::default()
+    };
+
+    match funding_wallet(subscription).await {
+        Ok(Some(wallet)) => {
+            let retryable = execute_attempt(state, subscription, &wallet, &mut run).await;
+            // Retried on a later tick rather than in place, so one failing
+            // subscription does not hold up the others.
+            if retryable && run.attempts < SUBSCRIPTION_MAX_ATTEMPTS {
+                let retry_at = now + SUBSCRIPTION_RETRY_DELAY_SECS as i64 * run.attempts;
+                return db
+                    .schedule_retry(subscription_id, retry_at, run.attempts)
+                    .map(|_| ());
+            }
+        }
+        Ok(None) => {
+            run.status = RUN_SKIPPED.to_string();
+            run.message = "Insufficient balance".to_string();
+        }
+        Err(e) => {
+            run.status = RUN_FAILED.to_string();
+            run.message = e;
+        }
+    }
+    db.record_run(&run)?;
+
+    let succeeded = run.status == RUN_SUCCEEDED;
+    let runs_completed = subscription.runs_completed + succeeded as i64;
+    let schedule = match parse_schedule(&subscription.schedule) {
+        Ok(schedule) => schedule,
+        Err(_) => return db.set_status(subscription_id, STATUS_CANCELLED).map(|_| ()),
+    };
+    match next_run_after(&schedule, now) {
+        Some(next_run_at) if !is_finished(subscription, next_run_at, runs_completed) => db
+            .advance(subscription_id, next_run_at, succeeded)
+            .map(|_| ()),
+        // The final run: complete now rather than at the next due time.
+        _ => db.complete(subscription_id, succeeded).map(|_| ()),
+    }
+}
+
+/// The active wallet to buy from: the default one when it holds enough of
+/// the from-token, otherwise the first other wallet that does.
+async fn funding_wallet(
+    subscription: &Subscription,
+) -> std::result::Result<Option<String>, String> {
+    let amount = U256::from_dec_str(&subscription.amount)
+        .map_err(|_| format!("Invalid amount: {}", subscription.amount))?;
+    let wallets = WalletDatabase::new()
+        .and_then(|db| db.list_for_user(&subscription.user_id, false))
+        .map_err(|e| format!("Failed to load wallets: {}", e))?;
+    if wallets.is_empty() {
+        return Err("Wallet not found".to_string());
+    }
+
+    let provider = chain::provider()?;
+    for wallet in wallets {
+        let balance =
+            chain::token_balance(provider.clone(), &subscription.from_token, &wallet.address)
+                .await?;
+        if balance >= amount {
+            return Ok(Some(wallet.address));
+        }
+    }
+    Ok(None)
+}
+
+/// Makes one attempt at the subscription's swap, filling in `run`. Returns
+/// whether a failure may succeed when retried.
+async fn execute_attempt(
+    state: &AppState,
+    subscription: &Subscription,
+    wallet: &str,
+    run: &mut SubscriptionRun,
+) -> bool {
+    let result = swap::swap_for_user(
+        state,
+        &subscription.user_id,
+        QuoteAndSwapRequest {
+            quote: UserQuoteRequest {
+                from_token: subscription.from_token.clone(),
+                to_token: subscription.to_token.clone(),
+                amount: subscription.amount.clone(),
+                slippage: subscription.slippage.clone(),
+                to_address: None,
+                gasless: true,
+                affiliate_address: None,
+                affiliate_fee: None,
+                accept_high_impact: false,
+                wallet: Some(wallet.to_string()),
+            },
+            network_name: subscription.network_name.clone(),
+            permit_deadline: None,
+        },
+    )
+    .await;
+
+    match result {
+        Ok(response) => {
+            run.status = RUN_SUCCEEDED.to_string();
+            run.message = format!("Quote {}", response.quote.quote.quote_id);
+            run.swap_id = Some(response.swap.swap_id);
+            false
+        }
+        Err((status, e)) => {
+            run.status = RUN_FAILED.to_string();
+            run.message = e;
+            // Client errors (bad token, price impact over policy) won't fix themselves.
+            !status.is_client_error()
+        }
+    }
+}
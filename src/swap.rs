@@ -1,18 +1,27 @@
+use crate::chain;
+use crate::constants::{
+    PERMIT_DEFAULT_TTL_SECS, SWAP_HISTORY_DEFAULT_LIMIT, SWAP_HISTORY_MAX_LIMIT,
+};
 use crate::defi::models::*;
+use crate::events;
+use crate::guardrails;
 use crate::models::AppState;
-use crate::profiles::{ProfileDatabase};
-use crate::wallets::{WalletDatabase};
+use crate::permits;
+use crate::portfolio;
+use crate::swap_history::{SwapHistoryDatabase, SwapRecord, STATUS_QUOTED};
+use crate::utils;
+use crate::wallets::{self, WalletDatabase};
 use axum::{
     extract::{Path, Query, State},
     http::StatusCode,
     Json,
 };
-use ethers::prelude::*;
+use ethers::{prelude::*, types::transaction::eip712::TypedData, utils::hex};
 
 pub async fn get_quote(
     State(state): State<AppState>,
     Json(req): Json<GetQuoteRequest>,
-) -> Result<Json<QuoteResponse>, (StatusCode, String)> {
+) -> Result<Json<GuardedQuoteResponse>, (StatusCode, String)> {
     let params = QuoteParams {
         from_token_address: req.from_token,
         to_token_address: req.to_token,
@@ -25,13 +34,16 @@ pub async fn get_quote(
         affiliate_fee: req.affiliate_fee,
     };
 
-    let response = state.magpie.get_quote(&params).await.map_err(|e| {
-        (
-            StatusCode::INTERNAL_SERVER_ERROR,
-            format!("Failed to get quote: {}", e),
-        )
-    })?;
+    let response = quote(&state, params, req.accept_high_impact).await?;
+    Ok(Json(response))
+}
 
+pub async fn get_user_quote(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Json(req): Json<UserQuoteRequest>,
+) -> Result<Json<GuardedQuoteResponse>, (StatusCode, String)> {
+    let response = quote_for_user(&state, &user_id, req).await?;
     Ok(Json(response))
 }
 
@@ -40,7 +52,251 @@ pub async fn execute_swap(
     Path(user_id): Path<String>,
     Json(req): Json<ExecuteSwapRequest>,
 ) -> Result<Json<SwapResponse>, (StatusCode, String)> {
-    let _quote_details = state
+    let response = execute(&state, &user_id, req).await?;
+    Ok(Json(response))
+}
+
+pub async fn quote_and_execute_swap(
+    State(state): State<AppState>,
+    Path(user_id): Path<String>,
+    Json(req): Json<QuoteAndSwapRequest>,
+) -> Result<Json<QuoteAndSwapResponse>, (StatusCode, String)> {
+    let response = swap_for_user(&state, &user_id, req).await?;
+    Ok(Json(response))
+}
+
+/// Quotes a swap from one of the user's stored wallets, applying the swap
+/// guardrails.
+pub async fn quote_for_user(
+    state: &AppState,
+    user_id: &str,
+    req: UserQuoteRequest,
+) -> Result<GuardedQuoteResponse, (StatusCode, String)> {
+    let wallet = wallets::resolve_wallet(user_id, req.wallet.as_deref())?;
+    let accept_high_impact = req.accept_high_impact;
+    let params = user_quote_params(&wallet.address, req);
+
+    quote(state, params, accept_high_impact).await
+}
+
+/// Prices a swap from one of the user's stored wallets without recording
+/// the quote or checking price impact, for trigger conditions that are
+/// polled far more often than they fire.
+pub async fn preview_for_user(
+    state: &AppState,
+    user_id: &str,
+    req: UserQuoteRequest,
+) -> Result<QuoteResponse, (StatusCode, String)> {
+    let wallet = wallets::resolve_wallet(user_id, req.wallet.as_deref())?;
+    let mut params = user_quote_params(&wallet.address, req);
+    params.slippage = state
+        .swap_policy
+        .parse_slippage(&params.slippage)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?
+        .to_string();
+
+    state.magpie.get_quote(&params).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get quote: {}", e),
+        )
+    })
+}
+
+/// Quotes and immediately executes a gasless swap from one of the user's
+/// stored wallets.
+pub async fn swap_for_user(
+    state: &AppState,
+    user_id: &str,
+    mut req: QuoteAndSwapRequest,
+) -> Result<QuoteAndSwapResponse, (StatusCode, String)> {
+    // Only gasless quotes can be executed by the relayer on the user's behalf.
+    req.quote.gasless = true;
+
+    let wallet = req.quote.wallet.clone();
+    let quote = quote_for_user(state, user_id, req.quote).await?;
+
+    let swap = execute(
+        state,
+        user_id,
+        ExecuteSwapRequest {
+            quote_id: quote.quote.quote_id.clone(),
+            network_name: req.network_name,
+            wallet_key: None,
+            permit_deadline: req.permit_deadline,
+            from_token: None,
+            amount: None,
+            wallet,
+        },
+    )
+    .await?;
+
+    Ok(QuoteAndSwapResponse { quote, swap })
+}
+
+fn user_quote_params(wallet: &str, req: UserQuoteRequest) -> QuoteParams {
+    QuoteParams {
+        from_token_address: req.from_token,
+        to_token_address: req.to_token,
+        amount: req.amount,
+        slippage: req.slippage,
+        from_address: wallet.to_string(),
+        to_address: req.to_address.unwrap_or_else(|| wallet.to_string()),
+        gasless: req.gasless,
+        affiliate_address: req.affiliate_address,
+        affiliate_fee: req.affiliate_fee,
+    }
+}
+
+async fn quote(
+    state: &AppState,
+    mut params: QuoteParams,
+    accept_high_impact: bool,
+) -> Result<GuardedQuoteResponse, (StatusCode, String)> {
+    let slippage = state
+        .swap_policy
+        .parse_slippage(&params.slippage)
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    params.slippage = slippage.to_string();
+
+    guardrails::reference_amount(&params.amount).map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let provider = chain::provider().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    for token in [&params.from_token_address, &params.to_token_address] {
+        chain::validate_token(&provider, token)
+            .await
+            .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+    }
+
+    let response = state.magpie.get_quote(&params).await.map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to get quote: {}", e),
+        )
+    })?;
+
+    let impact = guardrails::estimate_price_impact(&state.magpie, &params, &response)
+        .await
+        .map_err(|e| (StatusCode::BAD_GATEWAY, e))?;
+
+    let warning = state
+        .swap_policy
+        .check_price_impact(impact.impact, accept_high_impact)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    // Only quotes the guardrails let through are recorded, and execution
+    // needs the record.
+    let user_id = WalletDatabase::new()
+        .and_then(|db| db.owner(&params.from_address))
+        .ok()
+        .flatten();
+    let record = SwapRecord {
+        user_id,
+        from_token: params.from_token_address.clone(),
+        to_token: params.to_token_address.clone(),
+        amount_in: params.amount.clone(),
+        amount_out: response.to_token_amount.clone(),
+        quote_id: response.quote_id.clone(),
+        status: STATUS_QUOTED.to_string(),
+        from_address: Some(params.from_address.clone()),
+        swap_message: response
+            .message
+            .as_ref()
+            .and_then(|m| serde_json::to_string(m).ok()),
+        price_impact: Some(impact.impact),
+        accept_high_impact,
+        ..Default::default()
+    };
+    SwapHistoryDatabase::new()
+        .and_then(|db| db.create(&record))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to record quote {}: {}", response.quote_id, e),
+            )
+        })?;
+
+    Ok(GuardedQuoteResponse {
+        quote: response,
+        slippage: params.slippage,
+        price_impact: Some(impact.impact),
+        warning,
+    })
+}
+
+/// Signs the quote's EIP-712 swap message, after checking it is for this
+/// chain and for the swap that was quoted.
+async fn sign_swap_message(wallet: &LocalWallet, quote: &SwapRecord) -> Result<String, String> {
+    let message: EIP712Message = match &quote.swap_message {
+        Some(message) => serde_json::from_str(message)
+            .map_err(|e| format!("Invalid swap message in quote {}: {}", quote.quote_id, e))?,
+        None => {
+            return Err(format!(
+                "Quote {} has no swap message to sign; request a gasless quote",
+                quote.quote_id
+            ))
+        }
+    };
+    if message.domain.chain_id != wallet.chain_id() {
+        return Err(format!(
+            "Swap message is for chain {}, not {}",
+            message.domain.chain_id,
+            wallet.chain_id()
+        ));
+    }
+    if !message
+        .message
+        .from_token
+        .eq_ignore_ascii_case(&quote.from_token)
+        || message.message.amount != quote.amount_in
+    {
+        return Err("Swap message does not match the quoted swap".to_string());
+    }
+
+    let typed_data: TypedData = serde_json::from_value(serde_json::json!({
+        "domain": message.domain,
+        "types": message.types,
+        "primaryType": "Swap",
+        "message": message.message,
+    }))
+    .map_err(|e| format!("Invalid swap message: {}", e))?;
+    let signature = wallet
+        .sign_typed_data(&typed_data)
+        .await
+        .map_err(|e| format!("Failed to sign swap: {}", e))?;
+    Ok(format!("0x{}", hex::encode(signature.to_vec())))
+}
+
+pub async fn execute(
+    state: &AppState,
+    user_id: &str,
+    req: ExecuteSwapRequest,
+) -> Result<SwapResponse, (StatusCode, String)> {
+    let quote = SwapHistoryDatabase::new()
+        .and_then(|db| db.get_by_quote(&req.quote_id))
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to load quote: {}", e),
+            )
+        })?
+        .ok_or((
+            StatusCode::NOT_FOUND,
+            format!("Quote {} not found", req.quote_id),
+        ))?;
+
+    // Quotes are only recorded once the guardrail passed; check the stored
+    // verdict again in case the policy was tightened since.
+    let impact = quote.price_impact.ok_or((
+        StatusCode::UNPROCESSABLE_ENTITY,
+        format!("Quote {} has no price impact check", req.quote_id),
+    ))?;
+    state
+        .swap_policy
+        .check_price_impact(impact, quote.accept_high_impact)
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+
+    let quote_details = state
         .magpie
         .get_transaction(&req.quote_id)
         .await
@@ -51,52 +307,133 @@ pub async fn execute_swap(
             )
         })?;
 
-    let profile_db = ProfileDatabase::new().unwrap();
-    let wallet_db = WalletDatabase::new().unwrap();
-    let profile = profile_db.get(&user_id).unwrap();
-    let wallet = wallet_db.get(&profile.unwrap().wallet).unwrap();
+    // Without a wallet named, swap from the one the quote was requested for.
+    let selector = req.wallet.as_deref().or(quote.from_address.as_deref());
+    let user_wallet = wallets::resolve_wallet(user_id, selector)?;
+    if matches!(&quote.from_address, Some(from) if !from.eq_ignore_ascii_case(&user_wallet.address))
+    {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Quote {} was requested for another wallet", req.quote_id),
+        ));
+    }
 
-    // For gasless swap, need to generate signatures
-    if let Some(user_wallet) = wallet {
-        // Create a wallet from the private key
-        let _wallet = user_wallet.private.parse::<LocalWallet>().map_err(|e| {
+    // Create a wallet from the private key
+    let chain_id = chain::chain_id().map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let wallet = user_wallet
+        .private
+        .parse::<LocalWallet>()
+        .map_err(|e| {
             (
                 StatusCode::BAD_REQUEST,
                 format!("Invalid wallet key: {}", e),
             )
-        })?;
+        })?
+        .with_chain_id(chain_id);
 
-        // This would require accessing the EIP-712 message from the quote response
-        // Simplified for example - in a real implementation, you would properly construct and sign the message
-        let swap_signature = "0x...".to_string(); // This would be generated by proper EIP-712 signing
-
-        let params = GaslessSwapParams {
-            network_name: req.network_name,
-            quote_id: req.quote_id,
-            swap_signature: swap_signature,
-            permit_signature: None,
-            permit_deadline: req.permit_deadline.map(|d| d.to_string()),
-        };
-
-        let response = state
-            .magpie
-            .execute_gasless_swap(&params)
-            .await
-            .map_err(|e| {
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    format!("Failed to execute gasless swap: {}", e),
-                )
-            })?;
-
-        Ok(Json(response))
-    } else {
-        // For self-execution, just return the transaction data
-        Err((
-            StatusCode::NOT_IMPLEMENTED,
-            "Self-execution not implemented in this example. Transaction data retrieved, but user must handle broadcasting.".to_string(),
-        ))
+    // The relayer pulls the from-token on our behalf, so tokens without an
+    // allowance for the router need a signed permit. Token and amount are the
+    // quoted ones; a request repeating them must agree.
+    for (field, given, quoted) in [
+        ("from_token", &req.from_token, &quote.from_token),
+        ("amount", &req.amount, &quote.amount_in),
+    ] {
+        if matches!(given, Some(given) if !given.eq_ignore_ascii_case(quoted)) {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("{} does not match quote {}", field, req.quote_id),
+            ));
+        }
+    }
+    if quote.from_token.is_empty() || quote.amount_in.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            format!("Quote {} has no token or amount to permit", req.quote_id),
+        ));
     }
+    let permit = if chain::is_native_token(&quote.from_token) {
+        None
+    } else {
+        let deadline = req
+            .permit_deadline
+            .unwrap_or_else(|| utils::now() as u64 + PERMIT_DEFAULT_TTL_SECS);
+
+        permits::sign_permit(
+            &wallet,
+            &quote.from_token,
+            &quote_details.to,
+            &quote.amount_in,
+            deadline,
+        )
+        .await
+        .map_err(|e| {
+            (
+                StatusCode::BAD_REQUEST,
+                format!("Cannot permit the swap: {}", e),
+            )
+        })?
+    };
+
+    let swap_signature = sign_swap_message(&wallet, &quote)
+        .await
+        .map_err(|e| (StatusCode::BAD_REQUEST, e))?;
+
+    let params = GaslessSwapParams {
+        network_name: req.network_name,
+        quote_id: req.quote_id,
+        swap_signature,
+        permit_signature: permit.as_ref().map(|p| p.signature.clone()),
+        permit_deadline: permit
+            .as_ref()
+            .map(|p| p.deadline)
+            .or(req.permit_deadline)
+            .map(|d| d.to_string()),
+        permit_type: permit.as_ref().map(|p| p.kind.clone()),
+        permit_nonce: permit.as_ref().map(|p| p.nonce.to_string()),
+    };
+
+    // Released by the status poller once the swap is booked or has failed.
+    portfolio::hold(user_id, &params.quote_id)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    let response = match state.magpie.execute_gasless_swap(&params).await {
+        Ok(response) => response,
+        Err(e) => {
+            let _ = portfolio::release(&params.quote_id);
+            return Err((
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to execute gasless swap: {}", e),
+            ));
+        }
+    };
+
+    SwapHistoryDatabase::new()
+        .and_then(|db| {
+            db.record_execution(
+                user_id,
+                &quote,
+                &response.swap_id,
+                &response.status,
+                response.tx_hash.as_deref(),
+            )
+        })
+        .map_err(|e| {
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                format!("Failed to record swap: {}", e),
+            )
+        })?;
+    state.events.publish(
+        user_id,
+        events::EVENT_SWAP,
+        serde_json::json!({
+            "swap_id": response.swap_id,
+            "quote_id": params.quote_id,
+            "status": response.status,
+            "tx_hash": response.tx_hash,
+        }),
+    );
+
+    Ok(response)
 }
 
 pub async fn get_swap_status(
@@ -152,3 +489,36 @@ pub async fn get_distributions(
 
     Ok(Json(response))
 }
+
+pub async fn list_swaps(
+    Path(user_id): Path<String>,
+    Query(req): Query<SwapHistoryQuery>,
+) -> Result<Json<SwapHistoryResponse>, (StatusCode, String)> {
+    let limit = req
+        .limit
+        .unwrap_or(SWAP_HISTORY_DEFAULT_LIMIT)
+        .clamp(1, SWAP_HISTORY_MAX_LIMIT);
+    let offset = req.offset.unwrap_or(0).max(0);
+
+    let db = SwapHistoryDatabase::new().map_err(|e| {
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to open swap history: {}", e),
+        )
+    })?;
+    let swaps = db.list_for_user(&user_id, limit, offset);
+    let total = db.count_for_user(&user_id);
+
+    match (swaps, total) {
+        (Ok(swaps), Ok(total)) => Ok(Json(SwapHistoryResponse {
+            swaps,
+            total,
+            limit,
+            offset,
+        })),
+        (Err(e), _) | (_, Err(e)) => Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to load swap history: {}", e),
+        )),
+    }
+}
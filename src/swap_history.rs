@@ -0,0 +1,315 @@
+#![allow(dead_code)]
+
+use crate::constants::{DB_PATH, SWAP_POLL_INTERVAL_SECS};
+use crate::defi::magpiefi::MagpieClient;
+use crate::events::{self, EventBus};
+use crate::portfolio;
+use crate::prices::PriceService;
+use crate::utils;
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use std::time::Duration;
+
+pub const STATUS_QUOTED: &str = "quoted";
+pub const STATUS_PENDING: &str = "pending";
+pub const STATUS_COMPLETED: &str = "completed";
+pub const STATUS_ERROR: &str = "error";
+pub const STATUS_FAILED: &str = "failed";
+
+const SWAP_COLUMNS: &str = "id, user_id, from_token, to_token, amount_in, amount_out, quote_id, \
+     swap_id, tx_hash, status, created_at, updated_at, from_address, swap_message, price_impact, \
+     accept_high_impact";
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct SwapRecord {
+    pub id: Option<i64>,
+    pub user_id: Option<String>,
+    pub from_token: String,
+    pub to_token: String,
+    pub amount_in: String,
+    pub amount_out: String,
+    pub quote_id: String,
+    pub swap_id: Option<String>,
+    pub tx_hash: Option<String>,
+    pub status: String,
+    pub created_at: i64,
+    pub updated_at: i64,
+    /// Wallet the quote was requested for.
+    #[serde(default)]
+    pub from_address: Option<String>,
+    /// The quote's EIP-712 swap message as JSON, signed at execution.
+    #[serde(skip)]
+    pub swap_message: Option<String>,
+    /// Price impact the guardrail measured at quote time.
+    #[serde(default)]
+    pub price_impact: Option<f64>,
+    /// Whether the user accepted an impact above the warning threshold.
+    #[serde(skip)]
+    pub accept_high_impact: bool,
+}
+
+pub struct SwapHistoryDatabase {
+    pub conn: Connection,
+}
+
+impl SwapHistoryDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS swaps (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                user_id TEXT NULL,
+                from_token TEXT NOT NULL,
+                to_token TEXT NOT NULL,
+                amount_in TEXT NOT NULL,
+                amount_out TEXT NOT NULL,
+                quote_id TEXT NOT NULL UNIQUE,
+                swap_id TEXT NULL,
+                tx_hash TEXT NULL,
+                status TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                updated_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS swaps_user_id ON swaps (user_id, created_at)",
+            [],
+        )?;
+        utils::add_column_if_missing(&conn, "swaps", "from_address", "TEXT")?;
+        utils::add_column_if_missing(&conn, "swaps", "swap_message", "TEXT")?;
+        utils::add_column_if_missing(&conn, "swaps", "price_impact", "REAL")?;
+        utils::add_column_if_missing(
+            &conn,
+            "swaps",
+            "accept_high_impact",
+            "INTEGER NOT NULL DEFAULT 0",
+        )?;
+
+        Ok(SwapHistoryDatabase { conn })
+    }
+
+    pub fn create(&self, swap: &SwapRecord) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO swaps (user_id, from_token, to_token, amount_in, amount_out, quote_id, swap_id, tx_hash, status,
+                from_address, swap_message, price_impact, accept_high_impact)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)",
+            params![
+                swap.user_id,
+                swap.from_token,
+                swap.to_token,
+                swap.amount_in,
+                swap.amount_out,
+                swap.quote_id,
+                swap.swap_id,
+                swap.tx_hash,
+                swap.status,
+                swap.from_address,
+                swap.swap_message,
+                swap.price_impact,
+                swap.accept_high_impact
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn get_by_quote(&self, quote_id: &str) -> Result<Option<SwapRecord>> {
+        self.conn
+            .query_row(
+                &format!("SELECT {} FROM swaps WHERE quote_id = ?1", SWAP_COLUMNS),
+                params![quote_id],
+                from_row,
+            )
+            .optional()
+    }
+
+    /// Records the result of submitting `quote` for execution, recreating
+    /// its row from the quote if it is gone.
+    pub fn record_execution(
+        &self,
+        user_id: &str,
+        quote: &SwapRecord,
+        swap_id: &str,
+        status: &str,
+        tx_hash: Option<&str>,
+    ) -> Result<()> {
+        let updated = self.conn.execute(
+            "UPDATE swaps SET user_id = COALESCE(user_id, ?1), swap_id = ?2, status = ?3, tx_hash = ?4,
+                updated_at = strftime('%s', 'now')
+             WHERE quote_id = ?5",
+            params![user_id, swap_id, status, tx_hash, quote.quote_id],
+        )?;
+
+        if updated == 0 {
+            self.create(&SwapRecord {
+                user_id: Some(user_id.to_string()),
+                swap_id: Some(swap_id.to_string()),
+                tx_hash: tx_hash.map(|h| h.to_string()),
+                status: status.to_string(),
+                ..quote.clone()
+            })?;
+        }
+
+        Ok(())
+    }
+
+    pub fn update_status(
+        &self,
+        swap_id: &str,
+        status: &str,
+        tx_hash: Option<&str>,
+        amount_out: Option<&str>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE swaps SET status = ?1, tx_hash = COALESCE(?2, tx_hash),
+                amount_out = COALESCE(?3, amount_out), updated_at = strftime('%s', 'now')
+             WHERE swap_id = ?4",
+            params![status, tx_hash, amount_out, swap_id],
+        )?;
+        Ok(())
+    }
+
+    /// Swaps that were submitted but have not reached a terminal status yet.
+    pub fn list_in_flight(&self) -> Result<Vec<SwapRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM swaps WHERE swap_id IS NOT NULL
+                AND status NOT IN ('completed', 'error', 'failed')",
+            SWAP_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], from_row)?;
+        rows.collect()
+    }
+
+    pub fn list_for_user(&self, user_id: &str, limit: i64, offset: i64) -> Result<Vec<SwapRecord>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM swaps WHERE user_id = ?1
+             ORDER BY created_at DESC, id DESC LIMIT ?2 OFFSET ?3",
+            SWAP_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![user_id, limit, offset], from_row)?;
+        rows.collect()
+    }
+
+    /// Whether `tx_hash` belongs to a swap submitted through the API.
+    pub fn is_swap_tx(&self, tx_hash: &str) -> Result<bool> {
+        self.conn.query_row(
+            "SELECT EXISTS (SELECT 1 FROM swaps WHERE tx_hash = ?1 COLLATE NOCASE)",
+            params![tx_hash],
+            |row| row.get(0),
+        )
+    }
+
+    pub fn count_for_user(&self, user_id: &str) -> Result<i64> {
+        self.conn.query_row(
+            "SELECT COUNT(*) FROM swaps WHERE user_id = ?1",
+            params![user_id],
+            |row| row.get(0),
+        )
+    }
+}
+
+fn from_row(row: &Row) -> Result<SwapRecord> {
+    Ok(SwapRecord {
+        id: row.get(0)?,
+        user_id: row.get(1)?,
+        from_token: row.get(2)?,
+        to_token: row.get(3)?,
+        amount_in: row.get(4)?,
+        amount_out: row.get(5)?,
+        quote_id: row.get(6)?,
+        swap_id: row.get(7)?,
+        tx_hash: row.get(8)?,
+        status: row.get(9)?,
+        created_at: row.get(10)?,
+        updated_at: row.get(11)?,
+        from_address: row.get(12)?,
+        swap_message: row.get(13)?,
+        price_impact: row.get(14)?,
+        accept_high_impact: row.get(15)?,
+    })
+}
+
+/// Refreshes in-flight swaps from Magpie until they settle, booking completed
+/// swaps into the owner's portfolio.
+pub async fn run_status_poller(magpie: MagpieClient, prices: Arc<PriceService>, events: EventBus) {
+    let mut interval = tokio::time::interval(Duration::from_secs(SWAP_POLL_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        let in_flight = match SwapHistoryDatabase::new().and_then(|db| db.list_in_flight()) {
+            Ok(swaps) => swaps,
+            Err(e) => {
+                println!("Swap poller failed to load swaps: {}", e);
+                continue;
+            }
+        };
+
+        for swap in in_flight {
+            let swap_id = match &swap.swap_id {
+                Some(id) => id,
+                None => continue,
+            };
+
+            match magpie.get_swap_details(swap_id).await {
+                Ok(details) => {
+                    if details.status == swap.status && details.tx_hash == swap.tx_hash {
+                        continue;
+                    }
+                    let result = SwapHistoryDatabase::new().and_then(|db| {
+                        db.update_status(
+                            swap_id,
+                            &details.status,
+                            details.tx_hash.as_deref(),
+                            Some(&details.to_amount),
+                        )
+                    });
+                    if let Err(e) = result {
+                        println!("Swap poller failed to update {}: {}", swap_id, e);
+                        continue;
+                    }
+                    if let Some(user_id) = &swap.user_id {
+                        events.publish(
+                            user_id,
+                            events::EVENT_SWAP,
+                            serde_json::json!({
+                                "swap_id": swap_id,
+                                "quote_id": swap.quote_id,
+                                "status": details.status,
+                                "previous_status": swap.status,
+                                "tx_hash": details.tx_hash,
+                            }),
+                        );
+                    }
+
+                    if let (STATUS_COMPLETED, Some(user_id)) =
+                        (details.status.as_str(), &swap.user_id)
+                    {
+                        let settled = SwapRecord {
+                            amount_out: details.to_amount.clone(),
+                            ..swap.clone()
+                        };
+                        if let Err(e) =
+                            portfolio::record_swap(prices.as_ref(), user_id, &settled).await
+                        {
+                            println!("Failed to book swap {} into portfolio: {}", swap_id, e);
+                        }
+                    }
+                    if matches!(
+                        details.status.as_str(),
+                        STATUS_COMPLETED | STATUS_ERROR | STATUS_FAILED
+                    ) {
+                        if let Err(e) = portfolio::release(&swap.quote_id) {
+                            println!("Failed to release hold for swap {}: {}", swap_id, e);
+                        }
+                    }
+                }
+                Err(e) => println!("Swap poller failed to fetch {}: {}", swap_id, e),
+            }
+        }
+    }
+}
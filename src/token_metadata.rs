@@ -0,0 +1,206 @@
+#![allow(dead_code)]
+
+use crate::admin;
+use crate::chain::{self, Erc20};
+use crate::constants::{DB_PATH, TOKEN_METADATA_REFRESH_INTERVAL_SECS};
+use crate::models::{Project, TokenMetadata};
+use crate::projects::ProjectDatabase;
+use crate::utils;
+use axum::{
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use ethers::types::Address;
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct EnrichmentReport {
+    pub checked: usize,
+    pub verified: usize,
+    pub mismatched: usize,
+    pub failed: Vec<String>,
+}
+
+pub struct TokenMetadataDatabase {
+    pub conn: Connection,
+}
+
+impl TokenMetadataDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS project_token_metadata (
+                project_id INTEGER PRIMARY KEY REFERENCES projects (id),
+                address TEXT NOT NULL,
+                name TEXT NOT NULL,
+                symbol TEXT NOT NULL,
+                decimals INTEGER NOT NULL,
+                total_supply TEXT NOT NULL,
+                mismatches TEXT NOT NULL,
+                checked_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+
+        Ok(TokenMetadataDatabase { conn })
+    }
+
+    pub fn upsert(&self, project_id: i64, address: &str, metadata: &TokenMetadata) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO project_token_metadata (project_id, address, name, symbol, decimals, total_supply, mismatches)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+             ON CONFLICT (project_id) DO UPDATE SET
+                address = excluded.address, name = excluded.name, symbol = excluded.symbol,
+                decimals = excluded.decimals, total_supply = excluded.total_supply,
+                mismatches = excluded.mismatches, checked_at = strftime('%s', 'now')",
+            params![
+                project_id,
+                address,
+                metadata.name,
+                metadata.symbol,
+                metadata.decimals,
+                metadata.total_supply,
+                metadata.mismatches.join(",")
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Metadata for a project, ignoring rows read for an address the project
+    /// no longer points at.
+    pub fn get(&self, project_id: i64, address: &str) -> Result<Option<TokenMetadata>> {
+        self.conn
+            .query_row(
+                "SELECT name, symbol, decimals, total_supply, mismatches, checked_at
+                 FROM project_token_metadata WHERE project_id = ?1 AND address = ?2",
+                params![project_id, address],
+                |row| {
+                    let mismatches: String = row.get(4)?;
+                    let mismatches: Vec<String> = mismatches
+                        .split(',')
+                        .filter(|m| !m.is_empty())
+                        .map(|m| m.to_string())
+                        .collect();
+                    Ok(TokenMetadata {
+                        name: row.get(0)?,
+                        symbol: row.get(1)?,
+                        decimals: row.get(2)?,
+                        total_supply: row.get(3)?,
+                        verified: mismatches.is_empty(),
+                        mismatches,
+                        checked_at: row.get(5)?,
+                    })
+                },
+            )
+            .optional()
+    }
+}
+
+/// Attaches stored on-chain metadata to catalog entries that have a token.
+pub fn attach(projects: &mut [Project]) -> Result<()> {
+    let db = TokenMetadataDatabase::new()?;
+    for project in projects.iter_mut() {
+        if let (Some(id), false) = (project.id, project.address.is_empty()) {
+            project.token_metadata = db.get(id, &project.address)?;
+        }
+    }
+    Ok(())
+}
+
+/// Reads token metadata from the chain and compares it with the catalog entry.
+pub async fn fetch_metadata(project: &Project) -> std::result::Result<TokenMetadata, String> {
+    let provider = chain::provider_for(&project.chain)?;
+    let address = project
+        .address
+        .parse::<Address>()
+        .map_err(|_| format!("Invalid address: {}", project.address))?;
+    let token = Erc20::new(address, provider);
+
+    let name = token.name().call().await.map_err(|e| e.to_string())?;
+    let symbol = token.symbol().call().await.map_err(|e| e.to_string())?;
+    let decimals = token.decimals().call().await.map_err(|e| e.to_string())? as u64;
+    let total_supply = token
+        .total_supply()
+        .call()
+        .await
+        .map_err(|e| e.to_string())?;
+
+    let mut mismatches = Vec::new();
+    if !project.symbol.is_empty() && !project.symbol.eq_ignore_ascii_case(&symbol) {
+        mismatches.push("symbol".to_string());
+    }
+    if project.decimals != decimals {
+        mismatches.push("decimals".to_string());
+    }
+
+    Ok(TokenMetadata {
+        name,
+        symbol,
+        decimals,
+        total_supply: total_supply.to_string(),
+        verified: mismatches.is_empty(),
+        mismatches,
+        checked_at: utils::now(),
+    })
+}
+
+pub async fn enrich_catalog() -> std::result::Result<EnrichmentReport, String> {
+    let projects = ProjectDatabase::new()
+        .and_then(|db| db.list())
+        .map_err(|e| e.to_string())?;
+    let db = TokenMetadataDatabase::new().map_err(|e| e.to_string())?;
+    let mut report = EnrichmentReport::default();
+
+    for project in projects.iter().filter(|p| !p.address.is_empty()) {
+        let project_id = project.id.unwrap_or_default();
+        report.checked += 1;
+
+        match fetch_metadata(project).await {
+            Ok(metadata) => {
+                if metadata.verified {
+                    report.verified += 1;
+                } else {
+                    report.mismatched += 1;
+                    println!(
+                        "Project {} disagrees with chain on: {}",
+                        project.name,
+                        metadata.mismatches.join(", ")
+                    );
+                }
+                db.upsert(project_id, &project.address, &metadata)
+                    .map_err(|e| e.to_string())?;
+            }
+            Err(e) => report.failed.push(format!("{}: {}", project.name, e)),
+        }
+    }
+
+    Ok(report)
+}
+
+pub async fn run_enrichment_job() {
+    let mut interval =
+        tokio::time::interval(Duration::from_secs(TOKEN_METADATA_REFRESH_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = enrich_catalog().await {
+            println!("Token metadata enrichment failed: {}", e);
+        }
+    }
+}
+
+pub async fn trigger_enrichment(
+    headers: HeaderMap,
+) -> std::result::Result<Json<EnrichmentReport>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let report = enrich_catalog()
+        .await
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    Ok(Json(report))
+}
@@ -0,0 +1,322 @@
+#![allow(dead_code)]
+
+use crate::admin;
+use crate::chain;
+use crate::constants::{DB_PATH, TVL_HISTORY_DAYS, TVL_SNAPSHOT_INTERVAL_SECS};
+use crate::models::TvlPoint;
+use crate::prices::{PriceService, PriceSource};
+use crate::projects::{ProjectDatabase, ProjectSummaryDatabase};
+use crate::utils;
+use axum::{
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use ethers::types::Address;
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct TvlContract {
+    pub id: Option<i64>,
+    #[serde(default)]
+    pub project_id: i64,
+    pub chain: String,
+    /// Pool or vault holding the tokens.
+    pub address: String,
+    /// Token addresses whose balances in `address` count towards TVL.
+    pub tokens: Vec<String>,
+    #[serde(default)]
+    pub label: String,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct TvlSnapshot {
+    pub project_id: i64,
+    pub chain: String,
+    pub tvl: f64,
+    /// USD value per token address.
+    pub breakdown: BTreeMap<String, f64>,
+    pub created_at: i64,
+}
+
+pub struct TvlDatabase {
+    pub conn: Connection,
+}
+
+impl TvlDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS tvl_contracts (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                project_id INTEGER NOT NULL REFERENCES projects (id),
+                chain TEXT NOT NULL,
+                address TEXT NOT NULL,
+                tokens TEXT NOT NULL,
+                label TEXT NOT NULL,
+                UNIQUE (project_id, chain, address)
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS tvl_snapshots (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                project_id INTEGER NOT NULL REFERENCES projects (id),
+                chain TEXT NOT NULL,
+                tvl REAL NOT NULL,
+                breakdown TEXT NOT NULL,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS tvl_snapshots_project ON tvl_snapshots (project_id, chain, created_at)",
+            [],
+        )?;
+
+        Ok(TvlDatabase { conn })
+    }
+
+    pub fn add_contract(&self, contract: &TvlContract) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO tvl_contracts (project_id, chain, address, tokens, label) VALUES (?1, ?2, ?3, ?4, ?5)
+             ON CONFLICT (project_id, chain, address) DO UPDATE SET tokens = excluded.tokens, label = excluded.label",
+            params![
+                contract.project_id,
+                contract.chain,
+                contract.address,
+                contract.tokens.join(","),
+                contract.label
+            ],
+        )?;
+
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn delete_contract(&self, project_id: i64, contract_id: i64) -> Result<bool> {
+        let deleted = self.conn.execute(
+            "DELETE FROM tvl_contracts WHERE id = ?1 AND project_id = ?2",
+            params![contract_id, project_id],
+        )?;
+        Ok(deleted == 1)
+    }
+
+    pub fn contracts(&self, project_id: Option<i64>) -> Result<Vec<TvlContract>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, project_id, chain, address, tokens, label FROM tvl_contracts
+             WHERE ?1 IS NULL OR project_id = ?1 ORDER BY project_id, chain, id",
+        )?;
+        let rows = stmt.query_map(params![project_id], |row| {
+            let tokens: String = row.get(4)?;
+            Ok(TvlContract {
+                id: row.get(0)?,
+                project_id: row.get(1)?,
+                chain: row.get(2)?,
+                address: row.get(3)?,
+                tokens: tokens
+                    .split(',')
+                    .filter(|t| !t.is_empty())
+                    .map(|t| t.to_string())
+                    .collect(),
+                label: row.get(5)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    pub fn record_snapshot(&self, snapshot: &TvlSnapshot) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO tvl_snapshots (project_id, chain, tvl, breakdown) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                snapshot.project_id,
+                snapshot.chain,
+                snapshot.tvl,
+                serde_json::to_string(&snapshot.breakdown).unwrap_or_default()
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Snapshots since `since`, oldest first.
+    pub fn history(&self, project_id: i64, chain: &str, since: i64) -> Result<Vec<TvlPoint>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT tvl, created_at FROM tvl_snapshots
+             WHERE project_id = ?1 AND chain = ?2 AND created_at >= ?3 ORDER BY created_at",
+        )?;
+        let rows = stmt.query_map(params![project_id, chain, since], |row| {
+            Ok(TvlPoint {
+                tvl: row.get(0)?,
+                timestamp: row.get(1)?,
+            })
+        })?;
+        rows.collect()
+    }
+}
+
+/// Values every token balance held by a project's contracts on one chain.
+/// Fails when a token has no price: a total without it would understate TVL.
+pub async fn compute_tvl(
+    prices: &dyn PriceSource,
+    chain: &str,
+    contracts: &[TvlContract],
+) -> std::result::Result<BTreeMap<String, f64>, String> {
+    let provider = chain::provider_for(chain)?;
+    let mut breakdown: BTreeMap<String, f64> = BTreeMap::new();
+
+    for contract in contracts {
+        for token in &contract.tokens {
+            let price = prices
+                .usd_price(chain, token)
+                .await
+                .ok_or_else(|| format!("No price for {} on {}", token, chain))?;
+
+            let balance = chain::token_balance(provider.clone(), token, &contract.address).await?;
+            let decimals = chain::token_decimals(provider.clone(), token).await?;
+            let amount = chain::to_units(balance, decimals);
+
+            *breakdown.entry(token.to_lowercase()).or_default() += amount * price;
+        }
+    }
+
+    Ok(breakdown)
+}
+
+pub async fn snapshot_all(prices: &dyn PriceSource) -> std::result::Result<usize, String> {
+    let db = TvlDatabase::new().map_err(|e| e.to_string())?;
+    let contracts = db.contracts(None).map_err(|e| e.to_string())?;
+    let summaries = ProjectSummaryDatabase::new().map_err(|e| e.to_string())?;
+
+    let mut groups: BTreeMap<(i64, String), Vec<TvlContract>> = BTreeMap::new();
+    for contract in contracts {
+        groups
+            .entry((contract.project_id, contract.chain.clone()))
+            .or_default()
+            .push(contract);
+    }
+
+    let mut recorded = 0;
+    for ((project_id, chain), contracts) in groups {
+        match compute_tvl(prices, &chain, &contracts).await {
+            Ok(breakdown) if breakdown.is_empty() => {
+                println!("No TVL tokens for project {} on {}", project_id, chain);
+            }
+            Ok(breakdown) => {
+                let snapshot = TvlSnapshot {
+                    project_id,
+                    chain,
+                    tvl: breakdown.values().sum(),
+                    breakdown,
+                    created_at: utils::now(),
+                };
+                db.record_snapshot(&snapshot).map_err(|e| e.to_string())?;
+                // Sorting the catalog by TVL reads the summary row.
+                summaries
+                    .set_tvl(project_id, &snapshot.chain, snapshot.tvl)
+                    .map_err(|e| e.to_string())?;
+                recorded += 1;
+            }
+            Err(e) => println!("TVL snapshot failed for project {}: {}", project_id, e),
+        }
+    }
+
+    Ok(recorded)
+}
+
+pub async fn run_snapshot_job(prices: Arc<PriceService>) {
+    let mut interval = tokio::time::interval(Duration::from_secs(TVL_SNAPSHOT_INTERVAL_SECS));
+
+    loop {
+        interval.tick().await;
+
+        if let Err(e) = snapshot_all(prices.as_ref()).await {
+            println!("TVL snapshot job failed: {}", e);
+        }
+    }
+}
+
+/// TVL series for the summary endpoint covering the last `TVL_HISTORY_DAYS`.
+pub fn recent_history(project_id: i64, chain: &str) -> Result<Vec<TvlPoint>> {
+    let since = utils::now() - TVL_HISTORY_DAYS * 24 * 60 * 60;
+    TvlDatabase::new()?.history(project_id, chain, since)
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("TVL database error: {}", e),
+    )
+}
+
+pub async fn list_tvl_contracts(
+    headers: HeaderMap,
+    Path(pid): Path<i64>,
+) -> std::result::Result<Json<Vec<TvlContract>>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let contracts = TvlDatabase::new()
+        .and_then(|db| db.contracts(Some(pid)))
+        .map_err(db_error)?;
+    Ok(Json(contracts))
+}
+
+pub async fn add_tvl_contract(
+    headers: HeaderMap,
+    Path(pid): Path<i64>,
+    Json(mut contract): Json<TvlContract>,
+) -> std::result::Result<Json<TvlContract>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    ProjectDatabase::new()
+        .and_then(|db| db.get(pid))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Project not found".to_string()))?;
+
+    contract.project_id = pid;
+    contract.chain = contract.chain.trim().to_lowercase();
+    for address in std::iter::once(&contract.address).chain(contract.tokens.iter()) {
+        if !chain::is_native_token(address) && address.parse::<Address>().is_err() {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid address: {}", address),
+            ));
+        }
+    }
+    if contract.tokens.is_empty() {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "At least one token is required".to_string(),
+        ));
+    }
+
+    let db = TvlDatabase::new().map_err(db_error)?;
+    db.add_contract(&contract).map_err(db_error)?;
+    contract.id = db
+        .contracts(Some(pid))
+        .map_err(db_error)?
+        .into_iter()
+        .find(|c| c.chain == contract.chain && c.address == contract.address)
+        .and_then(|c| c.id);
+
+    Ok(Json(contract))
+}
+
+pub async fn delete_tvl_contract(
+    headers: HeaderMap,
+    Path((pid, contract_id)): Path<(i64, i64)>,
+) -> std::result::Result<StatusCode, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let deleted = TvlDatabase::new()
+        .and_then(|db| db.delete_contract(pid, contract_id))
+        .map_err(db_error)?;
+    if !deleted {
+        return Err((StatusCode::NOT_FOUND, "Contract not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
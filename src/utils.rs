@@ -0,0 +1,43 @@
+use rusqlite::{params, Connection, Result};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Current unix time in seconds, matching SQLite's `strftime('%s', 'now')`.
+pub fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64
+}
+
+/// Adds `column` to `table` unless an earlier version already created it.
+/// Returns whether the column was added.
+pub fn add_column_if_missing(
+    conn: &Connection,
+    table: &str,
+    column: &str,
+    definition: &str,
+) -> Result<bool> {
+    let exists = conn
+        .prepare("SELECT 1 FROM pragma_table_info(?1) WHERE name = ?2")?
+        .exists(params![table, column])?;
+    if !exists {
+        conn.execute(
+            &format!("ALTER TABLE {} ADD COLUMN {} {}", table, column, definition),
+            [],
+        )?;
+    }
+    Ok(!exists)
+}
+
+/// Runs the test binary in a fresh directory so `DB_PATH` is a scratch
+/// database. Shared by every test module, as the working directory is
+/// process-wide.
+#[cfg(test)]
+pub(crate) fn use_temp_db() {
+    static INIT: std::sync::Once = std::sync::Once::new();
+    INIT.call_once(|| {
+        let dir = std::env::temp_dir().join(format!("ops-tests-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::env::set_current_dir(&dir).unwrap();
+    });
+}
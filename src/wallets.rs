@@ -1,24 +1,57 @@
 #![allow(dead_code)]
 
-use crate::constants::DB_PATH;
+use crate::constants::{DB_PATH, DEFAULT_WALLET_LABEL, WALLET_CREATE_ATTEMPTS};
+use crate::events::{self, EventBus};
+use crate::external_wallets::ExternalWalletDatabase;
 use crate::profiles::ProfileDatabase;
+use crate::utils;
+use axum::{
+    extract::{Path, Query},
+    http::StatusCode,
+    Json,
+};
 use rusqlite::OptionalExtension;
 use rusqlite::{params, Connection, Result};
 use serde::{Deserialize, Serialize};
 
 use ethers::{
+    core::k256::ecdsa::SigningKey,
     prelude::*,
     providers::{Http, Provider},
+    signers::coins_bip39::English,
     types::Address,
     utils::format_units,
 };
 use std::{convert::TryFrom, env, str::FromStr, sync::Arc};
 
+const WALLET_COLUMNS: &str =
+    "id, address, private, derivation_index, user_id, label, is_default, archived_at";
+
 #[derive(Debug, Serialize, Deserialize, Default)]
 pub struct Wallet {
     pub id: Option<i64>,
     pub address: String,
+    #[serde(skip_serializing)]
     pub private: String,
+    /// BIP-44 address index under the master seed; `None` for wallets with
+    /// an independent random key.
+    #[serde(default)]
+    pub derivation_index: Option<i64>,
+    #[serde(default)]
+    pub user_id: Option<String>,
+    #[serde(default)]
+    pub label: Option<String>,
+    /// The wallet used when a request names none; mirrored in `Profile.wallet`.
+    #[serde(default)]
+    pub is_default: bool,
+    #[serde(default)]
+    pub archived_at: Option<i64>,
+}
+
+/// Selects one of a user's wallets by address or label.
+#[derive(Debug, Deserialize, Default)]
+pub struct WalletQuery {
+    pub wallet: Option<String>,
 }
 
 pub struct WalletDatabase {
@@ -37,38 +70,185 @@ impl WalletDatabase {
             )",
             [],
         )?;
+        utils::add_column_if_missing(&conn, "wallets", "derivation_index", "INTEGER")?;
+        utils::add_column_if_missing(&conn, "wallets", "label", "TEXT")?;
+        utils::add_column_if_missing(&conn, "wallets", "is_default", "INTEGER NOT NULL DEFAULT 0")?;
+        utils::add_column_if_missing(&conn, "wallets", "archived_at", "INTEGER")?;
+        if utils::add_column_if_missing(&conn, "wallets", "user_id", "TEXT")? {
+            // Wallets created before profiles could hold several become the
+            // default wallet of the profile pointing at them.
+            let has_profiles = conn
+                .prepare("SELECT 1 FROM sqlite_master WHERE type = 'table' AND name = 'profiles'")?
+                .exists([])?;
+            if has_profiles {
+                conn.execute(
+                    "UPDATE wallets SET
+                        user_id = (SELECT p.user_id FROM profiles p
+                            WHERE p.wallet = wallets.address COLLATE NOCASE),
+                        label = ?1, is_default = 1
+                     WHERE EXISTS (SELECT 1 FROM profiles p
+                        WHERE p.wallet = wallets.address COLLATE NOCASE)",
+                    params![DEFAULT_WALLET_LABEL],
+                )?;
+            }
+        }
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS wallets_derivation_index ON wallets (derivation_index)",
+            [],
+        )?;
+        conn.execute(
+            "CREATE UNIQUE INDEX IF NOT EXISTS wallets_user_label ON wallets (user_id, label)
+             WHERE archived_at IS NULL",
+            [],
+        )?;
 
         Ok(WalletDatabase { conn })
     }
 
     pub fn create(&self, wallet: &Wallet) -> Result<i64> {
         self.conn.execute(
-            "INSERT INTO wallets (address, private) VALUES (?1, ?2)",
-            params![wallet.address, wallet.private],
+            "INSERT INTO wallets (address, private, derivation_index, user_id, label, is_default)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            params![
+                wallet.address,
+                wallet.private,
+                wallet.derivation_index,
+                wallet.user_id,
+                wallet.label,
+                wallet.is_default
+            ],
         )?;
 
         Ok(self.conn.last_insert_rowid())
     }
 
+    pub fn list_for_user(&self, user_id: &str, include_archived: bool) -> Result<Vec<Wallet>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM wallets WHERE user_id = ?1 AND (?2 OR archived_at IS NULL)
+             ORDER BY is_default DESC, id",
+            WALLET_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![user_id, include_archived], wallet_from_row)?;
+        rows.collect()
+    }
+
+    /// An active wallet of `user_id` whose address or label is `selector`.
+    pub fn find_for_user(&self, user_id: &str, selector: &str) -> Result<Option<Wallet>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM wallets WHERE user_id = ?1 AND archived_at IS NULL
+                        AND (address = ?2 COLLATE NOCASE OR label = ?2)",
+                    WALLET_COLUMNS
+                ),
+                params![user_id, selector],
+                wallet_from_row,
+            )
+            .optional()
+    }
+
+    pub fn default_for_user(&self, user_id: &str) -> Result<Option<Wallet>> {
+        self.conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM wallets WHERE user_id = ?1 AND is_default = 1",
+                    WALLET_COLUMNS
+                ),
+                params![user_id],
+                wallet_from_row,
+            )
+            .optional()
+    }
+
+    /// User owning `address`, if it is a managed wallet assigned to one.
+    pub fn owner(&self, address: &str) -> Result<Option<String>> {
+        self.conn
+            .query_row(
+                "SELECT user_id FROM wallets WHERE address = ?1 COLLATE NOCASE",
+                params![address],
+                |row| row.get(0),
+            )
+            .optional()
+            .map(Option::flatten)
+    }
+
+    pub fn rename(&self, id: i64, label: &str) -> Result<()> {
+        self.conn.execute(
+            "UPDATE wallets SET label = ?1 WHERE id = ?2",
+            params![label, id],
+        )?;
+        Ok(())
+    }
+
+    /// Makes `address` the user's default wallet and points the profile at it.
+    pub fn set_default(&self, user_id: &str, address: &str) -> Result<()> {
+        let tx = self.conn.unchecked_transaction()?;
+        tx.execute(
+            "UPDATE wallets SET is_default = (address = ?2) WHERE user_id = ?1",
+            params![user_id, address],
+        )?;
+        tx.execute(
+            "UPDATE profiles SET wallet = ?2 WHERE user_id = ?1",
+            params![user_id, address],
+        )?;
+        tx.commit()
+    }
+
+    pub fn archive(&self, id: i64) -> Result<()> {
+        self.conn.execute(
+            "UPDATE wallets SET archived_at = ?1, is_default = 0 WHERE id = ?2",
+            params![utils::now(), id],
+        )?;
+        Ok(())
+    }
+
+    pub fn next_derivation_index(&self) -> Result<i64> {
+        self.conn.query_row(
+            "SELECT COALESCE(MAX(derivation_index) + 1, 0) FROM wallets",
+            [],
+            |row| row.get(0),
+        )
+    }
+
+    /// Wallets derived from the master seed, by index.
+    pub fn derived(&self) -> Result<Vec<Wallet>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM wallets WHERE derivation_index IS NOT NULL ORDER BY derivation_index",
+            WALLET_COLUMNS
+        ))?;
+        let rows = stmt.query_map([], wallet_from_row)?;
+        rows.collect()
+    }
+
+    pub fn count_random(&self) -> Result<i64> {
+        self.conn.query_row(
+            "SELECT COUNT(*) FROM wallets WHERE derivation_index IS NULL",
+            [],
+            |row| row.get(0),
+        )
+    }
+
     pub fn get(&self, address: &str) -> Result<Option<Wallet>> {
         let wallet = self
             .conn
             .query_row(
-                "SELECT id, address, private FROM wallets WHERE address = ?1",
+                &format!("SELECT {} FROM wallets WHERE address = ?1", WALLET_COLUMNS),
                 params![address],
-                |row| {
-                    Ok(Wallet {
-                        id: row.get(0)?,
-                        address: row.get(1)?,
-                        private: row.get(2)?,
-                    })
-                },
+                wallet_from_row,
             )
             .optional()?;
 
         Ok(wallet)
     }
 
+    pub fn addresses(&self) -> Result<Vec<String>> {
+        let mut stmt = self
+            .conn
+            .prepare("SELECT address FROM wallets ORDER BY id")?;
+        let rows = stmt.query_map([], |row| row.get(0))?;
+        rows.collect()
+    }
+
     pub fn delete(&self, address: &str) -> Result<()> {
         self.conn
             .execute("DELETE FROM wallets WHERE address = ?1", params![address])?;
@@ -76,77 +256,460 @@ impl WalletDatabase {
     }
 }
 
-pub async fn get_balance(user_id: &str) -> Result<String> {
-    let db = ProfileDatabase::new().unwrap();
-    let profile = db.get(&user_id).unwrap();
-
-    if let Some(p) = profile {
-        let provider = Provider::<Http>::try_from(env::var("RPC_URL").unwrap()).unwrap();
-
-        let provider = Arc::new(provider);
-        let address = Address::from_str(&p.wallet).unwrap();
-        let balance = provider.get_balance(address, None).await.unwrap();
-        let formatted = format_units(balance, 18).unwrap();
-        return Ok(formatted.to_string());
-    }
-
-    return Ok("0".to_string());
-}
-
-pub async fn transfer(user_id: &str, recipient: &str, amount: &str) -> Result<String> {
-    let db = ProfileDatabase::new().unwrap();
-    let profile = db.get(&user_id).unwrap();
-
-    if let Some(p) = profile {
-        let db = WalletDatabase::new().unwrap();
-        let wallet = db.get(&p.wallet).unwrap();
-        if let Some(w) = wallet {
-            let provider = Provider::<Http>::try_from(env::var("RPC_URL").unwrap()).unwrap();
-            let provider = Arc::new(provider);
-            let chain_id = env::var("CHAIN_ID")
-                .unwrap()
-                .parse::<u64>()
-                .expect("Invalid chain ID");
-
-            let wallet = w
-                .private
-                .parse::<LocalWallet>()
-                .unwrap()
-                .with_chain_id(chain_id);
-            println!("addr {}", wallet.address());
-            let client = Arc::new(SignerMiddleware::new(provider.clone(), wallet.clone()));
-
-            let to_address = recipient.parse::<Address>().unwrap();
-            let value = ethers::utils::parse_ether(amount).unwrap();
-
-            let tx = TransactionRequest::new()
-                .from(wallet.address())
-                .to(to_address)
-                .value(value)
-                .gas_price(provider.get_gas_price().await.unwrap())
-                .gas(21000); // Standard gas limit for ETH transfers
-
-            match client.send_transaction(tx, None).await {
-                Ok(pending_tx) => {
-                    let tx_hash = pending_tx.tx_hash();
-                    println!("Transaction sent! Tx Hash: {:?}", tx_hash);
-
-                    let receipt = pending_tx.await.unwrap();
-                    println!(
-                        "Transaction confirmed in block: {:?}",
-                        receipt.unwrap().block_number
-                    );
-
-                    return Ok(format!(
-                        "{}/tx/{:?}",
-                        env::var("CHAIN_EXPLORER_URL").unwrap(),
-                        tx_hash
-                    ));
-                }
-                Err(_) => {}
-            };
+fn wallet_from_row(row: &rusqlite::Row) -> Result<Wallet> {
+    Ok(Wallet {
+        id: row.get(0)?,
+        address: row.get(1)?,
+        private: row.get(2)?,
+        derivation_index: row.get(3)?,
+        user_id: row.get(4)?,
+        label: row.get(5)?,
+        is_default: row.get(6)?,
+        archived_at: row.get(7)?,
+    })
+}
+
+/// BIP-39 master seed that user wallets are derived from along
+/// `m/44'/60'/0'/0/<index>`.
+pub struct MasterSeed {
+    phrase: String,
+    passphrase: Option<String>,
+}
+
+impl MasterSeed {
+    /// Enabled when `WALLET_MNEMONIC` is set; `WALLET_MNEMONIC_PASSPHRASE` is
+    /// the optional BIP-39 passphrase.
+    pub fn from_env() -> Option<Self> {
+        let phrase = env::var("WALLET_MNEMONIC").ok()?;
+        if phrase.trim().is_empty() {
+            return None;
+        }
+        Some(Self {
+            phrase: phrase.trim().to_string(),
+            passphrase: env::var("WALLET_MNEMONIC_PASSPHRASE")
+                .ok()
+                .filter(|p| !p.is_empty()),
+        })
+    }
+
+    pub fn derive(&self, index: u32) -> std::result::Result<LocalWallet, String> {
+        let mut builder = MnemonicBuilder::<English>::default()
+            .phrase(self.phrase.as_str())
+            .index(index)
+            .map_err(|e| format!("Invalid derivation index {}: {}", index, e))?;
+        if let Some(passphrase) = &self.passphrase {
+            builder = builder.password(passphrase);
+        }
+        builder
+            .build()
+            .map_err(|e| format!("Failed to derive wallet {}: {}", index, e))
+    }
+}
+
+pub fn wallet_record(wallet: &LocalWallet, derivation_index: Option<i64>) -> Wallet {
+    Wallet {
+        id: None,
+        address: format!("{:#x}", wallet.address()),
+        private: hex::encode(wallet.signer().to_bytes()),
+        derivation_index,
+        ..Default::default()
+    }
+}
+
+/// Creates and stores a new custody wallet for `user_id`: derived at the
+/// next free index when a master seed is configured, random otherwise.
+/// Derived wallets keep their key in `private` too, so signing does not
+/// depend on the seed.
+pub fn create_wallet(
+    user_id: &str,
+    label: &str,
+    is_default: bool,
+) -> std::result::Result<Wallet, String> {
+    let db = WalletDatabase::new().map_err(|e| e.to_string())?;
+    let owned = |wallet: Wallet| Wallet {
+        user_id: Some(user_id.to_string()),
+        label: Some(label.to_string()),
+        is_default,
+        ..wallet
+    };
+
+    let Some(seed) = MasterSeed::from_env() else {
+        let signing_key = SigningKey::random(&mut rand::thread_rng());
+        let mut wallet = owned(wallet_record(&LocalWallet::from(signing_key), None));
+        wallet.id = Some(db.create(&wallet).map_err(|e| e.to_string())?);
+        return Ok(wallet);
+    };
+
+    // Another request can claim the same index between reading and inserting;
+    // the unique index rejects the second insert and we try the next one.
+    let mut last_error = String::new();
+    for _ in 0..WALLET_CREATE_ATTEMPTS {
+        let index = db.next_derivation_index().map_err(|e| e.to_string())?;
+        let derived = seed.derive(index as u32)?;
+        let mut wallet = owned(wallet_record(&derived, Some(index)));
+        match db.create(&wallet) {
+            Ok(id) => {
+                wallet.id = Some(id);
+                return Ok(wallet);
+            }
+            Err(e) => last_error = e.to_string(),
+        }
+    }
+
+    Err(format!(
+        "Failed to allocate a derivation index: {}",
+        last_error
+    ))
+}
+
+/// Re-derives every seed-derived wallet and checks its stored address and
+/// key. Returns the number of mismatches.
+pub fn verify_derived_wallets(seed: &MasterSeed) -> std::result::Result<usize, String> {
+    let db = WalletDatabase::new().map_err(|e| e.to_string())?;
+    let wallets = db.derived().map_err(|e| e.to_string())?;
+
+    let mut mismatches = 0;
+    for wallet in &wallets {
+        let index = wallet.derivation_index.unwrap_or_default();
+        let expected = wallet_record(&seed.derive(index as u32)?, Some(index));
+        let address_ok = expected.address.eq_ignore_ascii_case(&wallet.address);
+        let key_ok = expected
+            .private
+            .eq_ignore_ascii_case(wallet.private.trim_start_matches("0x"));
+
+        if address_ok && key_ok {
+            println!("ok        {:>6}  {}", index, wallet.address);
+        } else {
+            mismatches += 1;
+            println!(
+                "MISMATCH  {:>6}  stored {} derived {}{}",
+                index,
+                wallet.address,
+                expected.address,
+                if address_ok { " (key differs)" } else { "" }
+            );
+        }
+    }
+
+    let random = db.count_random().map_err(|e| e.to_string())?;
+    println!(
+        "{} derived wallets checked, {} mismatched; {} random wallets are not covered by the seed",
+        wallets.len(),
+        mismatches,
+        random
+    );
+    Ok(mismatches)
+}
+
+/// Entry point of the `recover-wallets` command.
+pub fn run_recovery() -> i32 {
+    let Some(seed) = MasterSeed::from_env() else {
+        println!("WALLET_MNEMONIC is not set");
+        return 2;
+    };
+    match verify_derived_wallets(&seed) {
+        Ok(0) => 0,
+        Ok(_) => 1,
+        Err(e) => {
+            println!("Wallet recovery failed: {}", e);
+            2
+        }
+    }
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Wallet database error: {}", e),
+    )
+}
+
+/// The user's wallet named by `selector` (address or label), or their
+/// default wallet when no selector is given.
+pub fn resolve_wallet(
+    user_id: &str,
+    selector: Option<&str>,
+) -> std::result::Result<Wallet, (StatusCode, String)> {
+    let profile = ProfileDatabase::new()
+        .and_then(|db| db.get(user_id))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+    let db = WalletDatabase::new().map_err(db_error)?;
+
+    let wallet = match selector.map(str::trim).filter(|s| !s.is_empty()) {
+        Some(selector) => {
+            let wallet = db.find_for_user(user_id, selector).map_err(db_error)?;
+            if wallet.is_none()
+                && ExternalWalletDatabase::new()
+                    .and_then(|db| db.find_for_user(user_id, selector))
+                    .map_err(db_error)?
+                    .is_some()
+            {
+                return Err((
+                    StatusCode::FORBIDDEN,
+                    format!(
+                        "Wallet {} is watch-only and cannot sign transactions",
+                        selector
+                    ),
+                ));
+            }
+            wallet
+        }
+        None => match db.default_for_user(user_id).map_err(db_error)? {
+            Some(wallet) => Some(wallet),
+            None => db.get(&profile.wallet).map_err(db_error)?,
+        },
+    };
+    wallet.ok_or((StatusCode::NOT_FOUND, "Wallet not found".to_string()))
+}
+
+/// Like `resolve_wallet`, but for reads: linked watch-only wallets are
+/// accepted too. Returns the address.
+pub fn resolve_address(
+    user_id: &str,
+    selector: Option<&str>,
+) -> std::result::Result<String, (StatusCode, String)> {
+    match resolve_wallet(user_id, selector) {
+        Ok(wallet) => Ok(wallet.address),
+        Err((StatusCode::FORBIDDEN, message)) => ExternalWalletDatabase::new()
+            .and_then(|db| db.find_for_user(user_id, selector.unwrap_or_default().trim()))
+            .map_err(db_error)?
+            .map(|wallet| wallet.address)
+            .ok_or((StatusCode::FORBIDDEN, message)),
+        Err(e) => Err(e),
+    }
+}
+
+/// Addresses of every custody wallet of the user, default first. Archived
+/// wallets are included: they keep their keys and can still hold funds.
+pub fn custody_addresses(user_id: &str) -> Result<Vec<String>> {
+    let wallets = WalletDatabase::new()?.list_for_user(user_id, true)?;
+    Ok(wallets.into_iter().map(|w| w.address).collect())
+}
+
+pub async fn get_balance(address: &str) -> Result<String> {
+    let provider = Provider::<Http>::try_from(env::var("RPC_URL").unwrap()).unwrap();
+
+    let provider = Arc::new(provider);
+    let address = Address::from_str(address).unwrap();
+    let balance = provider.get_balance(address, None).await.unwrap();
+    let formatted = format_units(balance, 18).unwrap();
+    Ok(formatted.to_string())
+}
+
+/// What `transfer` returns when the transaction could not be sent.
+pub const TRANSFER_NOT_SENT: &str = "0x";
+
+pub async fn transfer(
+    events: &EventBus,
+    user_id: &str,
+    from: &Wallet,
+    recipient: &str,
+    amount: &str,
+) -> Result<String> {
+    let provider = Provider::<Http>::try_from(env::var("RPC_URL").unwrap()).unwrap();
+    let provider = Arc::new(provider);
+    let chain_id = env::var("CHAIN_ID")
+        .unwrap()
+        .parse::<u64>()
+        .expect("Invalid chain ID");
+
+    let wallet = from
+        .private
+        .parse::<LocalWallet>()
+        .unwrap()
+        .with_chain_id(chain_id);
+    println!("addr {}", wallet.address());
+    let client = Arc::new(SignerMiddleware::new(provider.clone(), wallet.clone()));
+
+    let to_address = recipient.parse::<Address>().unwrap();
+    let value = ethers::utils::parse_ether(amount).unwrap();
+
+    let tx = TransactionRequest::new()
+        .from(wallet.address())
+        .to(to_address)
+        .value(value)
+        .gas_price(provider.get_gas_price().await.unwrap())
+        .gas(21000); // Standard gas limit for ETH transfers
+
+    if let Ok(pending_tx) = client.send_transaction(tx, None).await {
+        let tx_hash = pending_tx.tx_hash();
+        println!("Transaction sent! Tx Hash: {:?}", tx_hash);
+        events.publish(
+            user_id,
+            events::EVENT_TRANSACTION,
+            serde_json::json!({
+                "tx_hash": format!("{:#x}", tx_hash),
+                "status": events::TX_SUBMITTED,
+            }),
+        );
+
+        let receipt = pending_tx.await.unwrap();
+        let block_number = receipt.as_ref().and_then(|r| r.block_number);
+        println!("Transaction confirmed in block: {:?}", block_number);
+        let status = match receipt.as_ref().and_then(|r| r.status) {
+            Some(status) if status.as_u64() == 1 => events::TX_CONFIRMED,
+            Some(_) => events::TX_FAILED,
+            None if receipt.is_some() => events::TX_CONFIRMED,
+            None => events::TX_DROPPED,
+        };
+        events.publish(
+            user_id,
+            events::EVENT_TRANSACTION,
+            serde_json::json!({
+                "tx_hash": format!("{:#x}", tx_hash),
+                "status": status,
+                "block_number": block_number.map(|b| b.as_u64()),
+            }),
+        );
+
+        return Ok(format!(
+            "{}/tx/{:?}",
+            env::var("CHAIN_EXPLORER_URL").unwrap(),
+            tx_hash
+        ));
+    };
+
+    Ok(TRANSFER_NOT_SENT.to_string())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListWalletsQuery {
+    #[serde(default)]
+    pub include_archived: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWalletRequest {
+    pub label: String,
+    #[serde(default)]
+    pub default: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWalletRequest {
+    pub label: Option<String>,
+    /// Only `true` is meaningful; pick another wallet to move the default.
+    pub default: Option<bool>,
+}
+
+pub fn validate_label(label: &str) -> std::result::Result<String, (StatusCode, String)> {
+    let label = label.trim();
+    if label.is_empty() || label.len() > 32 {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Label must be 1 to 32 characters".to_string(),
+        ));
+    }
+    if label.starts_with("0x") {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            "Label must not look like an address".to_string(),
+        ));
+    }
+    Ok(label.to_string())
+}
+
+pub fn ensure_label_free(
+    db: &WalletDatabase,
+    user_id: &str,
+    label: &str,
+) -> std::result::Result<(), (StatusCode, String)> {
+    if db
+        .find_for_user(user_id, label)
+        .map_err(db_error)?
+        .is_some()
+    {
+        return Err((
+            StatusCode::CONFLICT,
+            format!("A wallet labelled {} already exists", label),
+        ));
+    }
+    Ok(())
+}
+
+pub async fn list_wallets(
+    Path(user_id): Path<String>,
+    Query(query): Query<ListWalletsQuery>,
+) -> std::result::Result<Json<Vec<Wallet>>, (StatusCode, String)> {
+    let wallets = WalletDatabase::new()
+        .and_then(|db| db.list_for_user(&user_id, query.include_archived))
+        .map_err(db_error)?;
+    Ok(Json(wallets))
+}
+
+pub async fn create_user_wallet(
+    Path(user_id): Path<String>,
+    Json(req): Json<CreateWalletRequest>,
+) -> std::result::Result<Json<Wallet>, (StatusCode, String)> {
+    let label = validate_label(&req.label)?;
+    ProfileDatabase::new()
+        .and_then(|db| db.get(&user_id))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Profile not found".to_string()))?;
+    let db = WalletDatabase::new().map_err(db_error)?;
+    ensure_label_free(&db, &user_id, &label)?;
+
+    let mut wallet = create_wallet(&user_id, &label, false)
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e))?;
+    if req.default {
+        db.set_default(&user_id, &wallet.address)
+            .map_err(db_error)?;
+        wallet.is_default = true;
+    }
+
+    Ok(Json(wallet))
+}
+
+/// Renames a wallet and/or makes it the default.
+pub async fn update_wallet(
+    Path((user_id, selector)): Path<(String, String)>,
+    Json(req): Json<UpdateWalletRequest>,
+) -> std::result::Result<Json<Wallet>, (StatusCode, String)> {
+    let db = WalletDatabase::new().map_err(db_error)?;
+    let wallet = db
+        .find_for_user(&user_id, &selector)
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Wallet not found".to_string()))?;
+    let id = wallet.id.unwrap_or_default();
+
+    if let Some(label) = &req.label {
+        let label = validate_label(label)?;
+        if wallet.label.as_deref() != Some(label.as_str()) {
+            ensure_label_free(&db, &user_id, &label)?;
+            db.rename(id, &label).map_err(db_error)?;
         }
     }
+    if req.default == Some(true) && !wallet.is_default {
+        db.set_default(&user_id, &wallet.address)
+            .map_err(db_error)?;
+    }
+
+    let updated = db
+        .get(&wallet.address)
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Wallet not found".to_string()))?;
+    Ok(Json(updated))
+}
+
+/// Archives a wallet. It stays in custody and indexed, but can no longer be
+/// selected. The default wallet cannot be archived.
+pub async fn archive_wallet(
+    Path((user_id, selector)): Path<(String, String)>,
+) -> std::result::Result<StatusCode, (StatusCode, String)> {
+    let db = WalletDatabase::new().map_err(db_error)?;
+    let wallet = db
+        .find_for_user(&user_id, &selector)
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Wallet not found".to_string()))?;
+    if wallet.is_default {
+        return Err((
+            StatusCode::CONFLICT,
+            "The default wallet cannot be archived".to_string(),
+        ));
+    }
 
-    return Ok("0x".to_string());
+    db.archive(wallet.id.unwrap_or_default())
+        .map_err(db_error)?;
+    Ok(StatusCode::NO_CONTENT)
 }
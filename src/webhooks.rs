@@ -0,0 +1,437 @@
+#![allow(dead_code)]
+
+use crate::admin;
+use crate::constants::{
+    DB_PATH, WEBHOOK_DISPATCH_INTERVAL_SECS, WEBHOOK_MAX_ATTEMPTS, WEBHOOK_RETRY_BASE_SECS,
+    WEBHOOK_TIMEOUT_SECS,
+};
+use crate::utils;
+use axum::{
+    extract::Path,
+    http::{HeaderMap, StatusCode},
+    Json,
+};
+use hmac::{Hmac, Mac};
+use reqwest::{Client, Url};
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result, Row};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::Duration;
+
+pub const SIGNATURE_HEADER: &str = "x-webhook-signature";
+pub const TIMESTAMP_HEADER: &str = "x-webhook-timestamp";
+pub const EVENT_HEADER: &str = "x-webhook-event";
+pub const DELIVERY_HEADER: &str = "x-webhook-delivery";
+
+pub const DELIVERY_PENDING: &str = "pending";
+pub const DELIVERY_DELIVERED: &str = "delivered";
+pub const DELIVERY_FAILED: &str = "failed";
+/// Never sent because the endpoint was deactivated first.
+pub const DELIVERY_CANCELLED: &str = "cancelled";
+
+const DELIVERY_COLUMNS: &str =
+    "id, endpoint_id, event, payload, status, attempts, next_attempt_at, \
+     last_status_code, last_error, created_at, delivered_at";
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct WebhookEndpoint {
+    pub id: Option<i64>,
+    pub url: String,
+    /// Events to receive; empty means all.
+    #[serde(default)]
+    pub events: Vec<String>,
+    /// Only returned when the endpoint is created.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub active: bool,
+    #[serde(default)]
+    pub created_at: i64,
+}
+
+#[derive(Debug, Serialize, Deserialize, Default, Clone)]
+pub struct WebhookDelivery {
+    pub id: i64,
+    pub endpoint_id: i64,
+    pub event: String,
+    pub payload: serde_json::Value,
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: i64,
+    pub last_status_code: Option<i64>,
+    pub last_error: Option<String>,
+    pub created_at: i64,
+    pub delivered_at: Option<i64>,
+}
+
+pub struct WebhookDatabase {
+    pub conn: Connection,
+}
+
+impl WebhookDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS webhook_endpoints (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                url TEXT NOT NULL,
+                events TEXT NOT NULL,
+                secret TEXT NOT NULL,
+                active INTEGER NOT NULL DEFAULT 1,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS webhook_deliveries (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                endpoint_id INTEGER NOT NULL REFERENCES webhook_endpoints (id),
+                event TEXT NOT NULL,
+                payload TEXT NOT NULL,
+                status TEXT NOT NULL,
+                attempts INTEGER NOT NULL DEFAULT 0,
+                next_attempt_at INTEGER NOT NULL,
+                last_status_code INTEGER,
+                last_error TEXT,
+                created_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now')),
+                delivered_at INTEGER
+            )",
+            [],
+        )?;
+        conn.execute(
+            "CREATE INDEX IF NOT EXISTS webhook_deliveries_due ON webhook_deliveries (status, next_attempt_at)",
+            [],
+        )?;
+
+        Ok(WebhookDatabase { conn })
+    }
+
+    pub fn create_endpoint(&self, url: &str, events: &[String], secret: &str) -> Result<i64> {
+        self.conn.execute(
+            "INSERT INTO webhook_endpoints (url, events, secret) VALUES (?1, ?2, ?3)",
+            params![url, events.join(","), secret],
+        )?;
+        Ok(self.conn.last_insert_rowid())
+    }
+
+    pub fn endpoints(&self) -> Result<Vec<WebhookEndpoint>> {
+        let mut stmt = self.conn.prepare(
+            "SELECT id, url, events, active, created_at FROM webhook_endpoints ORDER BY id",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            let events: String = row.get(2)?;
+            Ok(WebhookEndpoint {
+                id: row.get(0)?,
+                url: row.get(1)?,
+                events: events
+                    .split(',')
+                    .filter(|e| !e.is_empty())
+                    .map(|e| e.to_string())
+                    .collect(),
+                secret: None,
+                active: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Deactivates an endpoint and cancels its pending deliveries; its
+    /// delivery log is kept.
+    pub fn deactivate_endpoint(&self, id: i64) -> Result<bool> {
+        let tx = self.conn.unchecked_transaction()?;
+        let updated = tx.execute(
+            "UPDATE webhook_endpoints SET active = 0 WHERE id = ?1 AND active = 1",
+            params![id],
+        )?;
+        tx.execute(
+            "UPDATE webhook_deliveries SET status = ?1 WHERE endpoint_id = ?2 AND status = ?3",
+            params![DELIVERY_CANCELLED, id, DELIVERY_PENDING],
+        )?;
+        tx.commit()?;
+        Ok(updated == 1)
+    }
+
+    /// URL and secret of an active endpoint.
+    fn endpoint_target(&self, id: i64) -> Result<Option<(String, String)>> {
+        self.conn
+            .query_row(
+                "SELECT url, secret FROM webhook_endpoints WHERE id = ?1 AND active = 1",
+                params![id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+    }
+
+    /// Queues one delivery per active endpoint subscribed to `event`.
+    pub fn enqueue(&self, event: &str, payload: &serde_json::Value) -> Result<usize> {
+        let endpoints = self.endpoints()?;
+        let payload = payload.to_string();
+        let mut queued = 0;
+
+        for endpoint in endpoints.iter().filter(|e| e.active) {
+            if !endpoint.events.is_empty() && !endpoint.events.iter().any(|e| e == event) {
+                continue;
+            }
+            self.conn.execute(
+                "INSERT INTO webhook_deliveries (endpoint_id, event, payload, status, next_attempt_at)
+                 VALUES (?1, ?2, ?3, ?4, strftime('%s', 'now'))",
+                params![endpoint.id, event, payload, DELIVERY_PENDING],
+            )?;
+            queued += 1;
+        }
+
+        Ok(queued)
+    }
+
+    pub fn due_deliveries(&self, now: i64) -> Result<Vec<WebhookDelivery>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM webhook_deliveries WHERE status = ?1 AND next_attempt_at <= ?2
+             ORDER BY next_attempt_at, id",
+            DELIVERY_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![DELIVERY_PENDING, now], delivery_from_row)?;
+        rows.collect()
+    }
+
+    pub fn deliveries_for(&self, endpoint_id: i64) -> Result<Vec<WebhookDelivery>> {
+        let mut stmt = self.conn.prepare(&format!(
+            "SELECT {} FROM webhook_deliveries WHERE endpoint_id = ?1 ORDER BY id DESC",
+            DELIVERY_COLUMNS
+        ))?;
+        let rows = stmt.query_map(params![endpoint_id], delivery_from_row)?;
+        rows.collect()
+    }
+
+    pub fn record_attempt(
+        &self,
+        id: i64,
+        status: &str,
+        next_attempt_at: i64,
+        status_code: Option<i64>,
+        error: Option<&str>,
+    ) -> Result<()> {
+        self.conn.execute(
+            "UPDATE webhook_deliveries SET status = ?1, attempts = attempts + 1,
+                next_attempt_at = ?2, last_status_code = ?3, last_error = ?4,
+                delivered_at = CASE WHEN ?1 = ?5 THEN strftime('%s', 'now') ELSE delivered_at END
+             WHERE id = ?6",
+            params![
+                status,
+                next_attempt_at,
+                status_code,
+                error,
+                DELIVERY_DELIVERED,
+                id
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+fn delivery_from_row(row: &Row) -> Result<WebhookDelivery> {
+    let payload: String = row.get(3)?;
+    Ok(WebhookDelivery {
+        id: row.get(0)?,
+        endpoint_id: row.get(1)?,
+        event: row.get(2)?,
+        payload: serde_json::from_str(&payload).unwrap_or_default(),
+        status: row.get(4)?,
+        attempts: row.get(5)?,
+        next_attempt_at: row.get(6)?,
+        last_status_code: row.get(7)?,
+        last_error: row.get(8)?,
+        created_at: row.get(9)?,
+        delivered_at: row.get(10)?,
+    })
+}
+
+/// Hex HMAC-SHA256 of `"{timestamp}.{body}"`. Receivers recompute it with
+/// their secret and should reject stale timestamps.
+pub fn sign(secret: &str, timestamp: i64, body: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(format!("{}.{}", timestamp, body).as_bytes());
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Queues `event` for every subscribed endpoint, logging instead of failing
+/// the caller when the queue is unavailable.
+pub fn notify(event: &str, data: serde_json::Value) {
+    let payload = serde_json::json!({
+        "event": event,
+        "created_at": utils::now(),
+        "data": data,
+    });
+    if let Err(e) = WebhookDatabase::new().and_then(|db| db.enqueue(event, &payload)) {
+        println!("Failed to queue webhook {}: {}", event, e);
+    }
+}
+
+fn retry_delay(attempts: i64) -> i64 {
+    WEBHOOK_RETRY_BASE_SECS * 2i64.pow(attempts.clamp(0, 16) as u32)
+}
+
+/// Posts one delivery, returning the response code and the failure reason.
+async fn send(
+    client: &Client,
+    url: &str,
+    secret: &str,
+    delivery: &WebhookDelivery,
+    timestamp: i64,
+) -> (Option<i64>, Option<String>) {
+    let body = delivery.payload.to_string();
+    let result = client
+        .post(url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header(EVENT_HEADER, &delivery.event)
+        .header(DELIVERY_HEADER, delivery.id.to_string())
+        .header(TIMESTAMP_HEADER, timestamp.to_string())
+        .header(SIGNATURE_HEADER, sign(secret, timestamp, &body))
+        .body(body)
+        .send()
+        .await;
+
+    match result {
+        Ok(response) if response.status().is_success() => {
+            (Some(response.status().as_u16() as i64), None)
+        }
+        Ok(response) => (
+            Some(response.status().as_u16() as i64),
+            Some(format!("Endpoint returned {}", response.status())),
+        ),
+        Err(e) => (None, Some(e.to_string())),
+    }
+}
+
+/// Sends due deliveries, retrying failures with exponential backoff until
+/// `WEBHOOK_MAX_ATTEMPTS`.
+pub async fn run_dispatcher() {
+    let mut interval = tokio::time::interval(Duration::from_secs(WEBHOOK_DISPATCH_INTERVAL_SECS));
+    let client = Client::builder()
+        .timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS))
+        .build()
+        .unwrap_or_default();
+
+    loop {
+        interval.tick().await;
+
+        let db = match WebhookDatabase::new() {
+            Ok(db) => db,
+            Err(e) => {
+                println!("Webhook dispatcher failed to open database: {}", e);
+                continue;
+            }
+        };
+        let due = match db.due_deliveries(utils::now()) {
+            Ok(due) => due,
+            Err(e) => {
+                println!("Webhook dispatcher failed to load deliveries: {}", e);
+                continue;
+            }
+        };
+
+        for delivery in &due {
+            let (url, secret) = match db.endpoint_target(delivery.endpoint_id) {
+                Ok(Some(target)) => target,
+                _ => continue,
+            };
+
+            let timestamp = utils::now();
+            let (status_code, error) = send(&client, &url, &secret, delivery, timestamp).await;
+
+            let attempts = delivery.attempts + 1;
+            let (status, next_attempt_at) = match &error {
+                None => (DELIVERY_DELIVERED, timestamp),
+                Some(_) if attempts >= WEBHOOK_MAX_ATTEMPTS => (DELIVERY_FAILED, timestamp),
+                Some(_) => (DELIVERY_PENDING, timestamp + retry_delay(delivery.attempts)),
+            };
+            if let Err(e) = db.record_attempt(
+                delivery.id,
+                status,
+                next_attempt_at,
+                status_code,
+                error.as_deref(),
+            ) {
+                println!("Failed to record webhook delivery {}: {}", delivery.id, e);
+            }
+        }
+    }
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Webhook database error: {}", e),
+    )
+}
+
+pub async fn create_webhook(
+    headers: HeaderMap,
+    Json(mut endpoint): Json<WebhookEndpoint>,
+) -> std::result::Result<Json<WebhookEndpoint>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    match Url::parse(&endpoint.url) {
+        Ok(url) if url.scheme() == "http" || url.scheme() == "https" => {}
+        _ => {
+            return Err((
+                StatusCode::BAD_REQUEST,
+                format!("Invalid webhook URL: {}", endpoint.url),
+            ))
+        }
+    }
+
+    let secret = format!("whsec_{}", hex::encode(rand::random::<[u8; 32]>()));
+    let db = WebhookDatabase::new().map_err(db_error)?;
+    endpoint.id = Some(
+        db.create_endpoint(&endpoint.url, &endpoint.events, &secret)
+            .map_err(db_error)?,
+    );
+    endpoint.secret = Some(secret);
+    endpoint.active = true;
+    endpoint.created_at = utils::now();
+
+    Ok(Json(endpoint))
+}
+
+pub async fn list_webhooks(
+    headers: HeaderMap,
+) -> std::result::Result<Json<Vec<WebhookEndpoint>>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let endpoints = WebhookDatabase::new()
+        .and_then(|db| db.endpoints())
+        .map_err(db_error)?;
+    Ok(Json(endpoints))
+}
+
+pub async fn delete_webhook(
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> std::result::Result<StatusCode, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let deactivated = WebhookDatabase::new()
+        .and_then(|db| db.deactivate_endpoint(id))
+        .map_err(db_error)?;
+    if !deactivated {
+        return Err((StatusCode::NOT_FOUND, "Webhook not found".to_string()));
+    }
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn list_deliveries(
+    headers: HeaderMap,
+    Path(id): Path<i64>,
+) -> std::result::Result<Json<Vec<WebhookDelivery>>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    let deliveries = WebhookDatabase::new()
+        .and_then(|db| db.deliveries_for(id))
+        .map_err(db_error)?;
+    Ok(Json(deliveries))
+}
@@ -0,0 +1,441 @@
+#![allow(dead_code)]
+
+use crate::admin;
+use crate::constants::{DB_PATH, WHITEPAPER_DIR, WHITEPAPER_SUMMARY_SENTENCES};
+use crate::projects::ProjectDatabase;
+use axum::{
+    body::Bytes,
+    extract::Path,
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use rusqlite::OptionalExtension;
+use rusqlite::{params, Connection, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+
+const STOPWORDS: &[&str] = &[
+    "a", "about", "above", "after", "again", "all", "also", "am", "an", "and", "any", "are", "as",
+    "at", "be", "because", "been", "before", "being", "between", "both", "but", "by", "can",
+    "could", "did", "do", "does", "doing", "down", "during", "each", "few", "for", "from",
+    "further", "had", "has", "have", "having", "he", "her", "here", "hers", "him", "his", "how",
+    "i", "if", "in", "into", "is", "it", "its", "itself", "just", "may", "me", "more", "most",
+    "must", "my", "no", "nor", "not", "now", "of", "off", "on", "once", "only", "or", "other",
+    "our", "ours", "out", "over", "own", "same", "she", "should", "so", "some", "such", "than",
+    "that", "the", "their", "theirs", "them", "then", "there", "these", "they", "this", "those",
+    "through", "to", "too", "under", "until", "up", "us", "very", "was", "we", "were", "what",
+    "when", "where", "which", "while", "who", "whom", "why", "will", "with", "would", "you",
+    "your", "yours",
+];
+
+/// Sentences outside this word range rarely make good summary lines.
+const MIN_SENTENCE_WORDS: usize = 6;
+const MAX_SENTENCE_WORDS: usize = 60;
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum WhitepaperFormat {
+    Pdf,
+    Markdown,
+    Text,
+}
+
+impl WhitepaperFormat {
+    /// Uses the upload's content type, sniffing for a PDF header when absent.
+    fn detect(content_type: Option<&str>, body: &[u8]) -> Option<Self> {
+        let content_type = content_type
+            .and_then(|ct| ct.split(';').next())
+            .map(|ct| ct.trim().to_lowercase());
+        match content_type.as_deref() {
+            Some("application/pdf") => Some(Self::Pdf),
+            Some("text/markdown") | Some("text/x-markdown") => Some(Self::Markdown),
+            Some("text/plain") => Some(Self::Text),
+            None | Some("application/octet-stream") if body.starts_with(b"%PDF") => Some(Self::Pdf),
+            None | Some("application/octet-stream") => Some(Self::Text),
+            _ => None,
+        }
+    }
+
+    fn extension(&self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Markdown => "md",
+            Self::Text => "txt",
+        }
+    }
+
+    fn content_type(&self) -> &'static str {
+        match self {
+            Self::Pdf => "application/pdf",
+            Self::Markdown => "text/markdown; charset=utf-8",
+            Self::Text => "text/plain; charset=utf-8",
+        }
+    }
+
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Pdf => "pdf",
+            Self::Markdown => "markdown",
+            Self::Text => "text",
+        }
+    }
+
+    fn parse(value: &str) -> Option<Self> {
+        match value {
+            "pdf" => Some(Self::Pdf),
+            "markdown" => Some(Self::Markdown),
+            "text" => Some(Self::Text),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct Whitepaper {
+    pub project_id: i64,
+    pub format: WhitepaperFormat,
+    pub path: String,
+    pub size: i64,
+    pub summary: String,
+    pub uploaded_at: i64,
+    #[serde(skip_serializing)]
+    pub text: String,
+}
+
+impl Whitepaper {
+    /// Public link to the original document.
+    pub fn url(&self) -> String {
+        format!("/whitepapers/{}", self.project_id)
+    }
+}
+
+pub struct WhitepaperDatabase {
+    pub conn: Connection,
+}
+
+impl WhitepaperDatabase {
+    pub fn new() -> Result<Self> {
+        let conn = Connection::open(DB_PATH)?;
+
+        conn.execute(
+            "CREATE TABLE IF NOT EXISTS whitepapers (
+                project_id INTEGER PRIMARY KEY REFERENCES projects (id),
+                format TEXT NOT NULL,
+                path TEXT NOT NULL,
+                size INTEGER NOT NULL,
+                text TEXT NOT NULL,
+                summary TEXT NOT NULL,
+                uploaded_at INTEGER NOT NULL DEFAULT (strftime('%s', 'now'))
+            )",
+            [],
+        )?;
+
+        Ok(WhitepaperDatabase { conn })
+    }
+
+    pub fn upsert(&self, whitepaper: &Whitepaper) -> Result<()> {
+        self.conn.execute(
+            "INSERT INTO whitepapers (project_id, format, path, size, text, summary)
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+             ON CONFLICT (project_id) DO UPDATE SET
+                format = excluded.format, path = excluded.path, size = excluded.size,
+                text = excluded.text, summary = excluded.summary,
+                uploaded_at = strftime('%s', 'now')",
+            params![
+                whitepaper.project_id,
+                whitepaper.format.as_str(),
+                whitepaper.path,
+                whitepaper.size,
+                whitepaper.text,
+                whitepaper.summary
+            ],
+        )?;
+        Ok(())
+    }
+
+    pub fn get(&self, project_id: i64) -> Result<Option<Whitepaper>> {
+        self.conn
+            .query_row(
+                "SELECT project_id, format, path, size, text, summary, uploaded_at
+                 FROM whitepapers WHERE project_id = ?1",
+                params![project_id],
+                |row| {
+                    let format: String = row.get(1)?;
+                    Ok(Whitepaper {
+                        project_id: row.get(0)?,
+                        format: WhitepaperFormat::parse(&format).unwrap_or(WhitepaperFormat::Text),
+                        path: row.get(2)?,
+                        size: row.get(3)?,
+                        text: row.get(4)?,
+                        summary: row.get(5)?,
+                        uploaded_at: row.get(6)?,
+                    })
+                },
+            )
+            .optional()
+    }
+}
+
+async fn extract_text(
+    format: WhitepaperFormat,
+    body: Bytes,
+) -> std::result::Result<String, String> {
+    match format {
+        WhitepaperFormat::Pdf => {
+            // pdf-extract panics on some malformed files; a blocking task
+            // turns that into a join error instead of taking the worker down.
+            tokio::task::spawn_blocking(move || pdf_extract::extract_text_from_mem(&body))
+                .await
+                .map_err(|_| "Could not read PDF".to_string())?
+                .map_err(|e| format!("Could not read PDF: {}", e))
+        }
+        WhitepaperFormat::Markdown => {
+            let text = String::from_utf8(body.to_vec()).map_err(|_| "Whitepaper is not UTF-8")?;
+            Ok(strip_markdown(&text))
+        }
+        WhitepaperFormat::Text => {
+            String::from_utf8(body.to_vec()).map_err(|_| "Whitepaper is not UTF-8".to_string())
+        }
+    }
+}
+
+/// Drops code blocks, tables and markup so only prose reaches the summarizer.
+/// Headings become sentences of their own so they never merge into the next
+/// paragraph.
+fn strip_markdown(markdown: &str) -> String {
+    let mut out = String::new();
+    let mut in_code = false;
+
+    for line in markdown.lines() {
+        let line = line.trim();
+        if line.starts_with("```") || line.starts_with("~~~") {
+            in_code = !in_code;
+            continue;
+        }
+        if in_code || line.starts_with('|') || line.starts_with("<") {
+            continue;
+        }
+
+        let heading = line.starts_with('#');
+        let line = line
+            .trim_start_matches('#')
+            .trim_start_matches(['>', '-', '*', '+'])
+            .trim();
+        let mut cleaned = String::new();
+        let mut chars = line.chars().peekable();
+        while let Some(c) = chars.next() {
+            match c {
+                // [text](url) keeps the text, ![alt](url) keeps nothing.
+                '!' if chars.peek() == Some(&'[') => {
+                    chars.by_ref().find(|c| *c == ']');
+                    if chars.peek() == Some(&'(') {
+                        chars.by_ref().find(|c| *c == ')');
+                    }
+                }
+                '[' => {}
+                ']' => {
+                    if chars.peek() == Some(&'(') {
+                        chars.by_ref().find(|c| *c == ')');
+                    }
+                }
+                '*' | '_' | '`' => {}
+                c => cleaned.push(c),
+            }
+        }
+
+        out.push_str(&cleaned);
+        if heading && !cleaned.is_empty() {
+            out.push('.');
+        }
+        out.push('\n');
+    }
+
+    out
+}
+
+fn split_sentences(text: &str) -> Vec<String> {
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        current.push(c);
+        if matches!(c, '.' | '!' | '?') && chars.peek().is_none_or(|n| n.is_whitespace()) {
+            let sentence = current.trim().to_string();
+            if !sentence.is_empty() {
+                sentences.push(sentence);
+            }
+            current.clear();
+        }
+    }
+    if !current.trim().is_empty() {
+        sentences.push(current.trim().to_string());
+    }
+
+    sentences
+}
+
+fn content_words(sentence: &str) -> Vec<String> {
+    sentence
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|w| w.len() > 2)
+        .map(|w| w.to_lowercase())
+        .filter(|w| !STOPWORDS.contains(&w.as_str()) && !w.chars().all(|c| c.is_numeric()))
+        .collect()
+}
+
+/// Picks the sentences whose content words are most frequent across the
+/// document and returns them in their original order.
+pub fn summarize(text: &str, max_sentences: usize) -> String {
+    let sentences = split_sentences(text);
+    let words: Vec<Vec<String>> = sentences.iter().map(|s| content_words(s)).collect();
+
+    let mut frequency: HashMap<&str, f64> = HashMap::new();
+    for word in words.iter().flatten() {
+        *frequency.entry(word.as_str()).or_default() += 1.0;
+    }
+    let max = frequency.values().cloned().fold(1.0, f64::max);
+
+    let mut scored: Vec<(usize, f64)> = sentences
+        .iter()
+        .zip(&words)
+        .enumerate()
+        .filter(|(_, (sentence, _))| {
+            let len = sentence.split_whitespace().count();
+            (MIN_SENTENCE_WORDS..=MAX_SENTENCE_WORDS).contains(&len)
+        })
+        .filter(|(_, (_, words))| !words.is_empty())
+        .map(|(i, (_, words))| {
+            let unique: HashSet<&str> = words.iter().map(|w| w.as_str()).collect();
+            let score: f64 = unique.iter().map(|w| frequency[w] / max).sum();
+            (
+                i,
+                score / unique.len() as f64 * (unique.len() as f64).ln_1p(),
+            )
+        })
+        .collect();
+
+    scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+    let mut picked: Vec<usize> = scored.iter().take(max_sentences).map(|(i, _)| *i).collect();
+    picked.sort();
+
+    picked
+        .into_iter()
+        .map(|i| sentences[i].as_str())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn db_error(e: rusqlite::Error) -> (StatusCode, String) {
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        format!("Whitepaper database error: {}", e),
+    )
+}
+
+pub async fn upload_whitepaper(
+    headers: HeaderMap,
+    Path(pid): Path<i64>,
+    body: Bytes,
+) -> std::result::Result<Json<Whitepaper>, (StatusCode, String)> {
+    admin::require_admin(&headers)?;
+
+    ProjectDatabase::new()
+        .and_then(|db| db.get(pid))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Project not found".to_string()))?;
+
+    if body.is_empty() {
+        return Err((StatusCode::BAD_REQUEST, "Whitepaper is empty".to_string()));
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok());
+    let format = WhitepaperFormat::detect(content_type, &body).ok_or((
+        StatusCode::UNSUPPORTED_MEDIA_TYPE,
+        "Whitepaper must be PDF, Markdown or plain text".to_string(),
+    ))?;
+
+    let text = extract_text(format, body.clone())
+        .await
+        .map_err(|e| (StatusCode::UNPROCESSABLE_ENTITY, e))?;
+    if text.trim().is_empty() {
+        return Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            "No text could be extracted from the whitepaper".to_string(),
+        ));
+    }
+
+    let path: PathBuf = [WHITEPAPER_DIR, &format!("{}.{}", pid, format.extension())]
+        .iter()
+        .collect();
+    let write = fs::create_dir_all(WHITEPAPER_DIR).and_then(|_| fs::write(&path, &body));
+    if let Err(e) = write {
+        return Err((
+            StatusCode::INTERNAL_SERVER_ERROR,
+            format!("Failed to store whitepaper: {}", e),
+        ));
+    }
+
+    let db = WhitepaperDatabase::new().map_err(db_error)?;
+    if let Some(previous) = db.get(pid).map_err(db_error)? {
+        if path.to_string_lossy() != previous.path {
+            let _ = fs::remove_file(&previous.path);
+        }
+    }
+
+    let mut whitepaper = Whitepaper {
+        project_id: pid,
+        format,
+        path: path.to_string_lossy().to_string(),
+        size: body.len() as i64,
+        summary: summarize(&text, WHITEPAPER_SUMMARY_SENTENCES),
+        uploaded_at: 0,
+        text,
+    };
+    db.upsert(&whitepaper).map_err(db_error)?;
+    whitepaper.uploaded_at = db
+        .get(pid)
+        .map_err(db_error)?
+        .map(|w| w.uploaded_at)
+        .unwrap_or_default();
+
+    Ok(Json(whitepaper))
+}
+
+pub async fn get_whitepaper(
+    Path(pid): Path<i64>,
+) -> std::result::Result<Response, (StatusCode, String)> {
+    let whitepaper = WhitepaperDatabase::new()
+        .and_then(|db| db.get(pid))
+        .map_err(db_error)?
+        .ok_or((StatusCode::NOT_FOUND, "Whitepaper not found".to_string()))?;
+
+    let body = fs::read(&whitepaper.path).map_err(|_| {
+        (
+            StatusCode::NOT_FOUND,
+            "Whitepaper file is missing".to_string(),
+        )
+    })?;
+    let filename = format!(
+        "inline; filename=\"whitepaper-{}.{}\"",
+        pid,
+        whitepaper.format.extension()
+    );
+
+    Ok((
+        [
+            (
+                header::CONTENT_TYPE,
+                whitepaper.format.content_type().to_string(),
+            ),
+            (header::CONTENT_DISPOSITION, filename),
+        ],
+        body,
+    )
+        .into_response())
+}